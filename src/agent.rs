@@ -1 +1,5 @@
 pub mod car;
+pub mod driver;
+pub mod pedestrian;
+pub mod spawner;
+pub mod vehicle;
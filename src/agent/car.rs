@@ -1,17 +1,108 @@
-use std::collections::HashMap;
-use crate::{gui, road::{self, RoadPoint}};
+use std::{collections::{HashMap, VecDeque}, io};
+use crate::{agent::{driver::DriverProfile, vehicle::{VehicleClass, VehicleType}}, emissions::{EmissionClass, EmissionModel, Emissions}, gui, output::trips::TripRecord, road::{self, path::{pathfinding, route_choice}, travel_times::TravelTimes}, snapshot::{Snapshot, SnapshotReader, SnapshotWriter}, utils::Rng};
 
-const SPEED: f32 = 80. / 3.6;
-const OPTIMAL_ACCELERATION: f32 = 0.14 * 9.81; // 0.14g, source : https://www.jsheld.com/insights/articles/a-naturalistic-study-of-vehicle-acceleration-and-deceleration-at-an-intersection
-const SEEING_DISTANCE: f32 = 100.;
+pub const SPEED: f32 = 80. / 3.6;
+pub const SEEING_DISTANCE: f32 = 100.;
+const MIN_GAP: f32 = 2.; // Distance kept to the leader when stopped
+const POINT_REACHED_DISTANCE: f32 = 0.5;
+const STOPPED_SPEED: f32 = 0.1; // A car is considered stopped below this speed...
+const MOVING_SPEED: f32 = 1.; // ...and moving again above this one
+const ROUTE_ALTERNATIVES: usize = 3;
+const ROUTE_CHOICE_THETA: f32 = 0.02; // 1 / meter
+const REROUTE_INTERVAL: f32 = 60.; // s
+const MIN_REROUTE_INTERVAL: f32 = 5.; // s, between two reroutes
+const REROUTE_DELAY_FACTOR: f32 = 2.; // A segment is considered delayed when it takes this many times its free-flow time
+const MIN_CAPACITY_FACTOR: f32 = 0.1; // Work zones keep at least this share of the capacity
+const PARKING_SEARCH_DISTANCE: f32 = 50.; // m, drivers look for parking from this distance to their destination
+const CRUISING_LOOKAHEAD: f32 = 20.; // m, the cruising path is extended when it ends closer than this
+const CRUISING_RADIUS: f32 = 100.; // m, beyond this distance to their destination, cruising drivers head back towards it
+
+// Where the car drives to
+#[derive(Clone, Debug, PartialEq)]
+pub enum TripPlan {
+    // Back and forth between two points, each way being a trip
+    BackAndForth(road::RoadPoint, road::RoadPoint),
+    // Around a cycle of RoadNodes, the first one not repeated at the end, forever
+    Loop(Vec<road::RoadNodeIdx>),
+    // Along the RoadNodes once, each of them at most once, the car having arrived at the last one
+    Route(Vec<road::RoadNodeIdx>),
+    // To the RoadNode, then cruising around it until a parking space is found, which is occupied for the duration. The
+    // car then drives back to where it entered the network along a Route.
+    Park { destination: road::RoadNodeIdx, duration: f32 },
+}
+
+// A point where the car stops until told to leave, like a bus stop
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stop {
+    pub position: road::RoadPoint,
+    // Out of the lane, so that the car doesn't block the traffic while stopped
+    pub in_bay: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StopState {
+    Approaching,
+    // Waiting to be given a dwell time
+    Arrived,
+    Dwelling(f32),
+    // Waiting for a gap to leave the bay
+    Leaving,
+}
 
 pub struct Car {
+    id: usize,
     position: road::RoadPoint,
     speed: f32,
+    acceleration: f32,
     target_speed: f32,
     road_information: RoadInformation,
     planned_trip: road::path::Path,
+    // Driven on before the current one, to draw the rear of the car
+    previous_segment: Option<road::RoadSegmentIdx>,
+    trip_plan: TripPlan,
+    // Where the car entered the network, which it drives back to after parking
+    origin: road::RoadPoint,
     is_back: bool,
+    // Informed drivers re-evaluate their route using the current travel times
+    informed: bool,
+    time_since_reroute: f32,
+    breakdown: Option<Breakdown>,
+    stops: VecDeque<Stop>,
+    stop_state: StopState,
+    // Time and distance driven when the search for parking started
+    parking_search: Option<(f32, f32)>,
+    trip: Option<TripRecord>,
+    finished_trips: Vec<TripRecord>,
+    distance_driven: f32,
+    stopped: bool,
+    vehicle_type: VehicleType,
+    driver: DriverProfile,
+    // What the driver saw, the oldest being the one acted upon
+    perceptions: VecDeque<Perception>,
+    // Own generator, so that the draws of a car don't depend on the other cars
+    rng: Rng,
+}
+
+// Closest obstacle ahead: the rear of another car, or a blocked part of the road
+#[derive(Clone, Copy, Debug)]
+pub struct Leader {
+    pub gap: f32,
+    pub speed: f32,
+}
+
+// The environment of the car as seen at a given time
+#[derive(Clone)]
+struct Perception {
+    time: f32,
+    leader: Option<Leader>,
+    // Position and speed limit of the signs in sight
+    signs: Vec<(road::RoadPoint, f32)>,
+}
+
+struct Breakdown {
+    position: road::RoadPoint,
+    time_left: f32,
+    started: bool,
 }
 
 
@@ -20,85 +111,699 @@ struct RoadInformation {
     incoming_speed_limits: HashMap<road::RoadPoint, f32>,
 }
 
+impl TripPlan {
+    // Whether the points and RoadNodes of the plan exist in the network
+    pub fn is_valid_on(&self, roads: &road::Roads) -> bool {
+        match self {
+            TripPlan::BackAndForth(start, end) => roads.contains(start) && roads.contains(end),
+            TripPlan::Loop(nodes) | TripPlan::Route(nodes) => nodes.iter().all(|node| roads.has_node(*node)),
+            TripPlan::Park { destination, .. } => roads.has_node(*destination),
+        }
+    }
+}
+
 
 impl Car {
-    pub fn new(position: road::RoadPoint) -> Self {
+    pub fn new(position: road::RoadPoint, vehicle_type: VehicleType, driver: DriverProfile, trip_plan: TripPlan, informed: bool, rng: Rng) -> Self {
         Self {
+            id: 0,
             position,
-            speed: 50. / 3.6,
-            target_speed: 50. / 3.6,
+            speed: (50. / 3.6f32).min(vehicle_type.max_speed),
+            acceleration: 0.,
+            target_speed: (50. / 3.6f32).min(vehicle_type.max_speed),
             road_information: RoadInformation { current_speed_limit: SPEED, incoming_speed_limits: HashMap::new() },
             planned_trip: { road::path::Path::new() },
+            previous_segment: None,
+            trip_plan,
+            origin: position,
             is_back: false,
+            informed,
+            // So that a closure on the initial path is avoided right away
+            time_since_reroute: MIN_REROUTE_INTERVAL,
+            breakdown: None,
+            stops: VecDeque::new(),
+            stop_state: StopState::Approaching,
+            parking_search: None,
+            trip: None,
+            finished_trips: Vec::new(),
+            distance_driven: 0.,
+            stopped: false,
+            vehicle_type,
+            driver,
+            perceptions: VecDeque::new(),
+            rng,
         }
     }
-    pub fn update(&mut self, step_size: f32, roads: &road::Roads) {
-        self.step(step_size, roads);
-        for sign in roads.get_signs(&self.position, SEEING_DISTANCE) {
-            if self.road_information.incoming_speed_limits.contains_key(&sign.position) {
-                // Skip the sign if we passed it already.
-                continue;
-            }
-            match sign.sign_type {
-                road::SignType::SpeedLimit => {
-                    self.road_information.incoming_speed_limits.insert(sign.position, sign.value);
-                },
-                road::SignType::EndSpeedLimit => {
-                    self.road_information.incoming_speed_limits.insert(sign.position, SPEED);
-                }
-            }
+
+    // Unique in a simulation, unlike the index of the car which changes when other cars leave
+    pub fn id(&self) -> usize { self.id }
+
+    pub(crate) fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    pub fn position(&self) -> &road::RoadPoint { &self.position }
+
+    pub fn speed(&self) -> f32 { self.speed }
+
+    pub fn acceleration(&self) -> f32 { self.acceleration }
+
+    pub fn target_speed(&self) -> f32 { self.target_speed }
+
+    pub fn remaining_segments(&self, roads: &road::Roads) -> Vec<road::RoadSegmentIdx> { self.planned_trip.remaining_segments(&self.position, roads) }
+
+    // Whether everything the car refers to exists in the network, e.g. for a car read from a snapshot
+    pub fn is_valid_on(&self, roads: &road::Roads) -> bool {
+        let mut points = [self.position, self.origin].into_iter()
+            .chain(self.road_information.incoming_speed_limits.keys().copied())
+            .chain(self.breakdown.as_ref().map(|breakdown| breakdown.position))
+            .chain(self.stops.iter().map(|stop| stop.position))
+            .chain(self.perceptions.iter().flat_map(|perception| perception.signs.iter().map(|(position, _speed_limit)| *position)));
+        self.trip_plan.is_valid_on(roads)
+            && self.planned_trip.is_valid_on(roads)
+            && self.previous_segment.is_none_or(|segment| roads.has_segment(segment))
+            && points.all(|point| roads.contains(&point))
+    }
+
+    // The car will stop at the given position and stay there for the duration. Returns false, leaving the car as it is,
+    // if the position is not on the path ahead.
+    pub fn break_down_at(&mut self, position: road::RoadPoint, duration: f32, roads: &road::Roads) -> bool {
+        if position.position() > roads.segment_length(position.road_segment()) || self.planned_trip.distance_to(&self.position, &position, roads).is_none() {
+            return false;
+        }
+        self.breakdown = Some(Breakdown { position, time_left: duration, started: false });
+
+        true
+    }
+
+    pub fn is_broken_down(&self) -> bool { self.breakdown.as_ref().is_some_and(|breakdown| breakdown.started) }
+
+    pub fn add_stop(&mut self, stop: Stop) {
+        self.stops.push_back(stop);
+    }
+
+    pub fn next_stop(&self) -> Option<&Stop> { self.stops.front() }
+
+    pub fn is_at_stop(&self) -> bool { !self.stops.is_empty() && self.stop_state != StopState::Approaching }
+
+    // At the stop, until dwell is called
+    pub fn has_arrived_at_stop(&self) -> bool { self.stop_state == StopState::Arrived }
+
+    pub fn is_in_bay(&self) -> bool { self.is_at_stop() && self.stops.front().is_some_and(|stop| stop.in_bay) }
+
+    pub fn dwell(&mut self, duration: f32) {
+        self.stop_state = StopState::Dwelling(duration);
+    }
+
+    // Done dwelling in a bay, until leave_stop is called
+    pub fn is_leaving_bay(&self) -> bool { self.stop_state == StopState::Leaving }
+
+    pub fn leave_stop(&mut self) {
+        self.stops.pop_front();
+        self.stop_state = StopState::Approaching;
+    }
+
+    // Only cars following a Route arrive
+    pub fn has_arrived(&self, roads: &road::Roads) -> bool {
+        matches!(self.trip_plan, TripPlan::Route(_)) && self.planned_trip.total_distance(roads) > 0.
+            && self.planned_trip.distance_left(&self.position, roads) <= POINT_REACHED_DISTANCE
+    }
+
+    pub fn parking_search_start(&self) -> Option<f32> { self.parking_search.map(|(time, _distance)| time) }
+
+    // Driven since the search for parking started
+    pub fn cruising_distance(&self) -> f32 { self.parking_search.map_or(0., |(_time, distance)| self.distance_driven - distance) }
+
+    pub fn parking_duration(&self) -> Option<f32> {
+        match self.trip_plan {
+            TripPlan::Park { duration, .. } => Some(duration),
+            _ => None,
+        }
+    }
+
+    pub fn distance_driven(&self) -> f32 { self.distance_driven }
+
+    // The trip in progress, with its arrival time set to the last update
+    pub fn current_trip(&self) -> Option<&TripRecord> { self.trip.as_ref() }
+
+    // Ends the trip in progress, when the car parks. The next one starts on the next update.
+    pub fn end_trip(&mut self) -> Option<TripRecord> { self.trip.take() }
+
+    // Leaves the parking space, driving back to the origin along a Route. Returns false, leaving the car as it is, if
+    // there is no path to it.
+    pub fn start_return_trip(&mut self, roads: &road::Roads) -> bool {
+        let (from, to) = roads.segment_nodes(self.position.road_segment());
+        let origin = roads.segment_nodes(self.origin.road_segment()).0;
+        let Some((path, _length)) = pathfinding::pathfind_between_nodes(to, origin, roads, |segment| Some(roads.segment_length(segment)), 1.) else {
+            return false;
+        };
+        // The Route starts at the end of the current segment, which may be the origin itself
+        self.set_trip_plan(TripPlan::Route(if path.len() > 1 { path } else { vec![from, to] }));
+        self.set_speed(0.);
+        self.acceleration = 0.;
+        self.perceptions.clear();
+
+        true
+    }
+
+    pub fn vehicle_type(&self) -> &VehicleType { &self.vehicle_type }
+
+    pub fn length(&self) -> f32 { self.vehicle_type.length }
+
+    pub fn is_bicycle(&self) -> bool { self.vehicle_type.class == VehicleClass::Bicycle }
+
+    // As last seen by the driver
+    pub fn current_speed_limit(&self) -> f32 { self.road_information.current_speed_limit }
+
+    // Speed limits seen ahead, by position
+    pub fn incoming_speed_limits(&self) -> Vec<(road::RoadPoint, f32)> {
+        let mut speed_limits: Vec<(road::RoadPoint, f32)> = self.road_information.incoming_speed_limits.iter().map(|(position, speed)| (*position, *speed)).collect();
+        speed_limits.sort_by(|a, b| a.0.road_segment().cmp(&b.0.road_segment()).then(a.0.position().total_cmp(&b.0.position())));
+
+        speed_limits
+    }
+
+    // Speed the driver aims for on the current road, without traffic
+    pub fn desired_speed(&self) -> f32 { (self.road_information.current_speed_limit * self.driver.desired_speed_factor).min(self.vehicle_type.max_speed) }
+
+    pub fn driver(&self) -> &DriverProfile { &self.driver }
+
+    // Sets both the speed and the target speed, for initial conditions and perturbations
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+        self.target_speed = speed;
+    }
+
+    // Replaces the planned path, the car drives according to the new plan from its next update
+    pub fn set_trip_plan(&mut self, trip_plan: TripPlan) {
+        self.trip_plan = trip_plan;
+        self.planned_trip = road::path::Path::new();
+        self.is_back = false;
+        self.parking_search = None;
+    }
+
+    pub fn emission_class(&self) -> EmissionClass { self.vehicle_type.emission_class }
+
+    pub fn take_finished_trips(&mut self) -> Vec<TripRecord> { std::mem::take(&mut self.finished_trips) }
+
+    // Returns the emissions of the step
+    pub fn update(&mut self, time: f32, step_size: f32, roads: &road::Roads, travel_times: &TravelTimes, emission_model: &EmissionModel, leader: Option<Leader>) -> Emissions {
+        // Moving is limited by the actual leader, but the target speed depends on the delayed perception of it
+        let moved = self.step(step_size, roads, self.closest_obstacle(leader, roads));
+        let perception = self.perceive(time + step_size, leader.map(|leader| Leader { gap: leader.gap - moved, ..leader }), roads);
+        let leader = self.closest_obstacle(perception.leader, roads);
+        let emissions = emission_model.emissions(self.emission_class(), self.speed, self.acceleration, step_size);
+        self.record_trip(time, step_size, moved, emissions);
+        self.check_breakdown(step_size, roads);
+        self.check_stop(step_size, roads);
+        if self.is_broken_down() || self.is_at_stop() {
+            self.target_speed = 0.;
+            return emissions;
+        }
+        for (position, speed_limit) in perception.signs {
+            // Signs already known are inserted again, as their value may have changed
+            self.road_information.incoming_speed_limits.insert(position, speed_limit);
         }
         self.target_speed = f32::INFINITY;
         for (speed_limit_position, speed) in self.road_information.incoming_speed_limits.iter() {
             // Update the current speed limit information
             if roads.get_distance(speed_limit_position, &self.position) <= roads.get_distance(&self.position, speed_limit_position) {
                 self.road_information.current_speed_limit = *speed;
-                self.target_speed = self.target_speed.min(self.road_information.current_speed_limit);
+                self.target_speed = self.target_speed.min(self.road_information.current_speed_limit * self.driver.desired_speed_factor);
             }
+            let desired_speed = speed * self.driver.desired_speed_factor;
             // Look at how much distance it would take to accelerate / decelerate
-            let acceleration_distance = (self.speed.powi(2) - speed.powi(2)).abs() / (2. * OPTIMAL_ACCELERATION);
+            let rate = if desired_speed < self.speed { self.deceleration() } else { self.acceleration_rate() };
+            let acceleration_distance = (self.speed.powi(2) - desired_speed.powi(2)).abs() / (2. * rate);
             if acceleration_distance > roads.get_distance(&self.position, speed_limit_position) {
-                self.target_speed = self.target_speed.min(*speed);
+                self.target_speed = self.target_speed.min(desired_speed);
             }
         }
-        self.target_speed = self.target_speed.min(self.road_information.current_speed_limit);
+        self.target_speed = self.target_speed
+            .min(self.road_information.current_speed_limit * self.driver.desired_speed_factor)
+            .min(self.vehicle_type.max_speed);
+        if let Some(leader) = leader {
+            // Highest speed from which the car can still stop behind its leader. The reaction time is already in the
+            // delay of the perceived leader.
+            let braking_distance = (leader.gap - MIN_GAP).max(0.);
+            let safe_speed = (leader.speed.powi(2) + 2. * self.deceleration() * braking_distance).sqrt();
+            // Speed at which the driver's preferred time headway is kept. Work zones lower the capacity of the road by
+            // making drivers keep longer headways.
+            let time_headway = self.driver.time_headway / roads.capacity_factor(self.position.road_segment()).max(MIN_CAPACITY_FACTOR);
+            let headway_speed = (leader.gap - MIN_GAP).max(0.) / time_headway;
+            self.target_speed = self.target_speed.min(safe_speed).min(headway_speed);
+        }
         // Remove speed limits not used anymore
         self.road_information.incoming_speed_limits.retain(|road_point, _speed| { roads.get_distance(&self.position, road_point) < roads.get_distance(road_point, &self.position) });
-        self.check_path(roads);
+        self.check_path(time, roads);
+        self.check_reroute(step_size, roads, travel_times);
+
+        emissions
+    }
+
+    // Angle of the direction from the rear to the front of the car, counterclockwise from the x axis, in radians
+    pub fn heading(&self, roads: &road::Roads) -> f32 {
+        let (front_x, front_y) = self.point_along(0., roads);
+        let (rear_x, rear_y) = self.point_along(-self.length(), roads);
+        if (front_x - rear_x).hypot(front_y - rear_y) > f32::EPSILON {
+            return (front_y - rear_y).atan2(front_x - rear_x);
+        }
+        // At the start of the first segment, along the road ahead
+        let (ahead_x, ahead_y) = self.point_along(POINT_REACHED_DISTANCE, roads);
+        (ahead_y - front_y).atan2(ahead_x - front_x)
+    }
+
+    // Middle of the car, on the road
+    pub fn center_xy(&self, roads: &road::Roads) -> (f32, f32) { self.point_along(-self.length() / 2., roads) }
+
+    pub fn render(&self, window: &gui::Window, roads: &road::Roads) {
+        window.draw_car(self.center_xy(roads), self.heading(roads), &self.vehicle_type);
     }
 
-    pub fn render(&self, window: &gui::Window, roads: &road::Roads, draw_speed: bool) {
-        window.draw_car(roads.get_position_xy(&self.position), if draw_speed { self.speed } else { -1. });
+    // Point offset along the road from the front of the car, on the previous or next segment when off the current one
+    fn point_along(&self, offset: f32, roads: &road::Roads) -> (f32, f32) {
+        let segment = self.position.road_segment();
+        let (length, position) = (roads.segment_length(segment), self.position.position() + offset);
+        let point = if position < 0. {
+            match self.previous_segment {
+                Some(previous) => road::RoadPoint::new(previous, (roads.segment_length(previous) + position).max(0.)),
+                None => road::RoadPoint::new(segment, 0.),
+            }
+        } else if position > length {
+            match self.remaining_segments(roads).first() {
+                Some(next) => road::RoadPoint::new(*next, (position - length).min(roads.segment_length(*next))),
+                None => road::RoadPoint::new(segment, length),
+            }
+        } else {
+            road::RoadPoint::new(segment, position)
+        };
+
+        roads.get_position_xy(&point)
     }
 
-    fn step(&mut self, step_size: f32, roads: &road::Roads) {
+    fn step(&mut self, step_size: f32, roads: &road::Roads, leader: Option<Leader>) -> f32 {
+        let previous_speed = self.speed;
         if self.speed > self.target_speed {
-            self.speed -= OPTIMAL_ACCELERATION * step_size;
+            self.speed -= self.deceleration() * step_size;
             if self.speed < self.target_speed {
                 self.speed = self.target_speed;
             }
         } else if self.speed < self.target_speed {
-            self.speed += OPTIMAL_ACCELERATION * step_size;
+            self.speed += self.acceleration_rate() * step_size;
             if self.speed > self.target_speed {
                 self.speed = self.target_speed;
             }
         }
-        self.planned_trip.move_by(&mut self.position, self.speed * step_size, roads);
+        let mut amount = self.speed * step_size;
+        if let Some(leader) = leader {
+            // Never drive into the leader
+            amount = amount.min((leader.gap - MIN_GAP).max(0.));
+        }
+        let segment = self.position.road_segment();
+        let moved = self.planned_trip.move_by(&mut self.position, amount, roads);
+        if self.position.road_segment() != segment {
+            self.previous_segment = Some(segment);
+        }
+        if moved < self.speed * step_size {
+            // The end of the path or the leader has been reached
+            self.speed = moved / step_size;
+        }
+        self.acceleration = (self.speed - previous_speed) / step_size;
+
+        moved
+    }
+
+    // Records what the driver sees now, and returns what they saw a reaction time ago
+    fn perceive(&mut self, time: f32, leader: Option<Leader>, roads: &road::Roads) -> Perception {
+        let signs = roads.get_signs(&self.position, SEEING_DISTANCE).iter().map(|sign| match sign.sign_type {
+            road::SignType::SpeedLimit => (sign.position, sign.value),
+            road::SignType::EndSpeedLimit => (sign.position, SPEED),
+        }).collect();
+        self.perceptions.push_back(Perception { time, leader, signs });
+        while self.perceptions.len() > 1 && self.perceptions[1].time <= time - self.driver.reaction_time {
+            self.perceptions.pop_front();
+        }
+
+        self.perceptions[0].clone()
+    }
+
+    fn acceleration_rate(&self) -> f32 { self.vehicle_type.max_acceleration * self.driver.acceleration_factor() }
+
+    fn deceleration(&self) -> f32 { self.vehicle_type.comfortable_deceleration * self.driver.deceleration_factor() }
+
+    // The emissions of the step count in the trip it ends, if any
+    fn record_trip(&mut self, time: f32, step_size: f32, moved: f32, emissions: Emissions) {
+        let trip = self.trip.get_or_insert(TripRecord { departure_time: time, arrival_time: time, distance: 0., stops: 0, free_flow_time: 0., emissions: Emissions::default() });
+        trip.arrival_time = time + step_size;
+        trip.distance += moved;
+        trip.emissions += emissions;
+        trip.free_flow_time += moved / self.road_information.current_speed_limit;
+        self.distance_driven += moved;
+        if !self.stopped && self.speed < STOPPED_SPEED {
+            self.stopped = true;
+            trip.stops += 1;
+        } else if self.stopped && self.speed > MOVING_SPEED {
+            self.stopped = false;
+        }
+        for _ in 0..self.planned_trip.take_finished_legs() {
+            let arrival_time = time + step_size;
+            self.finished_trips.push(*trip);
+            *trip = TripRecord { departure_time: arrival_time, arrival_time, distance: 0., stops: 0, free_flow_time: 0., emissions: Emissions::default() };
+        }
+    }
+
+    fn closest_obstacle(&self, mut leader: Option<Leader>, roads: &road::Roads) -> Option<Leader> {
+        let breakdown = self.breakdown.as_ref().filter(|breakdown| !breakdown.started).map(|breakdown| breakdown.position);
+        let stop = self.stops.front().filter(|_stop| self.stop_state == StopState::Approaching).map(|stop| stop.position);
+        // Breakdown points and stops are seen as stopped leaders, placed so that the car stops right on them
+        for position in breakdown.into_iter().chain(stop) {
+            match self.planned_trip.distance_to(&self.position, &position, roads) {
+                Some(distance) if leader.is_none_or(|leader| distance + MIN_GAP < leader.gap) => leader = Some(Leader { gap: distance + MIN_GAP, speed: 0. }),
+                _ => {},
+            }
+        }
+
+        leader
+    }
+
+    fn check_breakdown(&mut self, step_size: f32, roads: &road::Roads) {
+        let Some(breakdown) = &mut self.breakdown else {
+            return;
+        };
+        if breakdown.started {
+            breakdown.time_left -= step_size;
+            if breakdown.time_left <= 0. {
+                self.breakdown = None;
+            }
+        } else if self.planned_trip.distance_to(&self.position, &breakdown.position, roads).is_some_and(|distance| distance <= POINT_REACHED_DISTANCE) {
+            breakdown.started = true;
+            self.speed = 0.;
+        }
+    }
+
+    fn check_stop(&mut self, step_size: f32, roads: &road::Roads) {
+        let Some(stop) = self.stops.front() else {
+            return;
+        };
+        match self.stop_state {
+            StopState::Approaching => {
+                if self.planned_trip.distance_to(&self.position, &stop.position, roads).is_some_and(|distance| distance <= POINT_REACHED_DISTANCE) {
+                    self.stop_state = StopState::Arrived;
+                    self.speed = 0.;
+                }
+            },
+            StopState::Dwelling(time_left) if time_left > step_size => self.stop_state = StopState::Dwelling(time_left - step_size),
+            StopState::Dwelling(_) if stop.in_bay => self.stop_state = StopState::Leaving,
+            StopState::Dwelling(_) => self.leave_stop(),
+            StopState::Arrived | StopState::Leaving => {},
+        }
     }
 
-    fn check_path(&mut self, roads: &road::Roads) {
+    fn check_path(&mut self, time: f32, roads: &road::Roads) {
+        let (start, end) = match &self.trip_plan {
+            TripPlan::BackAndForth(start, end) => (*start, *end),
+            TripPlan::Loop(nodes) => {
+                // The path never ends, as the node lookups always go back to its start
+                if self.planned_trip.total_distance(roads) == 0. {
+                    let mut nodes = nodes.clone();
+                    nodes.push(nodes[0]);
+                    self.planned_trip = road::path::Path::from(nodes);
+                }
+                return;
+            },
+            TripPlan::Route(nodes) => {
+                if self.planned_trip.total_distance(roads) == 0. {
+                    self.planned_trip = road::path::Path::from(nodes.clone());
+                }
+                return;
+            },
+            TripPlan::Park { destination, .. } => {
+                let destination = *destination;
+                self.check_parking_path(time, destination, roads);
+                return;
+            },
+        };
         if self.planned_trip.total_distance(roads) < 100. || self.planned_trip.distance_left(&self.position, roads) < 100. {
-            let start = road::RoadPoint::new(road::RoadSegmentIdx(0), 1.);
-            let end = road::RoadPoint::new(road::RoadSegmentIdx(23), 1.);
-            if self.is_back {
-                self.planned_trip.append(road::path::pathfinding::pathfind(&end, &start, roads).expect("Could not find a way to go back to start"));
+            let routes = if self.is_back {
+                route_choice::generate_routes(&end, &start, ROUTE_ALTERNATIVES, roads)
             } else {
-                self.planned_trip.append(road::path::pathfinding::pathfind(&start, &end, roads).expect("Could not find a way to go to the top-right corner"));
-            }
+                route_choice::generate_routes(&start, &end, ROUTE_ALTERNATIVES, roads)
+            };
+            // There may be no route while some segments are closed, try again on the next update
+            let Some(chosen_route) = route_choice::choose_route(&routes, route_choice::RouteChoiceModel::PathSizeLogit, ROUTE_CHOICE_THETA, &mut self.rng) else {
+                return;
+            };
+            self.planned_trip.append_path(routes[chosen_route].path.clone());
             self.is_back = !self.is_back;
         }
         if self.planned_trip.total_distance(roads) > 100. {
             self.planned_trip.forget_before(&self.position, roads);
         }
     }
+
+    // Drives to the destination, then around it until a parking space is found
+    fn check_parking_path(&mut self, time: f32, destination: road::RoadNodeIdx, roads: &road::Roads) {
+        if self.planned_trip.total_distance(roads) == 0. {
+            let (from, to) = roads.segment_nodes(self.position.road_segment());
+            let path = pathfinding::pathfind_between_nodes(to, destination, roads, |segment| Some(roads.segment_length(segment)), 1.)
+                .map_or(vec![to], |(path, _length)| path);
+            self.planned_trip = road::path::Path::new();
+            self.planned_trip.extend(std::iter::once(from).chain(path).collect());
+        }
+        // The cruising path may go through the same RoadNodes again, so only the part ahead is kept
+        self.planned_trip.forget_before(&self.position, roads);
+        let distance_left = self.planned_trip.distance_left(&self.position, roads);
+        if self.parking_search.is_none() && distance_left <= PARKING_SEARCH_DISTANCE {
+            self.parking_search = Some((time, self.distance_driven));
+        }
+        if self.parking_search.is_some() && distance_left < CRUISING_LOOKAHEAD
+            && let Some(node) = self.next_cruising_node(destination, roads) {
+            self.planned_trip.extend(vec![node]);
+        }
+    }
+
+    // Random turn at the end of the path, without turning back unless at a dead end
+    fn next_cruising_node(&mut self, destination: road::RoadNodeIdx, roads: &road::Roads) -> Option<road::RoadNodeIdx> {
+        let nodes = self.planned_trip.nodes();
+        let last = *nodes.last()?;
+        let previous = nodes.len().checked_sub(2).map(|i| nodes[i]);
+        let next_nodes: Vec<road::RoadNodeIdx> = roads.outgoing_segments(last).into_iter()
+            .filter(|segment| !roads.is_closed(*segment))
+            .map(|segment| roads.segment_nodes(segment).1)
+            .collect();
+        let forward_nodes: Vec<road::RoadNodeIdx> = next_nodes.iter().copied().filter(|node| Some(*node) != previous).collect();
+        let next_nodes = if forward_nodes.is_empty() { next_nodes } else { forward_nodes };
+        let (destination_x, destination_y) = roads.node_xy(destination);
+        let distance = |node| {
+            let (x, y) = roads.node_xy(node);
+            ((x - destination_x).powi(2) + (y - destination_y).powi(2)).sqrt()
+        };
+        if distance(last) > CRUISING_RADIUS {
+            return next_nodes.into_iter().min_by(|a, b| distance(*a).total_cmp(&distance(*b)));
+        }
+        if next_nodes.is_empty() {
+            return None;
+        }
+
+        Some(next_nodes[(self.rng.next_u64() % next_nodes.len() as u64) as usize])
+    }
+
+    fn check_reroute(&mut self, step_size: f32, roads: &road::Roads, travel_times: &TravelTimes) {
+        if matches!(self.trip_plan, TripPlan::Loop(_) | TripPlan::Route(_) | TripPlan::Park { .. }) {
+            return;
+        }
+        self.time_since_reroute += step_size;
+        if self.time_since_reroute < MIN_REROUTE_INTERVAL {
+            return;
+        }
+        let segments_ahead = self.planned_trip.segments_ahead(&self.position, roads);
+        if segments_ahead.iter().any(|segment| roads.is_closed(*segment)) {
+            // Closures are signposted, so every driver avoids them. When there is no way around, the path is only
+            // searched again after MIN_REROUTE_INTERVAL.
+            if self.informed {
+                self.planned_trip.reroute(&self.position, roads, |segment| Some(travel_times.get(segment)), travel_times.min_time_per_meter());
+            } else {
+                self.planned_trip.reroute(&self.position, roads, |segment| Some(roads.segment_length(segment)), 1.);
+            }
+            self.time_since_reroute = 0.;
+            return;
+        }
+        if !self.informed {
+            return;
+        }
+        if self.time_since_reroute < REROUTE_INTERVAL {
+            let delay_ahead = segments_ahead
+                .iter()
+                .any(|segment| travel_times.get(*segment) > travel_times.free_flow(*segment) * REROUTE_DELAY_FACTOR);
+            if !delay_ahead {
+                return;
+            }
+        }
+        self.planned_trip.reroute(&self.position, roads, |segment| Some(travel_times.get(segment)), travel_times.min_time_per_meter());
+        self.time_since_reroute = 0.;
+    }
+}
+
+impl Snapshot for Car {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.id);
+        writer.write(&self.position);
+        writer.write(&self.speed);
+        writer.write(&self.acceleration);
+        writer.write(&self.target_speed);
+        writer.write(&self.road_information);
+        writer.write(&self.planned_trip);
+        writer.write(&self.previous_segment);
+        writer.write(&self.trip_plan);
+        writer.write(&self.origin);
+        writer.write(&self.is_back);
+        writer.write(&self.informed);
+        writer.write(&self.time_since_reroute);
+        writer.write(&self.breakdown);
+        writer.write(&self.stops);
+        writer.write(&self.stop_state);
+        writer.write(&self.parking_search);
+        writer.write(&self.trip);
+        writer.write(&self.finished_trips);
+        writer.write(&self.distance_driven);
+        writer.write(&self.stopped);
+        writer.write(&self.vehicle_type);
+        writer.write(&self.driver);
+        writer.write(&self.perceptions);
+        writer.write(&self.rng);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self {
+            id: reader.read()?,
+            position: reader.read()?,
+            speed: reader.read()?,
+            acceleration: reader.read()?,
+            target_speed: reader.read()?,
+            road_information: reader.read()?,
+            planned_trip: reader.read()?,
+            previous_segment: reader.read()?,
+            trip_plan: reader.read()?,
+            origin: reader.read()?,
+            is_back: reader.read()?,
+            informed: reader.read()?,
+            time_since_reroute: reader.read()?,
+            breakdown: reader.read()?,
+            stops: reader.read()?,
+            stop_state: reader.read()?,
+            parking_search: reader.read()?,
+            trip: reader.read()?,
+            finished_trips: reader.read()?,
+            distance_driven: reader.read()?,
+            stopped: reader.read()?,
+            vehicle_type: reader.read()?,
+            driver: reader.read()?,
+            perceptions: reader.read()?,
+            rng: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for TripPlan {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        match self {
+            TripPlan::BackAndForth(start, end) => {
+                writer.write(&0u8);
+                writer.write(start);
+                writer.write(end);
+            },
+            TripPlan::Loop(nodes) => {
+                writer.write(&1u8);
+                writer.write(nodes);
+            },
+            TripPlan::Route(nodes) => {
+                writer.write(&2u8);
+                writer.write(nodes);
+            },
+            TripPlan::Park { destination, duration } => {
+                writer.write(&3u8);
+                writer.write(destination);
+                writer.write(duration);
+            },
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(match reader.read_variant(4)? {
+            0 => TripPlan::BackAndForth(reader.read()?, reader.read()?),
+            1 => TripPlan::Loop(reader.read()?),
+            2 => TripPlan::Route(reader.read()?),
+            _ => TripPlan::Park { destination: reader.read()?, duration: reader.read()? },
+        })
+    }
+}
+
+impl Snapshot for Stop {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.position);
+        writer.write(&self.in_bay);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { position: reader.read()?, in_bay: reader.read()? }) }
+}
+
+impl Snapshot for StopState {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        match self {
+            StopState::Approaching => writer.write(&0u8),
+            StopState::Arrived => writer.write(&1u8),
+            StopState::Dwelling(time_left) => {
+                writer.write(&2u8);
+                writer.write(time_left);
+            },
+            StopState::Leaving => writer.write(&3u8),
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(match reader.read_variant(4)? {
+            0 => StopState::Approaching,
+            1 => StopState::Arrived,
+            2 => StopState::Dwelling(reader.read()?),
+            _ => StopState::Leaving,
+        })
+    }
+}
+
+impl Snapshot for Leader {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.gap);
+        writer.write(&self.speed);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { gap: reader.read()?, speed: reader.read()? }) }
+}
+
+impl Snapshot for Perception {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.time);
+        writer.write(&self.leader);
+        writer.write(&self.signs);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { time: reader.read()?, leader: reader.read()?, signs: reader.read()? }) }
+}
+
+impl Snapshot for Breakdown {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.position);
+        writer.write(&self.time_left);
+        writer.write(&self.started);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { position: reader.read()?, time_left: reader.read()?, started: reader.read()? }) }
+}
+
+impl Snapshot for RoadInformation {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.current_speed_limit);
+        writer.write(&self.incoming_speed_limits);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { current_speed_limit: reader.read()?, incoming_speed_limits: reader.read()? }) }
 }
@@ -0,0 +1,119 @@
+use std::io;
+use crate::{snapshot::{Snapshot, SnapshotReader, SnapshotWriter}, utils::Rng};
+
+// How a driver behaves, the same for the whole simulation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriverProfile {
+    // Speed the driver aims for, relative to the speed limit
+    pub desired_speed_factor: f32,
+    pub reaction_time: f32, // s
+    // Time gap kept to the leader
+    pub time_headway: f32, // s
+    // From 0 (calm) to 1, share of the vehicle's acceleration used and harder braking accepted
+    pub aggressiveness: f32,
+}
+
+// Normal distribution, clamped to [min, max]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Distribution {
+    pub mean: f32,
+    pub standard_deviation: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriverDistribution {
+    pub desired_speed_factor: Distribution,
+    pub reaction_time: Distribution,
+    pub time_headway: Distribution,
+    pub aggressiveness: Distribution,
+}
+
+impl DriverProfile {
+    // Acceleration actually used, out of the vehicle's maximum
+    pub fn acceleration_factor(&self) -> f32 { 0.6 + 0.4 * self.aggressiveness }
+
+    // Deceleration accepted, relative to the vehicle's comfortable one
+    pub fn deceleration_factor(&self) -> f32 { 1. + 0.5 * self.aggressiveness }
+}
+
+impl Default for DriverProfile {
+    fn default() -> Self { Self { desired_speed_factor: 1., reaction_time: 1., time_headway: 1.5, aggressiveness: 0.5 } }
+}
+
+impl Distribution {
+    pub fn new(mean: f32, standard_deviation: f32, min: f32, max: f32) -> Self { Self { mean, standard_deviation, min, max } }
+
+    // Always the mean
+    pub fn constant(value: f32) -> Self { Self::new(value, 0., value, value) }
+
+    pub fn sample(&self, rng: &mut Rng) -> f32 { rng.normal(self.mean, self.standard_deviation).clamp(self.min, self.max) }
+}
+
+impl DriverDistribution {
+    // Every driver has the given profile
+    pub fn constant(profile: DriverProfile) -> Self {
+        Self {
+            desired_speed_factor: Distribution::constant(profile.desired_speed_factor),
+            reaction_time: Distribution::constant(profile.reaction_time),
+            time_headway: Distribution::constant(profile.time_headway),
+            aggressiveness: Distribution::constant(profile.aggressiveness),
+        }
+    }
+
+    pub fn sample(&self, rng: &mut Rng) -> DriverProfile {
+        DriverProfile {
+            desired_speed_factor: self.desired_speed_factor.sample(rng),
+            reaction_time: self.reaction_time.sample(rng),
+            time_headway: self.time_headway.sample(rng),
+            aggressiveness: self.aggressiveness.sample(rng),
+        }
+    }
+}
+
+// Of the order of magnitude of the values observed on urban roads
+impl Default for DriverDistribution {
+    fn default() -> Self {
+        Self {
+            desired_speed_factor: Distribution::new(1.05, 0.1, 0.8, 1.3),
+            reaction_time: Distribution::new(1., 0.3, 0.4, 2.),
+            time_headway: Distribution::new(1.5, 0.4, 0.8, 3.),
+            aggressiveness: Distribution::new(0.5, 0.2, 0., 1.),
+        }
+    }
+}
+
+impl Snapshot for DriverProfile {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        for value in [self.desired_speed_factor, self.reaction_time, self.time_headway, self.aggressiveness] {
+            writer.write(&value);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self { desired_speed_factor: reader.read()?, reaction_time: reader.read()?, time_headway: reader.read()?, aggressiveness: reader.read()? })
+    }
+}
+
+impl Snapshot for Distribution {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        for value in [self.mean, self.standard_deviation, self.min, self.max] {
+            writer.write(&value);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self::new(reader.read()?, reader.read()?, reader.read()?, reader.read()?)) }
+}
+
+impl Snapshot for DriverDistribution {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        for distribution in [&self.desired_speed_factor, &self.reaction_time, &self.time_headway, &self.aggressiveness] {
+            writer.write(distribution);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self { desired_speed_factor: reader.read()?, reaction_time: reader.read()?, time_headway: reader.read()?, aggressiveness: reader.read()? })
+    }
+}
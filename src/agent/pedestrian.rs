@@ -0,0 +1,188 @@
+use std::io;
+use crate::{gui, road::{self, path::pathfinding}, snapshot::{Snapshot, SnapshotReader, SnapshotWriter}};
+
+pub const WALKING_SPEED: f32 = 1.34; // m/s, average
+const SIDEWALK_OFFSET: f32 = 3.; // m, from the middle of the road
+// Pedestrians this close to a zebra crossing are about to step on it, and cars yield to them already
+const APPROACHING_DISTANCE: f32 = 3.; // m
+
+// Walks back and forth between two RoadNodes along the sidewalks, crossing the roads at the crossings on the way
+pub struct Pedestrian {
+    origin: road::RoadNodeIdx,
+    destination: road::RoadNodeIdx,
+    route: Vec<road::RoadNodeIdx>,
+    // Index in route of the RoadNode the current segment starts from
+    leg: usize,
+    position: f32,
+    speed: f32,
+    state: PedestrianState,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PedestrianState {
+    Walking,
+    // At a signalised crossing, during the cars' phase
+    Waiting(road::RoadNodeIdx),
+    // progress goes from 0 to 1
+    Crossing { node: road::RoadNodeIdx, progress: f32 },
+}
+
+impl Pedestrian {
+    pub fn new(origin: road::RoadNodeIdx, destination: road::RoadNodeIdx, speed: f32) -> Self {
+        Self { origin, destination, route: Vec::new(), leg: 0, position: 0., speed, state: PedestrianState::Walking }
+    }
+
+    pub fn is_crossing(&self) -> bool { matches!(self.state, PedestrianState::Crossing { .. }) }
+
+    // RoadNode of the crossing the pedestrian is on or about to step on, which cars must yield at
+    pub fn blocked_crossing(&self, roads: &road::Roads) -> Option<road::RoadNodeIdx> {
+        match self.state {
+            PedestrianState::Crossing { node, .. } => Some(node),
+            PedestrianState::Waiting(_) => None,
+            PedestrianState::Walking => {
+                let segment = self.current_segment(roads)?;
+                let node = self.route[self.leg + 1];
+                let approaching = roads.segment_length(segment) - self.position < APPROACHING_DISTANCE;
+                roads.crossing_at(node).filter(|crossing| approaching && crossing.kind == road::CrossingKind::Zebra).map(|crossing| crossing.node)
+            },
+        }
+    }
+
+    pub fn update(&mut self, time: f32, step_size: f32, roads: &road::Roads) {
+        match self.state {
+            PedestrianState::Walking => {
+                let Some(segment) = self.current_segment(roads) else {
+                    self.plan_route(roads);
+                    return;
+                };
+                self.position += self.speed * step_size;
+                if self.position >= roads.segment_length(segment) {
+                    self.position = roads.segment_length(segment);
+                    let node = self.route[self.leg + 1];
+                    match roads.crossing_at(node) {
+                        Some(crossing) if crossing.is_walk_phase(time) => self.state = PedestrianState::Crossing { node, progress: 0. },
+                        Some(_crossing) => self.state = PedestrianState::Waiting(node),
+                        None => self.next_leg(roads),
+                    }
+                }
+            },
+            PedestrianState::Waiting(node) => {
+                if roads.crossing_at(node).is_none_or(|crossing| crossing.is_walk_phase(time)) {
+                    self.state = PedestrianState::Crossing { node, progress: 0. };
+                }
+            },
+            PedestrianState::Crossing { node, progress } => {
+                let length = roads.crossing_at(node).map_or(0., |crossing| crossing.length);
+                let progress = if length > 0. { progress + self.speed * step_size / length } else { 1. };
+                if progress >= 1. {
+                    self.next_leg(roads);
+                } else {
+                    self.state = PedestrianState::Crossing { node, progress };
+                }
+            },
+        }
+    }
+
+    pub fn position_xy(&self, roads: &road::Roads) -> (f32, f32) {
+        let Some(segment) = self.current_segment(roads) else {
+            return roads.node_xy(self.origin);
+        };
+        // On the right of the walking direction, then from right to left of the road when crossing at its end
+        let offset = match self.state {
+            PedestrianState::Crossing { progress, .. } => SIDEWALK_OFFSET * (1. - 2. * progress),
+            _ => SIDEWALK_OFFSET,
+        };
+        let length = roads.segment_length(segment);
+        let (x, y) = roads.get_position_xy(&road::RoadPoint::new(segment, self.position));
+        let (x_before, y_before) = roads.get_position_xy(&road::RoadPoint::new(segment, (self.position - 0.5).max(0.)));
+        let (x_after, y_after) = roads.get_position_xy(&road::RoadPoint::new(segment, (self.position + 0.5).min(length)));
+        let (dx, dy) = (x_after - x_before, y_after - y_before);
+        let norm = (dx * dx + dy * dy).sqrt().max(f32::EPSILON);
+
+        (x + dy / norm * offset, y - dx / norm * offset)
+    }
+
+    // Whether the RoadNodes of the pedestrian exist and its route follows the RoadSegments of the network
+    pub fn is_valid_on(&self, roads: &road::Roads) -> bool {
+        let state_node = match self.state {
+            PedestrianState::Walking => None,
+            PedestrianState::Waiting(node) | PedestrianState::Crossing { node, .. } => Some(node),
+        };
+        [self.origin, self.destination].into_iter().chain(state_node).chain(self.route.iter().copied()).all(|node| roads.has_node(node))
+            && self.route.windows(2).all(|nodes| roads.segment_between(nodes[0], nodes[1]).is_some())
+            && self.current_segment(roads).is_none_or(|segment| (0. ..=roads.segment_length(segment)).contains(&self.position))
+    }
+
+    pub fn render(&self, window: &gui::Window, roads: &road::Roads) {
+        window.draw_pedestrian(self.position_xy(roads));
+    }
+
+    fn current_segment(&self, roads: &road::Roads) -> Option<road::RoadSegmentIdx> {
+        if self.leg + 1 >= self.route.len() {
+            return None;
+        }
+        roads.segment_between(self.route[self.leg], self.route[self.leg + 1])
+    }
+
+    fn next_leg(&mut self, roads: &road::Roads) {
+        self.leg += 1;
+        self.position = 0.;
+        self.state = PedestrianState::Walking;
+        if self.leg + 1 >= self.route.len() {
+            // Arrived, walk back
+            std::mem::swap(&mut self.origin, &mut self.destination);
+            self.plan_route(roads);
+        }
+    }
+
+    // Shortest route along the sidewalks, none if there is no way
+    fn plan_route(&mut self, roads: &road::Roads) {
+        let sidewalk_length = |segment| if roads.has_sidewalk(segment) { Some(roads.segment_length(segment)) } else { None };
+        self.route = pathfinding::pathfind_between_nodes(self.origin, self.destination, roads, sidewalk_length, 1.)
+            .map(|(route, _length)| route)
+            .unwrap_or_default();
+        self.leg = 0;
+        self.position = 0.;
+    }
+}
+
+impl Snapshot for Pedestrian {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.origin);
+        writer.write(&self.destination);
+        writer.write(&self.route);
+        writer.write(&self.leg);
+        writer.write(&self.position);
+        writer.write(&self.speed);
+        writer.write(&self.state);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self { origin: reader.read()?, destination: reader.read()?, route: reader.read()?, leg: reader.read()?, position: reader.read()?, speed: reader.read()?, state: reader.read()? })
+    }
+}
+
+impl Snapshot for PedestrianState {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        match self {
+            PedestrianState::Walking => writer.write(&0u8),
+            PedestrianState::Waiting(node) => {
+                writer.write(&1u8);
+                writer.write(node);
+            },
+            PedestrianState::Crossing { node, progress } => {
+                writer.write(&2u8);
+                writer.write(node);
+                writer.write(progress);
+            },
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(match reader.read_variant(3)? {
+            0 => PedestrianState::Walking,
+            1 => PedestrianState::Waiting(reader.read()?),
+            _ => PedestrianState::Crossing { node: reader.read()?, progress: reader.read()? },
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use std::io;
+use crate::{agent::{car::{Car, TripPlan}, driver::DriverDistribution, vehicle::FleetMix}, road, snapshot::{Snapshot, SnapshotReader, SnapshotWriter}, utils::Rng};
+
+// Creates vehicles at a RoadPoint at a constant flow, drawing their type from a FleetMix and their driver from a DriverDistribution
+pub struct Spawner {
+    position: road::RoadPoint,
+    headway: f32, // s
+    fleet_mix: FleetMix,
+    drivers: DriverDistribution,
+    informed_share: f32,
+    // None to spawn vehicles forever
+    remaining: Option<usize>,
+    // Given to each vehicle
+    trip_plan: TripPlan,
+    next_time: f32,
+    waiting: Option<Car>,
+}
+
+impl Spawner {
+    // flow in veh/h
+    pub fn new(position: road::RoadPoint, flow: f32, fleet_mix: FleetMix, trip_plan: TripPlan, informed_share: f32, count: Option<usize>) -> Self {
+        Self { position, headway: 3600. / flow, fleet_mix, drivers: DriverDistribution::default(), informed_share, remaining: count, trip_plan, next_time: 0., waiting: None }
+    }
+
+    pub fn set_drivers(&mut self, drivers: DriverDistribution) {
+        self.drivers = drivers;
+    }
+
+    pub fn position(&self) -> &road::RoadPoint { &self.position }
+
+    // The vehicle to spawn, if it is time to. It must be given back with postpone if there is no room for it yet.
+    pub fn spawn(&mut self, time: f32, rng: &mut Rng) -> Option<Car> {
+        if let Some(car) = self.waiting.take() {
+            return Some(car);
+        }
+        if time < self.next_time || self.remaining == Some(0) {
+            return None;
+        }
+        self.next_time += self.headway;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+        let vehicle_type = self.fleet_mix.sample(rng);
+        let driver = self.drivers.sample(rng);
+        let informed = rng.next_f32() < self.informed_share;
+
+        Some(Car::new(self.position, vehicle_type, driver, self.trip_plan.clone(), informed, rng.fork()))
+    }
+
+    pub fn postpone(&mut self, car: Car) {
+        self.waiting = Some(car);
+    }
+
+    // Whether the spawner and the vehicle waiting to be spawned fit in the network
+    pub fn is_valid_on(&self, roads: &road::Roads) -> bool {
+        roads.contains(&self.position)
+            && self.trip_plan.is_valid_on(roads)
+            && self.waiting.as_ref().is_none_or(|car| car.is_valid_on(roads))
+    }
+}
+
+impl Snapshot for Spawner {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.position);
+        writer.write(&self.headway);
+        writer.write(&self.fleet_mix);
+        writer.write(&self.drivers);
+        writer.write(&self.informed_share);
+        writer.write(&self.remaining);
+        writer.write(&self.trip_plan);
+        writer.write(&self.next_time);
+        writer.write(&self.waiting);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self {
+            position: reader.read()?,
+            headway: reader.read()?,
+            fleet_mix: reader.read()?,
+            drivers: reader.read()?,
+            informed_share: reader.read()?,
+            remaining: reader.read()?,
+            trip_plan: reader.read()?,
+            next_time: reader.read()?,
+            waiting: reader.read()?,
+        })
+    }
+}
@@ -0,0 +1,159 @@
+use std::io;
+use macroquad::color::{Color, WHITE};
+use crate::{emissions::EmissionClass, snapshot::{self, Snapshot, SnapshotReader, SnapshotWriter}, utils::Rng};
+
+const CAR_SPRITE: &str = "resources/textures/car.png";
+const VAN_SPRITE: &str = "resources/textures/van.png";
+const TRUCK_SPRITE: &str = "resources/textures/truck.png";
+const BUS_SPRITE: &str = "resources/textures/bus.png";
+const MOTORCYCLE_SPRITE: &str = "resources/textures/motorcycle.png";
+const BICYCLE_SPRITE: &str = "resources/textures/bicycle.png";
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum VehicleClass {
+    Car,
+    Van,
+    Truck,
+    Bus,
+    Motorcycle,
+    Bicycle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VehicleType {
+    pub class: VehicleClass,
+    pub length: f32, // m
+    pub width: f32, // m
+    pub max_acceleration: f32, // m/s²
+    pub comfortable_deceleration: f32, // m/s²
+    pub max_speed: f32, // m/s
+    pub sprite: &'static str,
+    // Multiplied with the sprite colors
+    pub tint: Color,
+    pub emission_class: EmissionClass,
+}
+
+// Share of each vehicle type among the vehicles created by a spawner
+#[derive(Clone, Debug, PartialEq)]
+pub struct FleetMix {
+    shares: Vec<(VehicleType, f32)>,
+}
+
+impl VehicleClass {
+    pub const ALL: [VehicleClass; 6] = [VehicleClass::Car, VehicleClass::Van, VehicleClass::Truck, VehicleClass::Bus, VehicleClass::Motorcycle, VehicleClass::Bicycle];
+
+    pub fn default_type(&self) -> VehicleType {
+        match self {
+            // 0.14g, source : https://www.jsheld.com/insights/articles/a-naturalistic-study-of-vehicle-acceleration-and-deceleration-at-an-intersection
+            VehicleClass::Car => VehicleType {
+                class: *self, length: 4.5, width: 1.8, max_acceleration: 0.14 * 9.81, comfortable_deceleration: 2., max_speed: 160. / 3.6,
+                sprite: CAR_SPRITE, tint: WHITE, emission_class: EmissionClass::PetrolCar,
+            },
+            VehicleClass::Van => VehicleType {
+                class: *self, length: 5.5, width: 2., max_acceleration: 1.1, comfortable_deceleration: 2., max_speed: 130. / 3.6,
+                sprite: VAN_SPRITE, tint: WHITE, emission_class: EmissionClass::DieselVan,
+            },
+            VehicleClass::Truck => VehicleType {
+                class: *self, length: 12., width: 2.5, max_acceleration: 0.6, comfortable_deceleration: 1.5, max_speed: 90. / 3.6,
+                sprite: TRUCK_SPRITE, tint: WHITE, emission_class: EmissionClass::DieselTruck,
+            },
+            VehicleClass::Bus => VehicleType {
+                class: *self, length: 12., width: 2.55, max_acceleration: 0.8, comfortable_deceleration: 1.5, max_speed: 90. / 3.6,
+                sprite: BUS_SPRITE, tint: WHITE, emission_class: EmissionClass::DieselBus,
+            },
+            VehicleClass::Motorcycle => VehicleType {
+                class: *self, length: 2.2, width: 0.8, max_acceleration: 2.5, comfortable_deceleration: 2.5, max_speed: 160. / 3.6,
+                sprite: MOTORCYCLE_SPRITE, tint: WHITE, emission_class: EmissionClass::Motorcycle,
+            },
+            VehicleClass::Bicycle => VehicleType {
+                class: *self, length: 1.8, width: 0.6, max_acceleration: 0.8, comfortable_deceleration: 1.5, max_speed: 20. / 3.6,
+                sprite: BICYCLE_SPRITE, tint: WHITE, emission_class: EmissionClass::ZeroEmission,
+            },
+        }
+    }
+}
+
+impl FleetMix {
+    pub fn new() -> Self { Self { shares: Vec::new() } }
+
+    // Shares are relative to each other, they don't need to add up to 1
+    pub fn with(mut self, vehicle_type: VehicleType, share: f32) -> Self {
+        self.shares.push((vehicle_type, share));
+        self
+    }
+
+    pub fn shares(&self) -> &[(VehicleType, f32)] { &self.shares }
+
+    pub fn sample(&self, rng: &mut Rng) -> VehicleType {
+        let total: f32 = self.shares.iter().map(|(_vehicle_type, share)| share).sum();
+        let mut draw = rng.gen_range(0., total);
+        for (vehicle_type, share) in &self.shares {
+            if draw < *share {
+                return *vehicle_type;
+            }
+            draw -= share;
+        }
+
+        self.shares.last().map(|(vehicle_type, _share)| *vehicle_type).unwrap_or(VehicleClass::Car.default_type())
+    }
+}
+
+// Typical urban traffic
+impl Default for FleetMix {
+    fn default() -> Self {
+        Self::new()
+            .with(VehicleClass::Car.default_type(), 0.8)
+            .with(VehicleClass::Van.default_type(), 0.08)
+            .with(VehicleClass::Truck.default_type(), 0.05)
+            .with(VehicleClass::Bus.default_type(), 0.02)
+            .with(VehicleClass::Motorcycle.default_type(), 0.05)
+    }
+}
+
+impl Snapshot for VehicleClass {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&(VehicleClass::ALL.iter().position(|class| class == self).expect("Could not find the vehicle class") as u8));
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(VehicleClass::ALL[reader.read_variant(VehicleClass::ALL.len() as u8)? as usize]) }
+}
+
+// The sprite is saved by path, and must be the one of a vehicle class
+impl Snapshot for VehicleType {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.class);
+        for value in [self.length, self.width, self.max_acceleration, self.comfortable_deceleration, self.max_speed] {
+            writer.write(&value);
+        }
+        writer.write(&self.sprite.to_string());
+        writer.write(&self.tint);
+        writer.write(&self.emission_class);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        let (class, length, width, max_acceleration, comfortable_deceleration, max_speed) = (reader.read()?, reader.read()?, reader.read()?, reader.read()?, reader.read()?, reader.read()?);
+        let sprite: String = reader.read()?;
+        let sprite = VehicleClass::ALL.iter().map(|class| class.default_type().sprite).find(|known_sprite| *known_sprite == sprite)
+            .ok_or_else(|| snapshot::invalid_data(&format!("Unknown sprite {}", sprite)))?;
+
+        Ok(Self { class, length, width, max_acceleration, comfortable_deceleration, max_speed, sprite, tint: reader.read()?, emission_class: reader.read()? })
+    }
+}
+
+impl Snapshot for Color {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        for value in [self.r, self.g, self.b, self.a] {
+            writer.write(&value);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Color::new(reader.read()?, reader.read()?, reader.read()?, reader.read()?)) }
+}
+
+impl Snapshot for FleetMix {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.shares);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { shares: reader.read()? }) }
+}
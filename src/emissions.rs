@@ -0,0 +1,194 @@
+use std::{collections::HashMap, fs::File, io::{self, BufWriter, Write}, ops::AddAssign, path::Path};
+use crate::snapshot::{Snapshot, SnapshotReader, SnapshotWriter};
+
+const GRAVITY: f32 = 9.81;
+const AIR_DENSITY: f32 = 1.2; // kg/m³
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EmissionClass {
+    PetrolCar,
+    DieselCar,
+    DieselVan,
+    DieselTruck,
+    DieselBus,
+    Motorcycle,
+    ZeroEmission,
+}
+
+// Power-based fuel and emission model: fuel = idle rate + efficiency * tractive power (Akcelik's form), and emissions
+// from the fuel burnt and the power. The default values are of the order of magnitude of Euro 5/6 vehicles, and are
+// meant to be calibrated (against HBEFA or COPERT factors for instance) for real studies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EmissionParameters {
+    pub mass: f32, // kg
+    pub rolling_resistance: f32,
+    pub drag_area: f32, // m², drag coefficient times frontal area
+    pub idle_fuel_rate: f32, // mL/s
+    pub fuel_per_energy: f32, // mL/kJ
+    pub co2_per_fuel: f32, // g/mL
+    pub idle_nox_rate: f32, // g/s
+    pub nox_per_energy: f32, // g/kJ
+    pub idle_pm_rate: f32, // g/s
+    pub pm_per_energy: f32, // g/kJ
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Emissions {
+    pub fuel: f32, // mL
+    pub co2: f32, // g
+    pub nox: f32, // g
+    pub pm: f32, // g
+}
+
+#[derive(Clone, Debug)]
+pub struct EmissionModel {
+    parameters: HashMap<EmissionClass, EmissionParameters>,
+}
+
+impl EmissionClass {
+    pub const ALL: [EmissionClass; 7] = [
+        EmissionClass::PetrolCar,
+        EmissionClass::DieselCar,
+        EmissionClass::DieselVan,
+        EmissionClass::DieselTruck,
+        EmissionClass::DieselBus,
+        EmissionClass::Motorcycle,
+        EmissionClass::ZeroEmission,
+    ];
+
+    pub fn default_parameters(&self) -> EmissionParameters {
+        const PETROL_CO2: f32 = 2.31; // g/mL
+        const DIESEL_CO2: f32 = 2.68; // g/mL
+        match self {
+            EmissionClass::PetrolCar => EmissionParameters {
+                mass: 1300., rolling_resistance: 0.012, drag_area: 0.7, idle_fuel_rate: 0.375, fuel_per_energy: 0.09, co2_per_fuel: PETROL_CO2,
+                idle_nox_rate: 0.00002, nox_per_energy: 0.0002, idle_pm_rate: 0.0000005, pm_per_energy: 0.000003,
+            },
+            EmissionClass::DieselCar => EmissionParameters {
+                mass: 1450., rolling_resistance: 0.012, drag_area: 0.7, idle_fuel_rate: 0.3, fuel_per_energy: 0.075, co2_per_fuel: DIESEL_CO2,
+                idle_nox_rate: 0.0001, nox_per_energy: 0.0014, idle_pm_rate: 0.000002, pm_per_energy: 0.000015,
+            },
+            EmissionClass::DieselVan => EmissionParameters {
+                mass: 2500., rolling_resistance: 0.012, drag_area: 1.6, idle_fuel_rate: 0.4, fuel_per_energy: 0.075, co2_per_fuel: DIESEL_CO2,
+                idle_nox_rate: 0.00015, nox_per_energy: 0.0018, idle_pm_rate: 0.000003, pm_per_energy: 0.00002,
+            },
+            EmissionClass::DieselTruck => EmissionParameters {
+                mass: 15000., rolling_resistance: 0.007, drag_area: 6., idle_fuel_rate: 0.9, fuel_per_energy: 0.065, co2_per_fuel: DIESEL_CO2,
+                idle_nox_rate: 0.0005, nox_per_energy: 0.0006, idle_pm_rate: 0.00001, pm_per_energy: 0.00001,
+            },
+            EmissionClass::DieselBus => EmissionParameters {
+                mass: 14000., rolling_resistance: 0.008, drag_area: 6.5, idle_fuel_rate: 0.9, fuel_per_energy: 0.065, co2_per_fuel: DIESEL_CO2,
+                idle_nox_rate: 0.0005, nox_per_energy: 0.0006, idle_pm_rate: 0.00001, pm_per_energy: 0.00001,
+            },
+            EmissionClass::Motorcycle => EmissionParameters {
+                mass: 250., rolling_resistance: 0.02, drag_area: 0.5, idle_fuel_rate: 0.12, fuel_per_energy: 0.1, co2_per_fuel: PETROL_CO2,
+                idle_nox_rate: 0.00002, nox_per_energy: 0.0003, idle_pm_rate: 0.0000005, pm_per_energy: 0.000005,
+            },
+            // Bicycles, nothing is burnt
+            EmissionClass::ZeroEmission => EmissionParameters {
+                mass: 90., rolling_resistance: 0.005, drag_area: 0.5, idle_fuel_rate: 0., fuel_per_energy: 0., co2_per_fuel: 0.,
+                idle_nox_rate: 0., nox_per_energy: 0., idle_pm_rate: 0., pm_per_energy: 0.,
+            },
+        }
+    }
+}
+
+impl EmissionModel {
+    pub fn new() -> Self {
+        Self { parameters: EmissionClass::ALL.iter().map(|class| (*class, class.default_parameters())).collect() }
+    }
+
+    pub fn parameters(&self, class: EmissionClass) -> &EmissionParameters { &self.parameters[&class] }
+
+    pub fn set_parameters(&mut self, class: EmissionClass, parameters: EmissionParameters) {
+        self.parameters.insert(class, parameters);
+    }
+
+    // Emissions during a step of step_size seconds, at the given speed (m/s) and acceleration (m/s²)
+    pub fn emissions(&self, class: EmissionClass, speed: f32, acceleration: f32, step_size: f32) -> Emissions {
+        let parameters = self.parameters(class);
+        let force = parameters.mass * (acceleration + GRAVITY * parameters.rolling_resistance) + 0.5 * AIR_DENSITY * parameters.drag_area * speed.powi(2);
+        // No fuel is burnt to brake, the engine idles
+        let power = (force * speed / 1000.).max(0.); // kW
+        let fuel = (parameters.idle_fuel_rate + parameters.fuel_per_energy * power) * step_size;
+        Emissions {
+            fuel,
+            co2: fuel * parameters.co2_per_fuel,
+            nox: (parameters.idle_nox_rate + parameters.nox_per_energy * power) * step_size,
+            pm: (parameters.idle_pm_rate + parameters.pm_per_energy * power) * step_size,
+        }
+    }
+}
+
+impl Default for EmissionModel {
+    fn default() -> Self { Self::new() }
+}
+
+impl AddAssign for Emissions {
+    fn add_assign(&mut self, other: Self) {
+        self.fuel += other.fuel;
+        self.co2 += other.co2;
+        self.nox += other.nox;
+        self.pm += other.pm;
+    }
+}
+
+// Emissions on each RoadSegment, indexed by RoadSegmentIdx
+pub fn write_segment_emissions(segment_emissions: &[Emissions], path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "segment,fuel_l,co2_g,nox_g,pm_g")?;
+    for (segment, emissions) in segment_emissions.iter().enumerate() {
+        writeln!(writer, "{},{:.3},{:.1},{:.3},{:.5}", segment, emissions.fuel / 1000., emissions.co2, emissions.nox, emissions.pm)?;
+    }
+
+    writer.flush()
+}
+
+impl Snapshot for EmissionClass {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&(EmissionClass::ALL.iter().position(|class| class == self).expect("Could not find the emission class") as u8));
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(EmissionClass::ALL[reader.read_variant(EmissionClass::ALL.len() as u8)? as usize]) }
+}
+
+impl Snapshot for Emissions {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        for value in [self.fuel, self.co2, self.nox, self.pm] {
+            writer.write(&value);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { fuel: reader.read()?, co2: reader.read()?, nox: reader.read()?, pm: reader.read()? }) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmissionClass, EmissionModel};
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!((value - expected).abs() <= expected.abs() * 1e-4, "{} instead of {}", value, expected);
+    }
+
+    #[test]
+    fn emissions_follow_the_tractive_power_of_the_step() {
+        let model = EmissionModel::new();
+        // 1300 × (0.5 + 9.81 × 0.012) + 0.5 × 1.2 × 0.7 × 10² = 845.036 N, so 8.45036 kW at 10 m/s
+        let emissions = model.emissions(EmissionClass::PetrolCar, 10., 0.5, 0.1);
+        assert_close(emissions.fuel, (0.375 + 0.09 * 8.45036) * 0.1);
+        assert_close(emissions.co2, emissions.fuel * 2.31);
+        assert_close(emissions.nox, (0.00002 + 0.0002 * 8.45036) * 0.1);
+        assert_close(emissions.pm, (0.0000005 + 0.000003 * 8.45036) * 0.1);
+        // Proportional to the step size
+        assert_close(model.emissions(EmissionClass::PetrolCar, 10., 0.5, 0.2).fuel, 2. * emissions.fuel);
+    }
+
+    #[test]
+    fn braking_and_bicycles_burn_no_more_than_idling() {
+        let model = EmissionModel::new();
+        let braking = model.emissions(EmissionClass::DieselTruck, 15., -2., 0.5);
+        assert_close(braking.fuel, 0.9 * 0.5);
+        assert_close(braking.nox, 0.0005 * 0.5);
+        assert_eq!(model.emissions(EmissionClass::ZeroEmission, 5., 1., 1.), Default::default());
+    }
+}
@@ -1,27 +1,69 @@
-use crate::road;
-use macroquad::{color::*, prelude::rand};
+pub mod editor;
+pub mod heatmap;
+pub mod inspector;
+pub mod playback;
+pub mod replayer;
 
-const CAR_SIZE: (f32, f32) = (28.8, 14.28);
+use std::collections::HashMap;
+use crate::{agent::vehicle::{VehicleClass, VehicleType}, road};
+use macroquad::color::*;
+
+// Shared with the offscreen renderer
+pub const MIN_VEHICLE_SIZE: f32 = 2.; // px, so that vehicles stay visible when zoomed out
+pub const ROAD_THICKNESS: f32 = 3.; // px
+pub const SIGN_SIZE: f32 = 10.; // px
 const DEFAULT_SCALE: f32 = 10.; // px / meter
 const ZOOMING_SPEED: f32 = 2.;
+const PANEL_WIDTH: f32 = 300.; // px
+const LEGEND_WIDTH: f32 = 200.; // px
+const LEGEND_STEPS: usize = 20;
+const TIMELINE_MARGIN: f32 = 20.; // px, between the timeline and the edges of the window
+const TIMELINE_HEIGHT: f32 = 8.; // px
+// Dimmer than closed segments, which stay visible under the overlays
+const BACKGROUND_ROAD_COLOR: Color = Color::new(0.15, 0.15, 0.15, 1.);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum RoadSegmentStyle {
+    Open,
+    Closed,
+    WorkZone,
+    // With a dedicated bike lane
+    BikeLane,
+    // Picked in the editor
+    Selected,
+    // Under an overlay, where it has nothing to show
+    Background,
+    // From 0 (free flowing, green) to 1 (congested, red)
+    Heat(f32),
+}
 
 pub struct Window {
     zoom: f32,
     center: (f32, f32),
-    car_texture: macroquad::texture::Texture2D,
+    // Indexed by sprite path
+    vehicle_textures: HashMap<&'static str, macroquad::texture::Texture2D>,
+    pan_button: macroquad::input::MouseButton,
 }
 
 impl Window {
     pub async fn new() -> Self {
         macroquad::window::request_new_screen_size(800., 800.);
+        let mut vehicle_textures = HashMap::new();
+        for class in VehicleClass::ALL {
+            let sprite = class.default_type().sprite;
+            if !vehicle_textures.contains_key(sprite) {
+                vehicle_textures.insert(sprite, macroquad::texture::load_texture(sprite).await.expect("Could not load vehicle texture"));
+            }
+        }
         Self {
             zoom: 0.,
             center: (0., 0.),
-            car_texture: macroquad::texture::load_texture("resources/textures/car.png").await.expect("Could not load car texture"),
+            vehicle_textures,
+            pan_button: macroquad::input::MouseButton::Left,
         }
     }
 
-    pub fn draw_road_segment(&self, start: (f32, f32), end: (f32, f32)) {
+    pub fn draw_road_segment(&self, start: (f32, f32), end: (f32, f32), style: RoadSegmentStyle) {
         let window_start = (self.x_to_pixel(start.0), self.y_to_pixel(start.1));
         let window_end = (self.x_to_pixel(end.0), self.y_to_pixel(end.1));
         if (window_start.0 < 0. && window_end.0 < 0.)
@@ -30,37 +72,157 @@ impl Window {
             || (window_start.1 >= macroquad::window::screen_height() && window_end.1 >= macroquad::window::screen_height()) {
             return;
         }
-        macroquad::shapes::draw_line(window_start.0, window_start.1, window_end.0, window_end.1, 3., RED);
+        macroquad::shapes::draw_line(window_start.0, window_start.1, window_end.0, window_end.1, ROAD_THICKNESS, road_segment_color(style));
+    }
+
+    pub fn draw_blockage(&self, start: (f32, f32), end: (f32, f32)) {
+        macroquad::shapes::draw_line(self.x_to_pixel(start.0), self.y_to_pixel(start.1), self.x_to_pixel(end.0), self.y_to_pixel(end.1), 7., MAGENTA);
     }
 
     pub fn draw_sign(&self, (x, y): (f32, f32), sign_type: &road::SignType) {
         let window_position = (self.x_to_pixel(x), self.y_to_pixel(y));
-        if window_position.0 < -5.
-            || window_position.0 - 5. >= macroquad::window::screen_width()
-            || window_position.1 < -5.
-            || window_position.1 - 5. >= macroquad::window::screen_height() {
+        if window_position.0 < -SIGN_SIZE / 2.
+            || window_position.0 - SIGN_SIZE / 2. >= macroquad::window::screen_width()
+            || window_position.1 < -SIGN_SIZE / 2.
+            || window_position.1 - SIGN_SIZE / 2. >= macroquad::window::screen_height() {
             return;
         }
-        let color = match sign_type {
-            road::SignType::SpeedLimit => { ORANGE }
-            road::SignType::EndSpeedLimit => { BLUE }
+        macroquad::shapes::draw_rectangle(window_position.0 - SIGN_SIZE / 2., window_position.1 - SIGN_SIZE / 2., SIGN_SIZE, SIGN_SIZE, sign_color(sign_type));
+    }
+
+    pub fn draw_bus_stop(&self, (x, y): (f32, f32), in_bay: bool) {
+        let color = if in_bay { SKYBLUE } else { GREEN };
+        macroquad::shapes::draw_rectangle(self.x_to_pixel(x) - 4., self.y_to_pixel(y) - 4., 8., 8., color);
+    }
+
+    // signal is None for zebra crossings, and whether pedestrians may walk otherwise
+    pub fn draw_crossing(&self, (x, y): (f32, f32), signal: Option<bool>) {
+        let color = match signal {
+            None => WHITE,
+            Some(true) => GREEN,
+            Some(false) => RED,
         };
-        macroquad::shapes::draw_rectangle(self.x_to_pixel(x) - 5., self.y_to_pixel(y) - 5., 10., 10., color);
+        macroquad::shapes::draw_rectangle_lines(self.x_to_pixel(x) - 8., self.y_to_pixel(y) - 8., 16., 16., 2., color);
+    }
+
+    // Along the road, between the first and last spaces
+    pub fn draw_parking_spaces(&self, start: (f32, f32), end: (f32, f32), full: bool) {
+        let color = if full { ORANGE } else { BLUE };
+        macroquad::shapes::draw_line(self.x_to_pixel(start.0), self.y_to_pixel(start.1), self.x_to_pixel(end.0), self.y_to_pixel(end.1), 5., color);
+    }
+
+    pub fn draw_garage(&self, (x, y): (f32, f32), full: bool) {
+        let color = if full { ORANGE } else { BLUE };
+        macroquad::shapes::draw_rectangle_lines(self.x_to_pixel(x) - 6., self.y_to_pixel(y) - 6., 12., 12., 3., color);
     }
 
-    pub fn draw_car(&self, (x, y): (f32, f32), speed: f32) {
+    pub fn draw_node(&self, (x, y): (f32, f32), selected: bool) {
+        macroquad::shapes::draw_circle(self.x_to_pixel(x), self.y_to_pixel(y), 5., if selected { WHITE } else { GRAY });
+    }
+
+    pub fn draw_visual_keypoint(&self, (x, y): (f32, f32), selected: bool) {
+        macroquad::shapes::draw_circle_lines(self.x_to_pixel(x), self.y_to_pixel(y), 3., 1.5, if selected { WHITE } else { GRAY });
+    }
+
+    // One line of text per element, from the top left corner of the window
+    pub fn draw_text_lines(&self, lines: &[String]) {
+        for (i, line) in lines.iter().enumerate() {
+            macroquad::text::draw_text(line, 5., 20. + 20. * i as f32, 20., WHITE);
+        }
+    }
+
+    pub fn draw_pedestrian(&self, (x, y): (f32, f32)) {
+        macroquad::shapes::draw_circle(self.x_to_pixel(x), self.y_to_pixel(y), 3., PINK);
+    }
+
+    // xy is the center of the vehicle, heading counterclockwise from the x axis in radians
+    pub fn draw_car(&self, (x, y): (f32, f32), heading: f32, vehicle_type: &VehicleType) {
+        let size = ((vehicle_type.length * self.scale()).max(MIN_VEHICLE_SIZE), (vehicle_type.width * self.scale()).max(MIN_VEHICLE_SIZE));
         macroquad::texture::draw_texture_ex(
-            &self.car_texture,
-            self.x_to_pixel(x) - CAR_SIZE.0 / 2.,
-            self.y_to_pixel(y) - CAR_SIZE.1 / 2.,
-            WHITE,
-            macroquad::texture::DrawTextureParams { dest_size: Some(macroquad::math::Vec2::new(CAR_SIZE.0, CAR_SIZE.1)), rotation: rand::rand() as f32, ..Default::default() }
+            &self.vehicle_textures[vehicle_type.sprite],
+            self.x_to_pixel(x) - size.0 / 2.,
+            self.y_to_pixel(y) - size.1 / 2.,
+            vehicle_type.tint,
+            macroquad::texture::DrawTextureParams { dest_size: Some(macroquad::math::Vec2::new(size.0, size.1)), rotation: -heading, ..Default::default() }
         );
-        if speed != -1. {
-            macroquad::text::draw_text(&format!("Speed = {}km/h", speed * 3.6), 5., 100., 30., WHITE);
+    }
+
+    // Centered at the top of the window
+    pub fn draw_banner(&self, text: &str) {
+        let width = macroquad::text::measure_text(text, None, 24, 1.).width;
+        macroquad::text::draw_text(text, (macroquad::window::screen_width() - width) / 2., 24., 24., WHITE);
+    }
+
+    // Gradient of the Heat style in the bottom left corner of the window
+    pub fn draw_heat_legend(&self, title: &str, low_label: &str, high_label: &str) {
+        let (x, y) = (10., macroquad::window::screen_height() - 40.);
+        macroquad::text::draw_text(title, x, y - 8., 20., WHITE);
+        for i in 0..LEGEND_STEPS {
+            let level = i as f32 / (LEGEND_STEPS - 1) as f32;
+            macroquad::shapes::draw_rectangle(x + i as f32 * LEGEND_WIDTH / LEGEND_STEPS as f32, y, LEGEND_WIDTH / LEGEND_STEPS as f32 + 1., 10., heat_color(level));
         }
+        macroquad::text::draw_text(low_label, x, y + 28., 18., WHITE);
+        let high_label_width = macroquad::text::measure_text(high_label, None, 18, 1.).width;
+        macroquad::text::draw_text(high_label, x + LEGEND_WIDTH - high_label_width, y + 28., 18., WHITE);
     }
 
+    // Ring around the inspected element, radius in m
+    pub fn draw_highlight(&self, (x, y): (f32, f32), radius: f32) {
+        macroquad::shapes::draw_circle_lines(self.x_to_pixel(x), self.y_to_pixel(y), radius * self.scale(), 2., WHITE);
+    }
+
+    // Box on the right of the window, with one line of text per element
+    pub fn draw_panel(&self, lines: &[String]) {
+        let x = macroquad::window::screen_width() - PANEL_WIDTH;
+        macroquad::shapes::draw_rectangle(x, 0., PANEL_WIDTH, 10. + 20. * lines.len() as f32, Color::new(0., 0., 0., 0.7));
+        for (i, line) in lines.iter().enumerate() {
+            macroquad::text::draw_text(line, x + 5., 20. + 20. * i as f32, 18., WHITE);
+        }
+    }
+
+    // Bar along the bottom of the window, filled up to progress (0 to 1), with labels at both ends
+    pub fn draw_timeline(&self, progress: f32, start_label: &str, end_label: &str) {
+        let (x, y, width) = self.timeline_geometry();
+        macroquad::shapes::draw_rectangle(x, y, width, TIMELINE_HEIGHT, DARKGRAY);
+        macroquad::shapes::draw_rectangle(x, y, width * progress.clamp(0., 1.), TIMELINE_HEIGHT, LIGHTGRAY);
+        macroquad::shapes::draw_circle(x + width * progress.clamp(0., 1.), y + TIMELINE_HEIGHT / 2., TIMELINE_HEIGHT, WHITE);
+        macroquad::text::draw_text(start_label, x, y - 6., 18., WHITE);
+        let end_label_width = macroquad::text::measure_text(end_label, None, 18, 1.).width;
+        macroquad::text::draw_text(end_label, x + width - end_label_width, y - 6., 18., WHITE);
+    }
+
+    pub fn is_mouse_over_timeline(&self) -> bool {
+        let (x, y, width) = self.timeline_geometry();
+        let (px, py) = macroquad::input::mouse_position();
+        px >= x - TIMELINE_HEIGHT && px <= x + width + TIMELINE_HEIGHT && (py - y - TIMELINE_HEIGHT / 2.).abs() <= TIMELINE_HEIGHT
+    }
+
+    // Progress (0 to 1) of the timeline at the horizontal position of the mouse
+    pub fn timeline_progress(&self) -> f32 {
+        let (x, _y, width) = self.timeline_geometry();
+        ((macroquad::input::mouse_position().0 - x) / width).clamp(0., 1.)
+    }
+
+    // Left, top and width of the timeline, in px
+    fn timeline_geometry(&self) -> (f32, f32, f32) {
+        let (width, height) = (macroquad::window::screen_width(), macroquad::window::screen_height());
+        (TIMELINE_MARGIN, height - TIMELINE_MARGIN - TIMELINE_HEIGHT, width - 2. * TIMELINE_MARGIN)
+    }
+
+    // Left by default, the editor uses it to edit
+    pub fn set_pan_button(&mut self, button: macroquad::input::MouseButton) {
+        self.pan_button = button;
+    }
+
+    // Position of the mouse in the world
+    pub fn mouse_xy(&self) -> (f32, f32) {
+        let (px, py) = macroquad::input::mouse_position();
+        (self.px_to_x(px), self.px_to_y(py))
+    }
+
+    // Distance in the world covered by the given number of pixels
+    pub fn pixels_to_distance(&self, pixels: f32) -> f32 { pixels / self.scale() }
+
     pub fn update(&mut self) {
         let frame_zoom = macroquad::input::mouse_wheel().1;
         if frame_zoom != 0. {
@@ -69,7 +231,7 @@ impl Window {
             self.center.1 -= (self.center.1 - self.px_to_y(mouse_position.1)) * (1. - 1. / ZOOMING_SPEED.powf(frame_zoom));
             self.zoom += frame_zoom;
         }
-        if macroquad::input::is_mouse_button_down(macroquad::input::MouseButton::Left) {
+        if macroquad::input::is_mouse_button_down(self.pan_button) {
             self.center.0 += macroquad::input::mouse_delta_position().x / 2. * macroquad::window::screen_width() / self.scale();
             self.center.1 -= macroquad::input::mouse_delta_position().y / 2. * macroquad::window::screen_height() / self.scale();
         }
@@ -80,4 +242,29 @@ impl Window {
     fn px_to_x(&self, px: f32) -> f32 { (px - macroquad::window::screen_width() / 2.) / self.scale() + self.center.0 }
     fn px_to_y(&self, px: f32) -> f32 { (macroquad::window::screen_height() / 2. - px) / self.scale() + self.center.1 }
     fn scale(&self) -> f32 { DEFAULT_SCALE * ZOOMING_SPEED.powf(self.zoom) }
-}
\ No newline at end of file
+}
+
+pub fn road_segment_color(style: RoadSegmentStyle) -> Color {
+    match style {
+        RoadSegmentStyle::Open => { RED }
+        RoadSegmentStyle::Closed => { DARKGRAY }
+        RoadSegmentStyle::WorkZone => { YELLOW }
+        RoadSegmentStyle::BikeLane => { LIME }
+        RoadSegmentStyle::Selected => { WHITE }
+        RoadSegmentStyle::Background => { BACKGROUND_ROAD_COLOR }
+        RoadSegmentStyle::Heat(level) => { heat_color(level) }
+    }
+}
+
+pub fn sign_color(sign_type: &road::SignType) -> Color {
+    match sign_type {
+        road::SignType::SpeedLimit => { ORANGE }
+        road::SignType::EndSpeedLimit => { BLUE }
+    }
+}
+
+// Green, through yellow, to red
+fn heat_color(level: f32) -> Color {
+    let level = level.clamp(0., 1.);
+    Color::new((2. * level).min(1.), (2. - 2. * level).min(1.), 0., 1.)
+}
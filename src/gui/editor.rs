@@ -0,0 +1,336 @@
+use std::{io, path::{Path, PathBuf}};
+use macroquad::input::{self, KeyCode, MouseButton};
+use crate::{gui::{RoadSegmentStyle, Window}, road::{self, Roads}};
+
+const PICKING_DISTANCE: f32 = 10.; // px, elements closer than this to the mouse are picked
+const MAX_UNDO_STEPS: usize = 100;
+const DEFAULT_SPEED_LIMIT: f32 = 50. / 3.6;
+const SPEED_LIMIT_STEP: f32 = 10. / 3.6;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Tool {
+    // Click to place, drag to move
+    Node,
+    // Drag from a RoadNode to another one, holding shift for both ways. Click on a segment to select it.
+    Segment,
+    // Click on a segment to add, drag to move
+    VisualKeypoint,
+    // Click on a segment to add a speed limit, then up and down to change it, T to switch its type
+    Sign,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Selection {
+    Node(road::RoadNodeIdx),
+    Segment(road::RoadSegmentIdx),
+    VisualKeypoint(road::RoadSegmentIdx, usize),
+    Sign(road::RoadSegmentIdx, usize),
+}
+
+// What the mouse is dragging, with the roads from before to undo moves
+enum Drag {
+    Node(road::RoadNodeIdx, Roads),
+    VisualKeypoint(road::RoadSegmentIdx, usize, Roads),
+    NewSegment(road::RoadNodeIdx),
+}
+
+// Edits a map file with the mouse and keyboard, the window being panned with another button than the left one
+pub struct Editor {
+    pub roads: Roads,
+    path: PathBuf,
+    tool: Tool,
+    selection: Option<Selection>,
+    drag: Option<Drag>,
+    undo_stack: Vec<Roads>,
+    redo_stack: Vec<Roads>,
+    // Changed since the last save
+    modified: bool,
+    message: String,
+}
+
+impl Editor {
+    // Starts from an empty map if there is no file at path yet
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let roads = if path.exists() { Roads::load(&path)? } else { Roads::empty() };
+        Ok(Self { roads, path, tool: Tool::Node, selection: None, drag: None, undo_stack: Vec::new(), redo_stack: Vec::new(), modified: false, message: String::new() })
+    }
+
+    pub fn tool(&self) -> Tool { self.tool }
+
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+        self.selection = None;
+    }
+
+    pub fn selection(&self) -> Option<Selection> { self.selection }
+
+    pub fn is_modified(&self) -> bool { self.modified }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        self.roads.save(&self.path)?;
+        self.modified = false;
+
+        Ok(())
+    }
+
+    // Returns false if there is nothing to undo
+    pub fn undo(&mut self) -> bool {
+        let Some(roads) = self.undo_stack.pop() else {
+            return false;
+        };
+        self.redo_stack.push(std::mem::replace(&mut self.roads, roads));
+        self.selection = None;
+        self.modified = true;
+
+        true
+    }
+
+    // Returns false if there is nothing to redo
+    pub fn redo(&mut self) -> bool {
+        let Some(roads) = self.redo_stack.pop() else {
+            return false;
+        };
+        self.undo_stack.push(std::mem::replace(&mut self.roads, roads));
+        self.selection = None;
+        self.modified = true;
+
+        true
+    }
+
+    pub fn delete_selection(&mut self) {
+        let Some(selection) = self.selection.take() else {
+            return;
+        };
+        self.checkpoint();
+        match selection {
+            Selection::Node(node) => self.roads.remove_node(node),
+            Selection::Segment(segment) => self.roads.remove_segment(segment),
+            Selection::VisualKeypoint(segment, visual_keypoint) => self.roads.remove_visual_keypoint(segment, visual_keypoint),
+            Selection::Sign(segment, sign) => self.roads.remove_sign(segment, sign),
+        }
+    }
+
+    pub fn update(&mut self, window: &Window) {
+        let control = input::is_key_down(KeyCode::LeftControl) || input::is_key_down(KeyCode::RightControl);
+        let shift = input::is_key_down(KeyCode::LeftShift) || input::is_key_down(KeyCode::RightShift);
+        if control {
+            if input::is_key_pressed(KeyCode::Z) {
+                if shift { self.redo() } else { self.undo() };
+            }
+            if input::is_key_pressed(KeyCode::Y) {
+                self.redo();
+            }
+            if input::is_key_pressed(KeyCode::S) {
+                self.message = match self.save() {
+                    Ok(()) => format!("Saved to {}", self.path.display()),
+                    Err(error) => format!("Could not save to {}: {}", self.path.display(), error),
+                };
+            }
+        } else {
+            for (key, tool) in [(KeyCode::N, Tool::Node), (KeyCode::S, Tool::Segment), (KeyCode::K, Tool::VisualKeypoint), (KeyCode::L, Tool::Sign)] {
+                if input::is_key_pressed(key) {
+                    self.set_tool(tool);
+                }
+            }
+            if input::is_key_pressed(KeyCode::Delete) || input::is_key_pressed(KeyCode::Backspace) {
+                self.delete_selection();
+            }
+            if input::is_key_pressed(KeyCode::Up) {
+                self.change_speed_limit(SPEED_LIMIT_STEP);
+            }
+            if input::is_key_pressed(KeyCode::Down) {
+                self.change_speed_limit(-SPEED_LIMIT_STEP);
+            }
+            if input::is_key_pressed(KeyCode::T) {
+                self.switch_sign_type();
+            }
+        }
+        let xy = window.mouse_xy();
+        let picking_distance = window.pixels_to_distance(PICKING_DISTANCE);
+        if input::is_mouse_button_pressed(MouseButton::Left) {
+            self.press(xy, picking_distance);
+        } else if input::is_mouse_button_down(MouseButton::Left) {
+            self.drag_to(xy);
+        }
+        if input::is_mouse_button_released(MouseButton::Left) {
+            self.release(xy, picking_distance, shift);
+        }
+    }
+
+    pub fn render(&self, window: &Window) {
+        self.roads.render(window);
+        if let Some(Selection::Segment(segment)) = self.selection {
+            for piece in self.roads.segment_polyline(segment).windows(2) {
+                window.draw_road_segment(piece[0], piece[1], RoadSegmentStyle::Selected);
+            }
+        }
+        for segment in (0..self.roads.segment_count()).map(road::RoadSegmentIdx) {
+            for (i, xy) in self.roads.visual_keypoints(segment).into_iter().enumerate() {
+                window.draw_visual_keypoint(xy, self.selection == Some(Selection::VisualKeypoint(segment, i)));
+            }
+        }
+        for node in (0..self.roads.node_count()).map(road::RoadNodeIdx) {
+            window.draw_node(self.roads.node_xy(node), self.selection == Some(Selection::Node(node)));
+        }
+        if let Some(Drag::NewSegment(from)) = &self.drag {
+            window.draw_road_segment(self.roads.node_xy(*from), window.mouse_xy(), RoadSegmentStyle::Selected);
+        }
+        let mut lines = vec![
+            format!("{}{} - {:?} tool", self.path.display(), if self.modified { " (modified)" } else { "" }, self.tool),
+            "N: nodes, S: segments (shift for both ways), K: keypoints, L: signs (up/down: value, T: type)".to_string(),
+            "Delete: remove, Ctrl+Z/Ctrl+Y: undo/redo, Ctrl+S: save, right button: pan".to_string(),
+        ];
+        if let Some(Selection::Sign(segment, sign)) = self.selection {
+            let sign = &self.roads.signs(segment)[sign];
+            window.draw_visual_keypoint(self.roads.get_position_xy(&sign.position), true);
+            lines.push(match sign.sign_type {
+                road::SignType::SpeedLimit => format!("Speed limit of {:.0} km/h", sign.value * 3.6),
+                road::SignType::EndSpeedLimit => "End of speed limit".to_string(),
+            });
+        }
+        lines.push(self.message.clone());
+        window.draw_text_lines(&lines);
+    }
+
+    // Keeps the current roads to undo the edit about to be made
+    fn checkpoint(&mut self) {
+        self.push_undo(self.roads.clone());
+    }
+
+    fn push_undo(&mut self, roads: Roads) {
+        self.undo_stack.push(roads);
+        if self.undo_stack.len() > MAX_UNDO_STEPS {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+        self.modified = true;
+    }
+
+    fn press(&mut self, xy: (f32, f32), picking_distance: f32) {
+        let node = self.roads.nearest_node(xy, picking_distance);
+        let point = self.roads.nearest_point(xy, picking_distance);
+        match self.tool {
+            Tool::Node => match node {
+                Some(node) => {
+                    self.selection = Some(Selection::Node(node));
+                    self.drag = Some(Drag::Node(node, self.roads.clone()));
+                },
+                None => {
+                    self.checkpoint();
+                    self.selection = Some(Selection::Node(self.roads.add_node(xy)));
+                },
+            },
+            Tool::Segment => match node {
+                Some(node) => self.drag = Some(Drag::NewSegment(node)),
+                None => self.selection = point.map(|point| Selection::Segment(point.road_segment())),
+            },
+            Tool::VisualKeypoint => {
+                if let Some((segment, visual_keypoint)) = self.nearest_visual_keypoint(xy, picking_distance) {
+                    self.selection = Some(Selection::VisualKeypoint(segment, visual_keypoint));
+                    self.drag = Some(Drag::VisualKeypoint(segment, visual_keypoint, self.roads.clone()));
+                } else if let Some(point) = point {
+                    let before = self.roads.clone();
+                    let visual_keypoint = self.roads.insert_visual_keypoint(point.road_segment(), point.position(), xy);
+                    self.selection = Some(Selection::VisualKeypoint(point.road_segment(), visual_keypoint));
+                    self.drag = Some(Drag::VisualKeypoint(point.road_segment(), visual_keypoint, before));
+                } else {
+                    self.selection = None;
+                }
+            },
+            Tool::Sign => {
+                if let Some((segment, sign)) = self.nearest_sign(xy, picking_distance) {
+                    self.selection = Some(Selection::Sign(segment, sign));
+                } else if let Some(point) = point {
+                    self.checkpoint();
+                    self.roads.add_sign(road::Sign { sign_type: road::SignType::SpeedLimit, value: DEFAULT_SPEED_LIMIT, position: point });
+                    self.selection = Some(Selection::Sign(point.road_segment(), self.roads.signs(point.road_segment()).len() - 1));
+                } else {
+                    self.selection = None;
+                }
+            },
+        }
+    }
+
+    fn drag_to(&mut self, xy: (f32, f32)) {
+        match &self.drag {
+            Some(Drag::Node(node, _before)) => self.roads.move_node(*node, xy),
+            Some(Drag::VisualKeypoint(segment, visual_keypoint, _before)) => self.roads.move_visual_keypoint(*segment, *visual_keypoint, xy),
+            Some(Drag::NewSegment(_)) | None => {},
+        }
+    }
+
+    fn release(&mut self, xy: (f32, f32), picking_distance: f32, both_ways: bool) {
+        match self.drag.take() {
+            // A click without a move is only a selection
+            Some(Drag::Node(node, before)) if before.node_xy(node) != self.roads.node_xy(node) => self.push_undo(before),
+            Some(Drag::Node(..)) => {},
+            Some(Drag::VisualKeypoint(_segment, _visual_keypoint, before)) => self.push_undo(before),
+            Some(Drag::NewSegment(from)) => {
+                let Some(to) = self.roads.nearest_node(xy, picking_distance).filter(|to| *to != from) else {
+                    return;
+                };
+                let ways: Vec<(road::RoadNodeIdx, road::RoadNodeIdx)> = std::iter::once((from, to)).chain(both_ways.then_some((to, from)))
+                    .filter(|(from, to)| self.roads.segment_between(*from, *to).is_none())
+                    .collect();
+                if ways.is_empty() {
+                    return;
+                }
+                self.checkpoint();
+                for (from, to) in ways {
+                    self.selection = Some(Selection::Segment(self.roads.add_segment(from, to, &[])));
+                }
+            },
+            None => {},
+        }
+    }
+
+    fn change_speed_limit(&mut self, change: f32) {
+        let Some(Selection::Sign(segment, sign)) = self.selection else {
+            return;
+        };
+        let sign_value = &self.roads.signs(segment)[sign];
+        if sign_value.sign_type != road::SignType::SpeedLimit {
+            return;
+        }
+        let value = (sign_value.value + change).max(SPEED_LIMIT_STEP);
+        self.checkpoint();
+        self.roads.set_sign_value(segment, sign, value);
+    }
+
+    fn switch_sign_type(&mut self) {
+        let Some(Selection::Sign(segment, sign)) = self.selection else {
+            return;
+        };
+        self.checkpoint();
+        match self.roads.signs(segment)[sign].sign_type {
+            road::SignType::SpeedLimit => {
+                self.roads.set_sign_type(segment, sign, road::SignType::EndSpeedLimit);
+            },
+            road::SignType::EndSpeedLimit => {
+                self.roads.set_sign_type(segment, sign, road::SignType::SpeedLimit);
+                self.roads.set_sign_value(segment, sign, DEFAULT_SPEED_LIMIT);
+            },
+        }
+    }
+
+    fn nearest_visual_keypoint(&self, xy: (f32, f32), max_distance: f32) -> Option<(road::RoadSegmentIdx, usize)> {
+        nearest((0..self.roads.segment_count()).map(road::RoadSegmentIdx).flat_map(|segment| {
+            self.roads.visual_keypoints(segment).into_iter().enumerate().map(move |(i, keypoint_xy)| ((segment, i), keypoint_xy))
+        }), xy, max_distance)
+    }
+
+    fn nearest_sign(&self, xy: (f32, f32), max_distance: f32) -> Option<(road::RoadSegmentIdx, usize)> {
+        nearest((0..self.roads.segment_count()).map(road::RoadSegmentIdx).flat_map(|segment| {
+            self.roads.signs(segment).iter().enumerate().map(move |(i, sign)| ((segment, i), self.roads.get_position_xy(&sign.position)))
+        }), xy, max_distance)
+    }
+}
+
+// Element whose position is the closest to xy, within max_distance
+fn nearest<T>(elements: impl Iterator<Item = (T, (f32, f32))>, (x, y): (f32, f32), max_distance: f32) -> Option<T> {
+    elements.map(|(element, (element_x, element_y))| (element, (element_x - x).hypot(element_y - y)))
+        .filter(|(_element, distance)| *distance <= max_distance)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(element, _distance)| element)
+}
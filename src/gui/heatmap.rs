@@ -0,0 +1,126 @@
+use macroquad::input::{self, KeyCode};
+use crate::{gui::{RoadSegmentStyle, Window}, road, simulation::SimulationData};
+
+const SECTION_LENGTH: f32 = 10.; // m, the speeds are averaged over sections of the segments
+const JAM_DENSITY: f32 = 150.; // veh/km, shown in red
+const QUEUED_SPEED: f32 = 1.; // m/s, vehicles slower than this are queuing...
+const QUEUE_GAP: f32 = 5.; // m, ...if this close to the one in front
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum HeatmapMode {
+    #[default]
+    Off,
+    // Mean speed relative to the desired speed of the drivers, by section
+    Speed,
+    // Vehicles per km, by segment
+    Density,
+    // Stopped vehicles, from the front-most one
+    Queue,
+}
+
+// Colours the roads by how congested they are, the H key switching between the modes
+#[derive(Default)]
+pub struct Heatmap {
+    mode: HeatmapMode,
+}
+
+impl HeatmapMode {
+    pub fn next(self) -> Self {
+        match self {
+            HeatmapMode::Off => HeatmapMode::Speed,
+            HeatmapMode::Speed => HeatmapMode::Density,
+            HeatmapMode::Density => HeatmapMode::Queue,
+            HeatmapMode::Queue => HeatmapMode::Off,
+        }
+    }
+}
+
+impl Heatmap {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn mode(&self) -> HeatmapMode { self.mode }
+
+    pub fn set_mode(&mut self, mode: HeatmapMode) {
+        self.mode = mode;
+    }
+
+    pub fn update(&mut self) {
+        if input::is_key_pressed(KeyCode::H) {
+            self.mode = self.mode.next();
+        }
+    }
+
+    // Over the roads, before the vehicles
+    pub fn render(&self, window: &Window, sd: &SimulationData) {
+        if self.mode == HeatmapMode::Off {
+            return;
+        }
+        for segment in (0..sd.roads.segment_count()).map(road::RoadSegmentIdx) {
+            let style = if sd.roads.is_closed(segment) { sd.roads.segment_style(segment) } else { RoadSegmentStyle::Background };
+            draw_section(window, &sd.roads, segment, 0., sd.roads.segment_length(segment), style);
+            match self.mode {
+                HeatmapMode::Speed => render_speeds(window, sd, segment),
+                HeatmapMode::Density => render_density(window, sd, segment),
+                HeatmapMode::Queue => render_queue(window, sd, segment),
+                HeatmapMode::Off => {},
+            }
+        }
+        match self.mode {
+            HeatmapMode::Speed => window.draw_heat_legend("Speed (H to switch)", "desired", "stopped"),
+            HeatmapMode::Density => window.draw_heat_legend("Density (H to switch)", "0 veh/km", &format!("{:.0} veh/km", JAM_DENSITY)),
+            HeatmapMode::Queue => window.draw_heat_legend("Queue (H to switch)", "short", "whole segment"),
+            HeatmapMode::Off => {},
+        }
+    }
+}
+
+fn draw_section(window: &Window, roads: &road::Roads, segment: road::RoadSegmentIdx, start: f32, end: f32, style: RoadSegmentStyle) {
+    for piece in roads.section_polyline(segment, start, end).windows(2) {
+        window.draw_road_segment(piece[0], piece[1], style);
+    }
+}
+
+fn render_speeds(window: &Window, sd: &SimulationData, segment: road::RoadSegmentIdx) {
+    let length = sd.roads.segment_length(segment);
+    let section_count = (length / SECTION_LENGTH).ceil().max(1.) as usize;
+    // Sum of the relative speeds and vehicle count, by section
+    let mut sections = vec![(0., 0); section_count];
+    for car in sd.cars.iter().filter(|car| car.position().road_segment() == segment) {
+        let center = (car.position().position() - car.length() / 2.).max(0.);
+        let section = &mut sections[((center / SECTION_LENGTH) as usize).min(section_count - 1)];
+        section.0 += (car.speed() / car.desired_speed().max(f32::EPSILON)).min(1.);
+        section.1 += 1;
+    }
+    for (i, (relative_speed, count)) in sections.into_iter().enumerate().filter(|(_i, (_relative_speed, count))| *count > 0) {
+        let start = i as f32 * SECTION_LENGTH;
+        draw_section(window, &sd.roads, segment, start, (start + SECTION_LENGTH).min(length), RoadSegmentStyle::Heat(1. - relative_speed / count as f32));
+    }
+}
+
+fn render_density(window: &Window, sd: &SimulationData, segment: road::RoadSegmentIdx) {
+    let length = sd.roads.segment_length(segment);
+    let count = sd.cars.iter().filter(|car| car.position().road_segment() == segment).count();
+    if count > 0 {
+        let density = count as f32 / length * 1000.;
+        draw_section(window, &sd.roads, segment, 0., length, RoadSegmentStyle::Heat(density / JAM_DENSITY));
+    }
+}
+
+fn render_queue(window: &Window, sd: &SimulationData, segment: road::RoadSegmentIdx) {
+    let mut cars: Vec<_> = sd.cars.iter().filter(|car| car.position().road_segment() == segment).collect();
+    cars.sort_by(|a, b| b.position().position().total_cmp(&a.position().position()));
+    let mut queue: Option<(f32, f32)> = None;
+    for car in cars {
+        let (front, rear) = (car.position().position(), (car.position().position() - car.length()).max(0.));
+        match queue {
+            None if car.speed() < QUEUED_SPEED => queue = Some((front, rear)),
+            None => {},
+            Some((queue_front, queue_rear)) if car.speed() < QUEUED_SPEED && queue_rear - front <= QUEUE_GAP => queue = Some((queue_front, rear)),
+            Some(_) => break,
+        }
+    }
+    if let Some((front, rear)) = queue {
+        let length = sd.roads.segment_length(segment);
+        draw_section(window, &sd.roads, segment, rear, front, RoadSegmentStyle::Heat(0.5 + 0.5 * (front - rear) / length));
+    }
+}
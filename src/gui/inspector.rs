@@ -0,0 +1,138 @@
+use macroquad::input::{self, MouseButton};
+use crate::{agent::car::Car, gui::{RoadSegmentStyle, Window}, road, simulation::SimulationData};
+
+const PICKING_DISTANCE: f32 = 10.; // px, elements closer than this to the mouse are picked
+const CLICK_DISTANCE: f32 = 5.; // px, beyond this the mouse was dragged to pan, not clicked
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Inspected {
+    // By id
+    Car(usize),
+    Segment(road::RoadSegmentIdx),
+}
+
+// Shows the details of the car, or else the segment, clicked on
+#[derive(Default)]
+pub struct Inspector {
+    inspected: Option<Inspected>,
+    // In px
+    press_position: Option<(f32, f32)>,
+}
+
+impl Inspector {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn inspected(&self) -> Option<Inspected> { self.inspected }
+
+    pub fn inspect(&mut self, inspected: Option<Inspected>) {
+        self.inspected = inspected;
+    }
+
+    pub fn update(&mut self, window: &Window, sd: &SimulationData) {
+        if input::is_mouse_button_pressed(MouseButton::Left) {
+            self.press_position = Some(input::mouse_position());
+        }
+        if input::is_mouse_button_released(MouseButton::Left)
+            && let Some((press_x, press_y)) = self.press_position.take() {
+            let (x, y) = input::mouse_position();
+            if (x - press_x).hypot(y - press_y) <= CLICK_DISTANCE {
+                self.inspected = pick(window.mouse_xy(), window.pixels_to_distance(PICKING_DISTANCE), sd);
+            }
+        }
+        // The car left the simulation
+        if let Some(Inspected::Car(id)) = self.inspected
+            && !sd.cars.iter().any(|car| car.id() == id) {
+            self.inspected = None;
+        }
+    }
+
+    pub fn render(&self, window: &Window, sd: &SimulationData) {
+        match self.inspected {
+            Some(Inspected::Car(id)) => {
+                if let Some(car) = sd.cars.iter().find(|car| car.id() == id) {
+                    render_car(window, car, sd);
+                }
+            },
+            Some(Inspected::Segment(segment)) => render_segment(window, segment, sd),
+            None => {},
+        }
+    }
+}
+
+fn pick(xy: (f32, f32), picking_distance: f32, sd: &SimulationData) -> Option<Inspected> {
+    let distance = |(x, y): (f32, f32)| (x - xy.0).hypot(y - xy.1);
+    sd.cars.iter()
+        .map(|car| (car, distance(sd.roads.get_position_xy(car.position()))))
+        .filter(|(car, car_distance)| *car_distance <= picking_distance + car.length() / 2.)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(car, _distance)| Inspected::Car(car.id()))
+        .or_else(|| sd.roads.nearest_point(xy, picking_distance).map(|point| Inspected::Segment(point.road_segment())))
+}
+
+fn highlight_segment(window: &Window, segment: road::RoadSegmentIdx, roads: &road::Roads) {
+    for piece in roads.segment_polyline(segment).windows(2) {
+        window.draw_road_segment(piece[0], piece[1], RoadSegmentStyle::Selected);
+    }
+}
+
+fn render_car(window: &Window, car: &Car, sd: &SimulationData) {
+    // Planned route, from the current segment
+    highlight_segment(window, car.position().road_segment(), &sd.roads);
+    for segment in car.remaining_segments(&sd.roads) {
+        highlight_segment(window, segment, &sd.roads);
+    }
+    window.draw_highlight(sd.roads.get_position_xy(car.position()), car.length());
+    let mut lines = vec![
+        format!("Car {} ({:?})", car.id(), car.vehicle_type().class),
+        format!("Speed: {:.1} km/h", car.speed() * 3.6),
+        format!("Target speed: {:.1} km/h", car.target_speed() * 3.6),
+        format!("Speed limit: {:.0} km/h", car.current_speed_limit() * 3.6),
+        format!("Position: {}", car.position()),
+    ];
+    for (position, speed_limit) in car.incoming_speed_limits() {
+        lines.push(format!("  {:.0} km/h ahead at {}", speed_limit * 3.6, position));
+    }
+    lines.push(format!("Route: {} segments ahead", car.remaining_segments(&sd.roads).len()));
+    if let Some(trip) = car.current_trip() {
+        lines.push(format!("Trip: {:.0} s, {:.0} m, {} stops", trip.travel_time(), trip.distance, trip.stops));
+        lines.push(format!("Delay: {:.0} s, CO2: {:.0} g", trip.delay(), trip.emissions.co2));
+    }
+    lines.push(format!("Driven: {:.0} m", car.distance_driven()));
+    if car.is_broken_down() {
+        lines.push("Broken down".to_string());
+    }
+    if car.is_at_stop() {
+        lines.push("At a stop".to_string());
+    }
+    if let Some(search_start) = car.parking_search_start() {
+        lines.push(format!("Searching for parking for {:.0} s, {:.0} m", sd.time - search_start, car.cruising_distance()));
+    }
+    window.draw_panel(&lines);
+}
+
+fn render_segment(window: &Window, segment: road::RoadSegmentIdx, sd: &SimulationData) {
+    highlight_segment(window, segment, &sd.roads);
+    let (from, to) = sd.roads.segment_nodes(segment);
+    let speeds: Vec<f32> = sd.cars.iter().filter(|car| car.position().road_segment() == segment).map(|car| car.speed()).collect();
+    let mut lines = vec![
+        format!("Segment {} (node {} to {})", segment, from, to),
+        format!("Length: {:.1} m", sd.roads.segment_length(segment)),
+    ];
+    if sd.roads.is_closed(segment) {
+        lines.push("Closed".to_string());
+    } else if sd.roads.capacity_factor(segment) < 1. {
+        lines.push(format!("Work zone, capacity factor {:.2}", sd.roads.capacity_factor(segment)));
+    }
+    for sign in sd.roads.signs(segment) {
+        lines.push(match sign.sign_type {
+            road::SignType::SpeedLimit => format!("Speed limit of {:.0} km/h at {:.1} m", sign.value * 3.6, sign.position.position()),
+            road::SignType::EndSpeedLimit => format!("End of speed limit at {:.1} m", sign.position.position()),
+        });
+    }
+    lines.push(format!("Vehicles: {}", speeds.len()));
+    if !speeds.is_empty() {
+        lines.push(format!("Mean speed: {:.1} km/h", speeds.iter().sum::<f32>() / speeds.len() as f32 * 3.6));
+    }
+    lines.push(format!("Travel time: {:.1} s", sd.travel_times.get(segment)));
+    window.draw_panel(&lines);
+}
@@ -0,0 +1,134 @@
+use macroquad::input::{self, KeyCode};
+use crate::gui::Window;
+
+// Simulated time per real second
+const SPEED_MULTIPLIERS: [f32; 11] = [0.1, 0.2, 0.5, 1., 2., 5., 10., 20., 30., 50., 100.];
+const DEFAULT_SPEED: usize = 3;
+// Longer frames are cut to this, so that a slow frame doesn't make the next ones slower and slower
+const MAX_FRAME_TIME: f32 = 0.25; // s
+
+// Turns the real time of the frames into a number of simulation steps of a fixed size. Space pauses and resumes,
+// the right arrow steps once while paused, + and - change the speed.
+pub struct Playback {
+    step_size: f32, // s
+    speed: usize,
+    paused: bool,
+    // Simulated time due but not stepped yet
+    lag: f32, // s
+    step_requested: bool,
+}
+
+impl Playback {
+    pub fn new(step_size: f32) -> Self {
+        Self { step_size, speed: DEFAULT_SPEED, paused: false, lag: 0., step_requested: false }
+    }
+
+    pub fn step_size(&self) -> f32 { self.step_size }
+
+    pub fn is_paused(&self) -> bool { self.paused }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.lag = 0.;
+    }
+
+    pub fn speed_multiplier(&self) -> f32 { SPEED_MULTIPLIERS[self.speed] }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed + 1).min(SPEED_MULTIPLIERS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = self.speed.saturating_sub(1);
+    }
+
+    // Performs a single step on the next frame, while paused
+    pub fn request_step(&mut self) {
+        self.step_requested = true;
+    }
+
+    pub fn update(&mut self) {
+        if input::is_key_pressed(KeyCode::Space) {
+            self.set_paused(!self.paused);
+        }
+        if input::is_key_pressed(KeyCode::Right) && self.paused {
+            self.request_step();
+        }
+        if input::is_key_pressed(KeyCode::Equal) || input::is_key_pressed(KeyCode::KpAdd) {
+            self.faster();
+        }
+        if input::is_key_pressed(KeyCode::Minus) || input::is_key_pressed(KeyCode::KpSubtract) {
+            self.slower();
+        }
+    }
+
+    // Number of steps to perform for a frame which took frame_time of real time
+    pub fn steps(&mut self, frame_time: f32) -> usize {
+        if self.paused {
+            return std::mem::take(&mut self.step_requested) as usize;
+        }
+        self.lag += frame_time.min(MAX_FRAME_TIME) * self.speed_multiplier();
+        let steps = (self.lag / self.step_size).floor();
+        self.lag -= steps * self.step_size;
+
+        steps as usize
+    }
+
+    pub fn render(&self, window: &Window, time: f32) {
+        let state = if self.paused { "paused (space: resume, right: step)".to_string() } else { format!("x{} (space: pause, +/-: speed)", self.speed_multiplier()) };
+        window.draw_banner(&format!("{} {}", format_clock(time), state));
+    }
+}
+
+// hh:mm:ss.s
+pub fn format_clock(time: f32) -> String {
+    let tenths = (time * 10.).floor() as u64;
+    format!("{:02}:{:02}:{:02}.{}", tenths / 36000, tenths / 600 % 60, tenths / 10 % 60, tenths % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_clock, Playback};
+
+    #[test]
+    fn frames_shorter_than_a_step_accumulate() {
+        let mut playback = Playback::new(0.125);
+        // Real time at x1, two frames per step
+        let steps: Vec<usize> = (0..8).map(|_frame| playback.steps(0.0625)).collect();
+        assert_eq!(steps, [0, 1, 0, 1, 0, 1, 0, 1]);
+
+        playback.faster();
+        assert_eq!(playback.speed_multiplier(), 2.);
+        assert_eq!(playback.steps(0.0625), 1);
+        assert_eq!(playback.steps(0.1875), 3);
+    }
+
+    #[test]
+    fn long_frames_are_cut() {
+        let mut playback = Playback::new(0.125);
+        // A 1 s frame counts as 0.25 s
+        assert_eq!(playback.steps(1.), 2);
+        assert_eq!(playback.steps(0.0625), 0);
+    }
+
+    #[test]
+    fn paused_playback_only_performs_requested_steps() {
+        let mut playback = Playback::new(0.125);
+        assert_eq!(playback.steps(0.0625), 0);
+        playback.set_paused(true);
+        assert_eq!(playback.steps(1.), 0);
+        playback.request_step();
+        assert_eq!(playback.steps(0.), 1);
+        assert_eq!(playback.steps(0.), 0);
+        // The lag before the pause is dropped
+        playback.set_paused(false);
+        assert_eq!(playback.steps(0.0625), 0);
+        assert_eq!(playback.steps(0.0625), 1);
+    }
+
+    #[test]
+    fn clock_shows_tenths_of_seconds() {
+        assert_eq!(format_clock(0.), "00:00:00.0");
+        assert_eq!(format_clock(3725.46), "01:02:05.4");
+    }
+}
@@ -0,0 +1,93 @@
+use macroquad::input::{self, KeyCode, MouseButton};
+use crate::{gui::{playback::{self, Playback}, Window}, output::replay::Replay, road::RoadSegmentIdx};
+
+const DEFAULT_FRAME_INTERVAL: f32 = 0.05; // s, for replays of less than two frames
+
+// Plays a recorded run back. The playback controls work as when simulating, the left arrow steps back while paused,
+// and the timeline at the bottom of the window is dragged to jump to another time.
+pub struct Replayer {
+    replay: Replay,
+    playback: Playback,
+    time: f32,
+    scrubbing: bool,
+}
+
+impl Replayer {
+    pub fn new(replay: Replay) -> Self {
+        let frame_interval = match replay.frames.as_slice() {
+            [first, second, ..] => second.time - first.time,
+            _ => DEFAULT_FRAME_INTERVAL,
+        };
+        let time = replay.start_time();
+        let mut replayer = Self { replay, playback: Playback::new(frame_interval), time, scrubbing: false };
+        replayer.update_network();
+
+        replayer
+    }
+
+    pub fn time(&self) -> f32 { self.time }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.clamp(self.replay.start_time(), self.replay.end_time());
+    }
+
+    pub fn update(&mut self, window: &Window) {
+        self.playback.update();
+        if input::is_key_pressed(KeyCode::Left) && self.playback.is_paused() {
+            self.seek(self.time - self.playback.step_size());
+        }
+        if input::is_mouse_button_pressed(MouseButton::Left) && window.is_mouse_over_timeline() {
+            self.scrubbing = true;
+        }
+        if !input::is_mouse_button_down(MouseButton::Left) {
+            self.scrubbing = false;
+        }
+        let steps = self.playback.steps(macroquad::time::get_frame_time());
+        if self.scrubbing {
+            let (start, end) = (self.replay.start_time(), self.replay.end_time());
+            self.seek(start + window.timeline_progress() * (end - start));
+        } else {
+            self.seek(self.time + steps as f32 * self.playback.step_size());
+        }
+        // Waits at the end rather than looping
+        if self.time >= self.replay.end_time() && !self.playback.is_paused() {
+            self.playback.set_paused(true);
+        }
+        self.update_network();
+    }
+
+    // Closures and work zones as recorded in the current frame
+    fn update_network(&mut self) {
+        let Some(segments) = self.replay.frame_at(self.time).map(|frame| frame.segments.clone()) else {
+            return;
+        };
+        let roads = &mut self.replay.roads;
+        for segment in (0..roads.segment_count()).map(RoadSegmentIdx) {
+            roads.set_closed(segment, false);
+            roads.set_capacity_factor(segment, 1.);
+        }
+        for state in segments {
+            roads.set_closed(state.segment, state.closed);
+            roads.set_capacity_factor(state.segment, state.capacity_factor);
+        }
+    }
+
+    pub fn render(&self, window: &Window) {
+        self.replay.roads.render(window);
+        for crossing in self.replay.roads.crossings() {
+            crossing.render(window, &self.replay.roads, self.time);
+        }
+        if let Some(frame) = self.replay.frame_at(self.time) {
+            for &xy in &frame.pedestrians {
+                window.draw_pedestrian(xy);
+            }
+            for vehicle in &frame.vehicles {
+                window.draw_car((vehicle.x, vehicle.y), vehicle.heading, &vehicle.vehicle_type());
+            }
+        }
+        self.playback.render(window, self.time);
+        let (start, end) = (self.replay.start_time(), self.replay.end_time());
+        let progress = if end > start { (self.time - start) / (end - start) } else { 1. };
+        window.draw_timeline(progress, &playback::format_clock(start), &playback::format_clock(end));
+    }
+}
@@ -0,0 +1,11 @@
+pub mod road;
+pub mod agent;
+pub mod emissions;
+pub mod gui;
+pub mod output;
+pub mod parking;
+pub mod scenarios;
+pub mod simulation;
+pub mod snapshot;
+pub mod transit;
+pub mod utils;
@@ -1,37 +1,305 @@
-mod road;
-mod agent;
-mod gui;
-mod utils;
+use traffic_simulator::{agent::{car::TripPlan, pedestrian::{self, Pedestrian}, spawner::Spawner, vehicle::{FleetMix, VehicleClass}}, emissions, gui::{self, editor::Editor, heatmap::Heatmap, inspector::Inspector, playback::Playback, replayer::Replayer}, output::{diagrams, frames::{FrameRecorder, FrameRenderer}, replay::{Replay, ReplayWriter}, trajectories::{self, TrajectoryFormat, TrajectoryWriter}, EventLog}, parking::{ParkingFacility, ParkingLocation}, road, scenarios::RingBenchmark, simulation::SimulationData, transit::{BusLine, BusStop, Departures, StopKind}};
 
-struct SimulationData {
-    roads: road::Roads,
-    cars: Vec<agent::car::Car>,
+const INFORMED_DRIVERS_SHARE: f32 = 0.3;
+const SPAWN_FLOW: f32 = 360.; // veh/h
+const SPAWN_COUNT: usize = 10;
+const BUS_HEADWAY: f32 = 120.; // s
+const BUS_RUNS: usize = 10;
+const PEDESTRIAN_COUNT: usize = 8;
+const CYCLIST_SHARE: f32 = 0.1;
+const VISITOR_FLOW: f32 = 240.; // veh/h
+const VISITOR_COUNT: usize = 30;
+const PARKING_DURATION: f32 = 300.; // s
+const COMMUTER_PARKING_DURATION: f32 = 600.; // s
+const TRAJECTORY_INTERVAL: f32 = 1.; // s
+const STEP_SIZE: f32 = 0.05; // s
+const FUNDAMENTAL_DIAGRAM_INTERVAL: f32 = 60.; // s
+const DEFAULT_FRAME_INTERVAL: f32 = 10.; // s
+const DEFAULT_FRAME_SIZE: u32 = 600; // px
+const DEFAULT_GIF_FRAME_DELAY: f32 = 0.2; // s
+const REPLAY_INTERVAL: f32 = 0.1; // s
+const DEFAULT_CORRIDOR: [usize; 3] = [0, 2, 4]; // Bottom row of the mesh, from left to right
+
+// Usage: traffic-simulator [--headless <duration in seconds> | --frames <duration in seconds> [interval in seconds] [size in px] [GIF frame delay in seconds] | --ring <duration in seconds> | --edit <map file> | --replay <replay file>]
+// Options: --map <map file to simulate instead of the mesh>, --corridor <RoadSegments of the diagrams, separated by commas>,
+// --record <replay file> [interval in seconds]
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let duration = || -> f32 { args.get(2).and_then(|duration| duration.parse().ok()).expect("Usage: --headless|--frames|--ring <duration in seconds>") };
+    let options = || Options::parse(&args);
+    match args.get(1).map(String::as_str) {
+        // Rendering images of the run too with --frames
+        Some("--headless" | "--frames") => run_headless(duration(), &options()),
+        Some("--ring") => run_ring(duration()),
+        Some("--edit") => macroquad::Window::new("MyGame", run_editor(args.get(2).expect("Usage: --edit <map file>").clone())),
+        Some("--replay") => macroquad::Window::new("MyGame", run_replay(args.get(2).expect("Usage: --replay <replay file>").clone())),
+        _ => macroquad::Window::new("MyGame", run_window(options())),
+    }
+}
+
+// Options of the runs simulating a network
+struct Options {
+    map: Option<String>,
+    // RoadSegments of the diagrams
+    corridor: Vec<road::RoadSegmentIdx>,
+    // Replay file and recording interval
+    record: Option<(String, f32)>,
+    frames: Option<FrameOptions>,
+}
+
+struct FrameOptions {
+    interval: f32, // s
+    size: u32, // px
+    gif_frame_delay: f32, // s
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Self {
+        let map = option(args, "--map").map(str::to_string);
+        let corridor = match option(args, "--corridor") {
+            Some(segments) => segments.split(',').map(|segment| segment.trim().parse().map(road::RoadSegmentIdx)).collect::<Result<_, _>>().expect("Usage: --corridor <RoadSegments separated by commas>"),
+            // The mesh corridor may not exist on other maps
+            None if map.is_some() => vec![road::RoadSegmentIdx(0)],
+            None => DEFAULT_CORRIDOR.map(road::RoadSegmentIdx).to_vec(),
+        };
+        let record_usage = "Usage: --record <replay file> [interval in seconds]";
+        let record = option_values(args, "--record").map(|values| match values {
+            [path] => (path.clone(), REPLAY_INTERVAL),
+            [path, interval] => (path.clone(), interval.parse().ok().filter(|interval: &f32| *interval > 0.).expect(record_usage)),
+            _ => panic!("{}", record_usage),
+        });
+        let frames_usage = "Usage: --frames <duration in seconds> [interval in seconds] [size in px] [GIF frame delay in seconds]";
+        let frames = option_values(args, "--frames").map(|values| {
+            assert!(values.len() <= 4, "{}", frames_usage);
+            let value = |i: usize, default: f32| values.get(i).map_or(Some(default), |value| value.parse().ok().filter(|value: &f32| *value > 0.)).expect(frames_usage);
+            FrameOptions { interval: value(1, DEFAULT_FRAME_INTERVAL), size: value(2, DEFAULT_FRAME_SIZE as f32) as u32, gif_frame_delay: value(3, DEFAULT_GIF_FRAME_DELAY) }
+        });
+
+        Self { map, corridor, record, frames }
+    }
+}
+
+// Values following the option name on the command line, up to the next option
+fn option_values<'a>(args: &'a [String], name: &str) -> Option<&'a [String]> {
+    let start = args.iter().position(|arg| arg == name)? + 1;
+    let end = args[start..].iter().position(|arg| arg.starts_with("--")).map_or(args.len(), |i| start + i);
+
+    Some(&args[start..end])
+}
+
+// Value following the option name on the command line
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    option_values(args, name).map(|values| values.first().map(String::as_str).unwrap_or_else(|| panic!("Usage: {} <value>", name)))
+}
+
+// The mesh with its demand, or the network of the map file with cars parking at its last RoadNode
+fn create_simulation(options: &Options) -> SimulationData {
+    let roads = match &options.map {
+        Some(path) => road::Roads::load(path).expect("Could not load the map"),
+        None => road::Roads::new(),
+    };
+    assert!(roads.segment_count() > 0, "The map has no RoadSegment");
+    let mut sd: SimulationData = SimulationData::new(roads);
+    std::fs::create_dir_all("output").expect("Could not create the output directory");
+    sd.log = EventLog::to_file("output/events.log").expect("Could not create the event log");
+    let detector_position = (sd.roads.segment_length(road::RoadSegmentIdx(0)) / 2.).min(15.);
+    sd.detectors.add(road::RoadPoint::new(road::RoadSegmentIdx(0), detector_position)).expect("Could not add the detector");
+    std::fs::create_dir_all("output/detectors").expect("Could not create the detectors output directory");
+    sd.detectors.write_csv_to("output/detectors").expect("Could not create the detectors output");
+    sd.trajectory_writers.push(TrajectoryWriter::create("output/trajectories.csv", TrajectoryFormat::Csv, TRAJECTORY_INTERVAL).expect("Could not create the trajectory output"));
+    sd.trajectory_writers.push(TrajectoryWriter::create("output/trajectories.bin", TrajectoryFormat::Binary, TRAJECTORY_INTERVAL).expect("Could not create the trajectory output"));
+    if options.map.is_some() {
+        let destination = road::RoadNodeIdx(sd.roads.node_count() - 1);
+        let trip_plan = TripPlan::Park { destination, duration: PARKING_DURATION };
+        sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), SPAWN_FLOW, FleetMix::default(), trip_plan, INFORMED_DRIVERS_SHARE, Some(SPAWN_COUNT)));
+        sd.parking.add_facility(ParkingFacility::new(ParkingLocation::Garage { node: destination }, SPAWN_COUNT));
+    } else {
+        add_mesh_demand(&mut sd);
+    }
+    // Once the network is complete
+    if let Some((path, interval)) = &options.record {
+        sd.replay_writer = Some(ReplayWriter::create(path, &sd.roads, *interval).expect("Could not create the replay"));
+    }
+
+    sd
+}
+
+// Buses, cyclists, pedestrians and visitors looking for parking around RoadNode 10
+fn add_mesh_demand(sd: &mut SimulationData) {
+    // Along the bottom row of the mesh
+    sd.transit.add_line(BusLine {
+        name: "1".to_string(),
+        segments: vec![road::RoadSegmentIdx(0), road::RoadSegmentIdx(2), road::RoadSegmentIdx(4)],
+        stops: vec![
+            BusStop { name: "A".to_string(), position: road::RoadPoint::new(road::RoadSegmentIdx(2), 15.), kind: StopKind::InLane, passenger_arrival_rate: 0.02, scheduled_offset: 20. },
+            BusStop { name: "B".to_string(), position: road::RoadPoint::new(road::RoadSegmentIdx(4), 15.), kind: StopKind::Bay, passenger_arrival_rate: 0.03, scheduled_offset: 40. },
+        ],
+        departures: Departures::Headway { first: 30., headway: BUS_HEADWAY, count: BUS_RUNS },
+    }, &sd.roads);
+    // Bottom row of the mesh, both ways
+    for segment in 0..6 {
+        sd.roads.set_bike_lane(road::RoadSegmentIdx(segment), road::BikeLane::Dedicated);
+    }
+    sd.roads.add_crossing(road::Crossing { node: road::RoadNodeIdx(1), kind: road::CrossingKind::Zebra, length: 7. });
+    sd.roads.add_crossing(road::Crossing { node: road::RoadNodeIdx(5), kind: road::CrossingKind::Signalised { cycle: 60., walk_time: 15., offset: 0. }, length: 7. });
+    for i in 0..PEDESTRIAN_COUNT {
+        let speed = sd.rng.normal(pedestrian::WALKING_SPEED, 0.2).clamp(0.8, 2.);
+        sd.pedestrians.push(Pedestrian::new(road::RoadNodeIdx(i % 4), road::RoadNodeIdx(15 - i % 4), speed));
+    }
+    // Across the mesh, to park near its top right corner
+    let trip_plan = TripPlan::Park { destination: road::RoadNodeIdx(14), duration: COMMUTER_PARKING_DURATION };
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), SPAWN_FLOW, FleetMix::default().with(VehicleClass::Bicycle.default_type(), CYCLIST_SHARE), trip_plan, INFORMED_DRIVERS_SHARE, Some(SPAWN_COUNT)));
+    // Fewer spaces around RoadNode 10 than visitors, so that some of them cruise
+    sd.parking.add_facility(ParkingFacility::new(ParkingLocation::OnStreet { segment: road::RoadSegmentIdx(14), start: 5., end: 25. }, 2));
+    sd.parking.add_facility(ParkingFacility::new(ParkingLocation::OnStreet { segment: road::RoadSegmentIdx(17), start: 5., end: 25. }, 2));
+    sd.parking.add_facility(ParkingFacility::new(ParkingLocation::Garage { node: road::RoadNodeIdx(14) }, 4));
+    let trip_plan = TripPlan::Park { destination: road::RoadNodeIdx(10), duration: PARKING_DURATION };
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(24), 0.), VISITOR_FLOW, FleetMix::new().with(VehicleClass::Car.default_type(), 1.), trip_plan, 0., Some(VISITOR_COUNT)));
+}
+
+fn finish_simulation(sd: &mut SimulationData) {
+    sd.detectors.finish(sd.time);
+    for writer in &mut sd.trajectory_writers {
+        writer.flush().expect("Could not write the trajectories");
+    }
+    if let Some(writer) = &mut sd.replay_writer {
+        writer.flush().expect("Could not write the replay");
+    }
+    if let Some(recorder) = sd.frame_recorder.take() {
+        recorder.finish().expect("Could not write the frames");
+    }
+    sd.trips.write_csv("output/trips.csv").expect("Could not write the trip summary");
+    sd.network_summary().write("output/network_summary.csv").expect("Could not write the network summary");
+    emissions::write_segment_emissions(&sd.segment_emissions, "output/segment_emissions.csv").expect("Could not write the segment emissions");
+    sd.transit.write_stop_records("output/bus_stops.csv").expect("Could not write the bus stop records");
+    sd.transit.write_summary("output/bus_lines.csv").expect("Could not write the bus line summary");
+    sd.parking.write_records("output/parking.csv").expect("Could not write the parking records");
+    sd.parking.write_summary("output/parking_summary.csv", sd.network_summary().vehicle_kilometres * 1000., sd.time).expect("Could not write the parking summary");
+}
+
+fn run_headless(duration: f32, options: &Options) {
+    let mut sd = create_simulation(options);
+    assert!(options.corridor.iter().all(|segment| **segment < sd.roads.segment_count()), "The corridor has RoadSegments out of the network");
+    if let Some(frames) = &options.frames {
+        let renderer = FrameRenderer::new(frames.size, frames.size, &sd.roads).expect("Could not create the frame renderer");
+        let mut recorder = FrameRecorder::create("output/frames", renderer, frames.interval).expect("Could not create the frames output directory");
+        recorder.write_gif_to("output/frames/run.gif", frames.gif_frame_delay).expect("Could not create the GIF");
+        sd.frame_recorder = Some(recorder);
+    }
+    while sd.time < duration {
+        sd.step(STEP_SIZE);
+    }
+    finish_simulation(&mut sd);
+    write_diagrams(&sd.roads, &options.corridor);
+}
+
+// Stop-and-go waves appearing on a ring road, from the drivers' reaction time only
+fn run_ring(duration: f32) {
+    let benchmark = RingBenchmark { duration, warm_up: duration / 2., step_size: STEP_SIZE, ..Default::default() };
+    let mut sd = benchmark.create_simulation();
+    std::fs::create_dir_all("output/ring").expect("Could not create the ring output directory");
+    sd.trajectory_writers.push(TrajectoryWriter::create("output/ring/trajectories.bin", TrajectoryFormat::Binary, TRAJECTORY_INTERVAL).expect("Could not create the trajectory output"));
+    let result = benchmark.run_simulation(&mut sd);
+    result.write("output/ring/benchmark.csv").expect("Could not write the ring benchmark results");
+    for writer in &mut sd.trajectory_writers {
+        writer.flush().expect("Could not write the trajectories");
+    }
+    let points = trajectories::read_binary("output/ring/trajectories.bin").expect("Could not read the trajectories");
+    let time_space = diagrams::time_space_diagram(&points, &[road::RoadSegmentIdx(0)], &sd.roads);
+    time_space.write_svg("output/ring/time_space.svg").expect("Could not write the time-space diagram");
+    time_space.write_png("output/ring/time_space.png").expect("Could not write the time-space diagram");
 }
 
+// Diagrams of the trajectories recorded during the run
+fn write_diagrams(roads: &road::Roads, corridor: &[road::RoadSegmentIdx]) {
+    let points = trajectories::read_binary("output/trajectories.bin").expect("Could not read the trajectories");
+    std::fs::create_dir_all("output/diagrams").expect("Could not create the diagrams output directory");
+    let time_space = diagrams::time_space_diagram(&points, corridor, roads);
+    time_space.write_svg("output/diagrams/time_space.svg").expect("Could not write the time-space diagram");
+    time_space.write_png("output/diagrams/time_space.png").expect("Could not write the time-space diagram");
+    for &segment in corridor {
+        let fundamental_points = diagrams::fundamental_diagram_points(&points, segment, roads, TRAJECTORY_INTERVAL, FUNDAMENTAL_DIAGRAM_INTERVAL);
+        let fundamental = diagrams::fundamental_diagram(&fundamental_points, &format!("Fundamental diagram of segment {}", segment));
+        fundamental.write_svg(format!("output/diagrams/fundamental_{}.svg", segment)).expect("Could not write the fundamental diagram");
+        fundamental.write_png(format!("output/diagrams/fundamental_{}.png", segment)).expect("Could not write the fundamental diagram");
+    }
+}
 
-#[macroquad::main("MyGame")]
-async fn main() {
-    let mut sd: SimulationData = SimulationData { roads: road::Roads::new(), cars: Vec::new() };
+async fn run_window(options: Options) {
+    let mut sd = create_simulation(&options);
     let mut window = gui::Window::new().await;
-    sd.cars.push(agent::car::Car::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.)));
-    // for _ in 0..10 {
-    //     let road_idx: usize = gen_range(0, sd.roads.segments.len());
-    //     let progression: f32 = gen_range(0., 1.);
-    //     sd.cars.push(Car::new(roads::RoadPoint::new(road_idx, progression)));
-    // }
+    let mut inspector = Inspector::new();
+    let mut heatmap = Heatmap::new();
+    let mut playback = Playback::new(STEP_SIZE);
+    // Closing the window quits through the loop too, so that the outputs are written
+    macroquad::input::prevent_quit();
     loop {
-        let step_size = macroquad::prelude::get_frame_time();
-        let roads = &sd.roads;
-        for car in sd.cars.iter_mut() {
-            car.update(step_size, roads);
+        playback.update();
+        for _ in 0..playback.steps(macroquad::time::get_frame_time()) {
+            sd.step(playback.step_size());
         }
         macroquad::window::clear_background(macroquad::color::BLACK);
         sd.roads.render(&window);
-        for (i, car) in sd.cars.iter().enumerate() {
-            car.render(&window, &sd.roads, i == 0)
+        heatmap.update();
+        heatmap.render(&window, &sd);
+        for blockage in &sd.blockages {
+            blockage.render(&window, &sd.roads);
         }
+        sd.transit.render(&window, &sd.roads);
+        sd.parking.render(&window, &sd.roads);
+        for crossing in sd.roads.crossings() {
+            crossing.render(&window, &sd.roads, sd.time);
+        }
+        for pedestrian in &sd.pedestrians {
+            pedestrian.render(&window, &sd.roads);
+        }
+        for car in &sd.cars {
+            car.render(&window, &sd.roads);
+        }
+        inspector.update(&window, &sd);
+        inspector.render(&window, &sd);
+        playback.render(&window, sd.time);
         window.update();
         macroquad::time::draw_fps();
+        if macroquad::input::is_key_pressed(macroquad::input::KeyCode::Escape) || macroquad::input::is_quit_requested() {
+            break;
+        }
+        macroquad::window::next_frame().await;
+    }
+    finish_simulation(&mut sd);
+}
+
+// Edits the map file, created on the first save if it doesn't exist
+async fn run_editor(path: String) {
+    let mut editor = Editor::open(&path).expect("Could not open the map file");
+    let mut window = gui::Window::new().await;
+    window.set_pan_button(macroquad::input::MouseButton::Right);
+    loop {
+        macroquad::window::clear_background(macroquad::color::BLACK);
+        editor.update(&window);
+        editor.render(&window);
+        window.update();
+        if macroquad::input::is_key_pressed(macroquad::input::KeyCode::Escape) {
+            break;
+        }
+        macroquad::window::next_frame().await;
+    }
+}
+
+// Plays back a run recorded to a replay file with --record
+async fn run_replay(path: String) {
+    let mut replayer = Replayer::new(Replay::load(&path).expect("Could not read the replay file"));
+    let mut window = gui::Window::new().await;
+    // The left one drags the timeline
+    window.set_pan_button(macroquad::input::MouseButton::Right);
+    loop {
+        macroquad::window::clear_background(macroquad::color::BLACK);
+        replayer.update(&window);
+        replayer.render(&window);
+        window.update();
+        if macroquad::input::is_key_pressed(macroquad::input::KeyCode::Escape) {
+            break;
+        }
         macroquad::window::next_frame().await;
     }
 }
@@ -0,0 +1,37 @@
+pub mod canvas;
+pub mod detectors;
+pub mod diagrams;
+pub mod figure;
+pub mod font;
+pub mod frames;
+pub mod replay;
+pub mod trajectories;
+pub mod trips;
+
+use std::{fmt::Display, fs::File, io::{self, BufWriter, Write}, path::Path};
+
+// Timestamped log of what happens during a run (network events, incidents...)
+pub struct EventLog {
+    writer: Option<Box<dyn Write>>,
+}
+
+impl EventLog {
+    // A log that discards everything
+    pub fn new() -> Self { Self { writer: None } }
+
+    pub fn to_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self { writer: Some(Box::new(BufWriter::new(File::create(path)?))) })
+    }
+
+    pub fn to_writer(writer: impl Write + 'static) -> Self { Self { writer: Some(Box::new(writer)) } }
+
+    pub fn log(&mut self, time: f32, message: impl Display) {
+        if let Some(writer) = &mut self.writer {
+            writeln!(writer, "{:.2}\t{}", time, message).expect("Could not write to the event log");
+        }
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self { Self::new() }
+}
@@ -0,0 +1,128 @@
+use std::path::Path;
+use macroquad::color::Color;
+use crate::output::font;
+
+// Software RGBA raster, to draw images without a window or a GPU
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32, background: Color) -> Self {
+        let mut canvas = Self { width, height, pixels: vec![0; (width * height * 4) as usize] };
+        canvas.clear(background);
+
+        canvas
+    }
+
+    pub fn width(&self) -> u32 { self.width }
+
+    pub fn height(&self) -> u32 { self.height }
+
+    pub fn pixels(&self) -> &[u8] { &self.pixels }
+
+    pub fn clear(&mut self, color: Color) {
+        let color: [u8; 4] = color.into();
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    // Blends the color over the pixel, ignoring pixels out of the canvas
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let idx = ((y as u32 * self.width + x as u32) * 4) as usize;
+        let source: [u8; 4] = color.into();
+        for (destination, source) in self.pixels[idx..idx + 3].iter_mut().zip(source) {
+            *destination = (source as f32 * color.a + *destination as f32 * (1. - color.a)).round() as u8;
+        }
+        self.pixels[idx + 3] = 255;
+    }
+
+    pub fn fill_rectangle(&mut self, x: f32, y: f32, width: f32, height: f32, color: Color) {
+        let (x_start, y_start) = (x.round() as i32, y.round() as i32);
+        let (x_end, y_end) = ((x + width).round() as i32, (y + height).round() as i32);
+        for py in y_start.max(0)..y_end.min(self.height as i32) {
+            for px in x_start.max(0)..x_end.min(self.width as i32) {
+                self.blend_pixel(px, py, color);
+            }
+        }
+    }
+
+    pub fn fill_circle(&mut self, x: f32, y: f32, radius: f32, color: Color) {
+        let r = radius.ceil() as i32;
+        let (cx, cy) = (x.round() as i32, y.round() as i32);
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if ((dx * dx + dy * dy) as f32) <= radius * radius {
+                    self.blend_pixel(cx + dx, cy + dy, color);
+                }
+            }
+        }
+    }
+
+    pub fn draw_line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, thickness: f32, color: Color) {
+        // Skip lines entirely out of the canvas, which can be arbitrarily long once zoomed in
+        let margin = thickness;
+        if (x1 < -margin && x2 < -margin) || (y1 < -margin && y2 < -margin)
+            || (x1 > self.width as f32 + margin && x2 > self.width as f32 + margin)
+            || (y1 > self.height as f32 + margin && y2 > self.height as f32 + margin) {
+            return;
+        }
+        let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+        let steps = length.ceil().max(1.) as i32;
+        let half_thickness = (thickness / 2.).max(0.5);
+        let brush = half_thickness.ceil() as i32;
+        let mut last_pixel = None;
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let (x, y) = ((x1 + (x2 - x1) * t).round() as i32, (y1 + (y2 - y1) * t).round() as i32);
+            if last_pixel == Some((x, y)) {
+                continue;
+            }
+            last_pixel = Some((x, y));
+            for dy in -brush..=brush {
+                for dx in -brush..=brush {
+                    if ((dx * dx + dy * dy) as f32).sqrt() <= half_thickness {
+                        self.set_pixel(x + dx, y + dy, color);
+                    }
+                }
+            }
+        }
+    }
+
+    // Left aligned on x, with its baseline at y like SVG text, size being the font size in px
+    pub fn draw_text(&mut self, x: f32, y: f32, text: &str, size: f32, color: Color) {
+        let dot = size / (font::GLYPH_HEIGHT + 2) as f32;
+        let top = y - (font::BASELINE + 1) as f32 * dot;
+        for (i, character) in text.chars().enumerate() {
+            let left = x + (i * (font::GLYPH_WIDTH + 1)) as f32 * dot;
+            for (column, bits) in font::glyph(character).iter().enumerate() {
+                for row in (0..font::GLYPH_HEIGHT).filter(|row| bits & (1 << row) != 0) {
+                    self.fill_rectangle(left + column as f32 * dot, top + row as f32 * dot, dot, dot, color);
+                }
+            }
+        }
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> image::ImageResult<()> {
+        image::save_buffer(path, &self.pixels, self.width, self.height, image::ColorType::Rgba8)
+    }
+
+    fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if color.a < 1. {
+            self.blend_pixel(x, y, color);
+            return;
+        }
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let idx = ((y as u32 * self.width + x as u32) * 4) as usize;
+        let color: [u8; 4] = color.into();
+        self.pixels[idx..idx + 4].copy_from_slice(&color);
+    }
+}
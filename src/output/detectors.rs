@@ -0,0 +1,172 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::{Path, PathBuf}};
+use crate::road::RoadPoint;
+
+// Virtual loop detector, counting the cars crossing a RoadPoint
+pub struct Detector {
+    position: RoadPoint,
+    count: usize,
+    speed_sum: f32,
+    occupied_time: f32,
+    records: Vec<DetectorRecord>,
+    writer: Option<BufWriter<File>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DetectorRecord {
+    pub start: f32,
+    pub end: f32,
+    pub count: usize,
+    // None if no car crossed the detector
+    pub time_mean_speed: Option<f32>,
+    // Share of the interval during which a car was over the detector
+    pub occupancy: f32,
+}
+
+// Movement of a car during a step
+pub struct CarMove {
+    pub from: RoadPoint,
+    pub to: RoadPoint,
+    pub speed: f32,
+    pub length: f32,
+}
+
+pub struct Detectors {
+    detectors: Vec<Detector>,
+    interval: f32,
+    interval_start: f32,
+    // Where the CSV files go, including those of the detectors added later
+    directory: Option<PathBuf>,
+}
+
+impl Detectors {
+    // interval is the aggregation interval, in seconds
+    pub fn new(interval: f32) -> Self { Self { detectors: Vec::new(), interval, interval_start: 0., directory: None } }
+
+    // Fails if the CSV file of the detector cannot be created, see write_csv_to
+    pub fn add(&mut self, position: RoadPoint) -> io::Result<usize> {
+        let i = self.detectors.len();
+        let writer = match &self.directory {
+            Some(directory) => Some(create_csv(directory, i)?),
+            None => None,
+        };
+        self.detectors.push(Detector { position, count: 0, speed_sum: 0., occupied_time: 0., records: Vec::new(), writer });
+
+        Ok(i)
+    }
+
+    // Writes a CSV file per detector in the directory, with a line per aggregation interval
+    pub fn write_csv_to(&mut self, directory: impl AsRef<Path>) -> io::Result<()> {
+        for (i, detector) in self.detectors.iter_mut().enumerate() {
+            detector.writer = Some(create_csv(directory.as_ref(), i)?);
+        }
+        self.directory = Some(directory.as_ref().to_path_buf());
+
+        Ok(())
+    }
+
+    // Closes the interval in progress, shorter than the others, at the end of the run
+    pub fn finish(&mut self, time: f32) {
+        if time > self.interval_start {
+            for (i, detector) in self.detectors.iter_mut().enumerate() {
+                detector.close_interval(i, self.interval_start, time);
+            }
+            self.interval_start = time;
+        }
+    }
+
+    pub fn records(&self, detector: usize) -> &[DetectorRecord] { &self.detectors[detector].records }
+
+    // time is the time at the end of the step
+    pub fn update(&mut self, time: f32, step_size: f32, moves: &[CarMove]) {
+        for detector in &mut self.detectors {
+            let position = detector.position;
+            for car_move in moves {
+                let crossed = if car_move.from.road_segment() == position.road_segment() {
+                    car_move.from.position() < position.position()
+                        && (car_move.to.road_segment() != position.road_segment() || car_move.to.position() >= position.position())
+                } else {
+                    car_move.to.road_segment() == position.road_segment() && car_move.to.position() >= position.position()
+                };
+                if crossed {
+                    detector.count += 1;
+                    detector.speed_sum += car_move.speed;
+                }
+                if car_move.to.road_segment() == position.road_segment()
+                    && car_move.to.position() - car_move.length < position.position()
+                    && position.position() <= car_move.to.position() {
+                    detector.occupied_time += step_size;
+                }
+            }
+        }
+        if time >= self.interval_start + self.interval {
+            for (i, detector) in self.detectors.iter_mut().enumerate() {
+                detector.close_interval(i, self.interval_start, time);
+            }
+            self.interval_start = time;
+        }
+    }
+}
+
+impl Detector {
+    fn close_interval(&mut self, i: usize, start: f32, end: f32) {
+        let record = DetectorRecord {
+            start,
+            end,
+            count: self.count,
+            time_mean_speed: if self.count == 0 { None } else { Some(self.speed_sum / self.count as f32) },
+            occupancy: (self.occupied_time / (end - start)).min(1.),
+        };
+        if let Some(writer) = &mut self.writer {
+            let speed = record.time_mean_speed.map(|speed| format!("{:.2}", speed * 3.6)).unwrap_or_default();
+            writeln!(writer, "{},{},{:.2},{:.2},{:.2},{},{:.1},{},{:.4}", i, self.position.road_segment(), self.position.position(), record.start, record.end, record.count, record.count as f32 * 3600. / (end - start), speed, record.occupancy)
+                .and_then(|_| writer.flush())
+                .expect("Could not write the detector output");
+        }
+        self.records.push(record);
+        self.count = 0;
+        self.speed_sum = 0.;
+        self.occupied_time = 0.;
+    }
+}
+
+fn create_csv(directory: &Path, i: usize) -> io::Result<BufWriter<File>> {
+    let mut writer = BufWriter::new(File::create(directory.join(format!("detector_{}.csv", i)))?);
+    writeln!(writer, "detector,segment,position_m,start_s,end_s,count,flow_veh_h,time_mean_speed_kmh,occupancy")?;
+
+    Ok(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::road::{RoadPoint, RoadSegmentIdx};
+    use super::{CarMove, DetectorRecord, Detectors};
+
+    fn car_move(from: (usize, f32), to: (usize, f32), speed: f32) -> CarMove {
+        CarMove { from: RoadPoint::new(RoadSegmentIdx(from.0), from.1), to: RoadPoint::new(RoadSegmentIdx(to.0), to.1), speed, length: 4. }
+    }
+
+    #[test]
+    fn counts_each_crossing_once_and_measures_occupancy() {
+        let mut detectors = Detectors::new(10.);
+        let detector = detectors.add(RoadPoint::new(RoadSegmentIdx(0), 10.)).expect("Could not add the detector");
+        // A car crossing the detector at step 2 and covering it for 2 s, and another one entering segment 0 past it at step 5
+        let steps = [
+            vec![car_move((0, 0.), (0, 5.), 5.)],
+            vec![car_move((0, 5.), (0, 10.), 5.)],
+            vec![car_move((0, 10.), (0, 13.), 5.)],
+            vec![car_move((0, 13.), (0, 16.), 5.)],
+            vec![car_move((2, 28.), (0, 12.), 7.)],
+        ];
+        for t in 1..=15 {
+            let moves = steps.get(t - 1).map(|moves| &moves[..]).unwrap_or(&[]);
+            detectors.update(t as f32, 1., moves);
+        }
+        detectors.finish(15.);
+
+        // Occupied from t = 1 to 3 by the first car and from t = 4 to 5 by the second one
+        assert_eq!(detectors.records(detector), [
+            DetectorRecord { start: 0., end: 10., count: 2, time_mean_speed: Some(6.), occupancy: 0.3 },
+            DetectorRecord { start: 10., end: 15., count: 0, time_mean_speed: None, occupancy: 0. },
+        ]);
+    }
+}
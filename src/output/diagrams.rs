@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use macroquad::color::{Color, BLACK, LIGHTGRAY};
+use crate::{output::{figure::Figure, trajectories::TrajectoryPoint}, road::{RoadSegmentIdx, Roads}};
+
+const WIDTH: u32 = 1000;
+const HEIGHT: u32 = 700;
+const PANEL_WIDTH: u32 = 450;
+const PANEL_HEIGHT: u32 = 400;
+const MARGIN: f32 = 60.;
+const TICKS: usize = 5;
+// Share of an aggregation interval under which the end of the records doesn't start a new one, for float rounding
+const BIN_TOLERANCE: f32 = 1e-3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FundamentalDiagramPoint {
+    pub start: f32,
+    pub flow: f32, // veh/h
+    pub density: f32, // veh/km
+    // km/h, None if no car was on the segment
+    pub speed: Option<f32>,
+}
+
+// Maps data coordinates to a rectangle of a Figure, and draws its frame and ticks
+struct Axes {
+    left: f32,
+    top: f32,
+    width: f32,
+    height: f32,
+    x_max: f32,
+    y_max: f32,
+}
+
+// Distance driven along the corridor for each car, colored by speed
+pub fn time_space_diagram(points: &[TrajectoryPoint], corridor: &[RoadSegmentIdx], roads: &Roads) -> Figure {
+    let mut segment_offsets: Vec<(RoadSegmentIdx, f32)> = Vec::new();
+    let mut corridor_length = 0.;
+    for segment in corridor {
+        segment_offsets.push((*segment, corridor_length));
+        corridor_length += roads.segment_length(*segment);
+    }
+    let corridor_distance = |point: &TrajectoryPoint| segment_offsets.iter()
+        .find(|(segment, _offset)| *segment == point.position.road_segment())
+        .map(|(_segment, offset)| offset + point.position.position());
+    let mut trajectories: BTreeMap<usize, Vec<(f32, f32, f32)>> = BTreeMap::new();
+    for point in points {
+        if let Some(distance) = corridor_distance(point) {
+            trajectories.entry(point.car).or_default().push((point.time, distance, point.speed));
+        }
+    }
+    let time_max = points.iter().map(|point| point.time).fold(0., f32::max);
+    let speed_max = points.iter().map(|point| point.speed).fold(1., f32::max);
+    let mut figure = Figure::new(WIDTH, HEIGHT);
+    let axes = Axes { left: MARGIN, top: MARGIN, width: WIDTH as f32 - 2. * MARGIN, height: HEIGHT as f32 - 2. * MARGIN, x_max: nice_max(time_max), y_max: nice_max(corridor_length) };
+    for (_segment, offset) in &segment_offsets {
+        figure.line(axes.to_px(0., *offset), axes.to_px(axes.x_max, *offset), 1., LIGHTGRAY);
+    }
+    axes.draw(&mut figure, "Time (s)", "Distance along the corridor (m)");
+    figure.text((MARGIN, MARGIN / 2.), format!("Time-space diagram of segments {:?}", corridor.iter().map(|segment| **segment).collect::<Vec<_>>()), 16., BLACK);
+    for trajectory in trajectories.values_mut() {
+        trajectory.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for pair in trajectory.windows(2) {
+            let ((t1, d1, _), (t2, d2, speed)) = (pair[0], pair[1]);
+            // The car left the corridor, or entered it again from its start
+            if d2 < d1 {
+                continue;
+            }
+            figure.line(axes.to_px(t1, d1), axes.to_px(t2, d2), 1.5, speed_color(speed / speed_max));
+        }
+    }
+
+    figure
+}
+
+// Edie's generalized definitions over each aggregation interval. sample_interval is the time between two trajectory records.
+pub fn fundamental_diagram_points(points: &[TrajectoryPoint], segment: RoadSegmentIdx, roads: &Roads, sample_interval: f32, aggregation_interval: f32) -> Vec<FundamentalDiagramPoint> {
+    let mut bins: BTreeMap<usize, (f32, f32)> = BTreeMap::new();
+    // Each record stands for the sample interval that follows it
+    let time_end = points.iter().map(|point| point.time).fold(0., f32::max) + sample_interval;
+    for bin in 0..(time_end / aggregation_interval - BIN_TOLERANCE).ceil() as usize {
+        bins.insert(bin, (0., 0.));
+    }
+    for point in points.iter().filter(|point| point.position.road_segment() == segment) {
+        let bin = (point.time / aggregation_interval) as usize;
+        if let Some((time_spent, distance_driven)) = bins.get_mut(&bin) {
+            *time_spent += sample_interval;
+            *distance_driven += point.speed * sample_interval;
+        }
+    }
+    bins.iter().map(|(bin, (time_spent, distance_driven))| {
+        let start = *bin as f32 * aggregation_interval;
+        // The last interval may be cut short by the end of the records
+        let area = roads.segment_length(segment) * aggregation_interval.min(time_end - start);
+        let density = time_spent / area; // veh/m
+        let flow = distance_driven / area; // veh/s
+        FundamentalDiagramPoint {
+            start,
+            flow: flow * 3600.,
+            density: density * 1000.,
+            speed: if density > 0. { Some(flow / density * 3.6) } else { None },
+        }
+    }).collect()
+}
+
+// Flow-density, speed-density and speed-flow scatter plots
+pub fn fundamental_diagram(points: &[FundamentalDiagramPoint], title: &str) -> Figure {
+    let mut figure = Figure::new(3 * PANEL_WIDTH, PANEL_HEIGHT);
+    let flow_max = nice_max(points.iter().map(|point| point.flow).fold(0., f32::max));
+    let density_max = nice_max(points.iter().map(|point| point.density).fold(0., f32::max));
+    let speed_max = nice_max(points.iter().filter_map(|point| point.speed).fold(0., f32::max));
+    let panel = |i: u32, x_max: f32, y_max: f32| Axes {
+        left: (i * PANEL_WIDTH) as f32 + MARGIN,
+        top: MARGIN,
+        width: PANEL_WIDTH as f32 - 1.5 * MARGIN,
+        height: PANEL_HEIGHT as f32 - 2. * MARGIN,
+        x_max,
+        y_max,
+    };
+    let panels = [
+        (panel(0, density_max, flow_max), "Density (veh/km)", "Flow (veh/h)"),
+        (panel(1, density_max, speed_max), "Density (veh/km)", "Speed (km/h)"),
+        (panel(2, flow_max, speed_max), "Flow (veh/h)", "Speed (km/h)"),
+    ];
+    for (axes, x_label, y_label) in &panels {
+        axes.draw(&mut figure, x_label, y_label);
+    }
+    figure.text((MARGIN, MARGIN / 2.), title, 16., BLACK);
+    let color = Color::new(0., 0.3, 0.8, 0.6);
+    for point in points {
+        figure.circle(panels[0].0.to_px(point.density, point.flow), 3., color);
+        if let Some(speed) = point.speed {
+            figure.circle(panels[1].0.to_px(point.density, speed), 3., color);
+            figure.circle(panels[2].0.to_px(point.flow, speed), 3., color);
+        }
+    }
+
+    figure
+}
+
+impl Axes {
+    fn to_px(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.left + x / self.x_max * self.width, self.top + self.height - y / self.y_max * self.height)
+    }
+
+    fn draw(&self, figure: &mut Figure, x_label: &str, y_label: &str) {
+        let bottom = self.top + self.height;
+        figure.line((self.left, bottom), (self.left + self.width, bottom), 1., BLACK);
+        figure.line((self.left, self.top), (self.left, bottom), 1., BLACK);
+        for i in 0..=TICKS {
+            let (x, y) = (self.x_max * i as f32 / TICKS as f32, self.y_max * i as f32 / TICKS as f32);
+            let (x_px, _) = self.to_px(x, 0.);
+            let (_, y_px) = self.to_px(0., y);
+            figure.line((x_px, bottom), (x_px, bottom + 5.), 1., BLACK);
+            figure.text((x_px - 10., bottom + 20.), format!("{}", x), 12., BLACK);
+            figure.line((self.left - 5., y_px), (self.left, y_px), 1., BLACK);
+            figure.text((self.left - 45., y_px + 4.), format!("{}", y), 12., BLACK);
+        }
+        figure.text((self.left + self.width / 2. - 40., bottom + 40.), x_label, 14., BLACK);
+        figure.text((self.left, self.top - 8.), y_label, 14., BLACK);
+    }
+}
+
+// Red when stopped, green at the highest speed
+fn speed_color(relative_speed: f32) -> Color {
+    let relative_speed = relative_speed.clamp(0., 1.);
+    Color::new(1. - relative_speed, relative_speed * 0.8, 0., 1.)
+}
+
+// Rounds up to 1, 2 or 5 times a power of 10, for readable axis ticks
+fn nice_max(value: f32) -> f32 {
+    if value <= 0. {
+        return 1.;
+    }
+    let magnitude = 10f32.powf(value.log10().floor());
+    for factor in [1., 2., 5., 10.] {
+        if value <= factor * magnitude {
+            return factor * magnitude;
+        }
+    }
+
+    10. * magnitude
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{output::trajectories::TrajectoryPoint, road::{RoadPoint, RoadSegmentIdx, Roads}};
+    use super::fundamental_diagram_points;
+
+    fn point(car: usize, time: f32, segment: usize, speed: f32) -> TrajectoryPoint {
+        TrajectoryPoint { car, time, position: RoadPoint::new(RoadSegmentIdx(segment), 0.), x: 0., y: 0., speed, acceleration: 0., target_speed: speed }
+    }
+
+    #[test]
+    fn edie_definitions_include_the_last_partial_interval() {
+        let roads = Roads::new();
+        // One car at 2 m/s on the 30 m segment 0 for 15 s, recorded every second, and one car elsewhere
+        let mut points: Vec<TrajectoryPoint> = (0..15).map(|t| point(0, t as f32, 0, 2.)).collect();
+        points.extend((0..15).map(|t| point(1, t as f32, 2, 5.)));
+        let diagram = fundamental_diagram_points(&points, RoadSegmentIdx(0), &roads, 1., 10.);
+
+        // [0, 10) : 10 s spent and 20 m driven over 30 m × 10 s, so k = 10 / 300 veh/m and q = 20 / 300 veh/s
+        // [10, 15) : 5 s spent and 10 m driven over 30 m × 5 s, so k = 5 / 150 veh/m and q = 10 / 150 veh/s
+        assert_eq!(diagram.len(), 2);
+        for (fd_point, start) in diagram.iter().zip([0., 10.]) {
+            assert_eq!(fd_point.start, start);
+            assert!((fd_point.density - 1000. / 30.).abs() < 1e-3, "{:?}", fd_point);
+            assert!((fd_point.flow - 240.).abs() < 1e-3, "{:?}", fd_point);
+            assert!((fd_point.speed.expect("Could not find the speed") - 7.2).abs() < 1e-3, "{:?}", fd_point);
+        }
+    }
+
+    #[test]
+    fn intervals_without_cars_have_no_speed() {
+        let roads = Roads::new();
+        let points: Vec<TrajectoryPoint> = (0..20).map(|t| point(0, t as f32, if t < 10 { 0 } else { 2 }, 2.)).collect();
+        let diagram = fundamental_diagram_points(&points, RoadSegmentIdx(0), &roads, 1., 10.);
+
+        assert_eq!(diagram.len(), 2);
+        assert_eq!((diagram[1].flow, diagram[1].density, diagram[1].speed), (0., 0., None));
+    }
+}
@@ -0,0 +1,83 @@
+use std::{fs, io, path::Path};
+use macroquad::color::{Color, WHITE};
+use crate::output::canvas::Canvas;
+
+pub enum Shape {
+    Line { from: (f32, f32), to: (f32, f32), width: f32, color: Color },
+    Circle { center: (f32, f32), radius: f32, color: Color },
+    Text { position: (f32, f32), text: String, size: f32, color: Color },
+}
+
+// Vector drawing, in pixels with y pointing down, that can be exported to SVG or PNG
+pub struct Figure {
+    pub width: u32,
+    pub height: u32,
+    pub shapes: Vec<Shape>,
+}
+
+impl Figure {
+    pub fn new(width: u32, height: u32) -> Self { Self { width, height, shapes: Vec::new() } }
+
+    pub fn line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: Color) {
+        self.shapes.push(Shape::Line { from, to, width, color });
+    }
+
+    pub fn circle(&mut self, center: (f32, f32), radius: f32, color: Color) {
+        self.shapes.push(Shape::Circle { center, radius, color });
+    }
+
+    pub fn text(&mut self, position: (f32, f32), text: impl Into<String>, size: f32, color: Color) {
+        self.shapes.push(Shape::Text { position, text: text.into(), size, color });
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n", self.width, self.height);
+        svg += &format!("<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n", svg_color(WHITE));
+        for shape in &self.shapes {
+            svg += &match shape {
+                Shape::Line { from, to, width, color } => format!(
+                    "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-opacity=\"{:.2}\" stroke-width=\"{}\"/>\n",
+                    from.0, from.1, to.0, to.1, svg_color(*color), color.a, width
+                ),
+                Shape::Circle { center, radius, color } => format!(
+                    "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{}\" fill=\"{}\" fill-opacity=\"{:.2}\"/>\n",
+                    center.0, center.1, radius, svg_color(*color), color.a
+                ),
+                Shape::Text { position, text, size, color } => format!(
+                    "<text x=\"{:.1}\" y=\"{:.1}\" font-family=\"sans-serif\" font-size=\"{}\" fill=\"{}\">{}</text>\n",
+                    position.0, position.1, size, svg_color(*color), escape_xml(text)
+                ),
+            };
+        }
+        svg += "</svg>\n";
+
+        svg
+    }
+
+    pub fn write_svg(&self, path: impl AsRef<Path>) -> io::Result<()> { fs::write(path, self.to_svg()) }
+
+    // Text is drawn with a bitmap font, so it looks plainer than in SVG
+    pub fn to_canvas(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.width, self.height, WHITE);
+        for shape in &self.shapes {
+            match shape {
+                Shape::Line { from, to, width, color } => canvas.draw_line(from.0, from.1, to.0, to.1, *width, *color),
+                Shape::Circle { center, radius, color } => canvas.fill_circle(center.0, center.1, *radius, *color),
+                Shape::Text { position, text, size, color } => canvas.draw_text(position.0, position.1, text, *size, *color),
+            }
+        }
+
+        canvas
+    }
+
+    pub fn write_png(&self, path: impl AsRef<Path>) -> image::ImageResult<()> { self.to_canvas().save_png(path) }
+}
+
+fn svg_color(color: Color) -> String {
+    let [r, g, b, _a]: [u8; 4] = color.into();
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,167 @@
+use std::{collections::HashMap, fs::File, io::{self, BufWriter, Write}, path::{Path, PathBuf}};
+use image::RgbaImage;
+use macroquad::color::{Color, BLACK};
+use crate::{agent::{car::Car, vehicle::{VehicleClass, VehicleType}}, gui, output::canvas::Canvas, road};
+
+const FRAME_MARGIN: f32 = 20.; // px, around the network
+
+// Draws like gui::Window, on a Canvas rather than the screen, so that frames can be made without a display or a GPU.
+// The view is fitted to the network and doesn't move.
+pub struct FrameRenderer {
+    canvas: Canvas,
+    center: (f32, f32), // m
+    scale: f32, // px / m
+    // Indexed by sprite path
+    vehicle_sprites: HashMap<&'static str, RgbaImage>,
+}
+
+// Renders the simulation at a regular interval, to numbered PNG files and optionally to an animated GIF
+pub struct FrameRecorder {
+    renderer: FrameRenderer,
+    directory: PathBuf,
+    interval: f32,
+    next_record_time: f32,
+    frame_count: usize,
+    // With the frame delay, in hundredths of a second
+    gif: Option<(gif::Encoder<BufWriter<File>>, u16)>,
+}
+
+impl FrameRenderer {
+    pub fn new(width: u32, height: u32, roads: &road::Roads) -> image::ImageResult<Self> {
+        let points: Vec<(f32, f32)> = (0..roads.segment_count()).flat_map(|segment| roads.segment_polyline(road::RoadSegmentIdx(segment))).collect();
+        let (min_x, max_x) = points.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), (x, _y)| (min.min(*x), max.max(*x)));
+        let (min_y, max_y) = points.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), (_x, y)| (min.min(*y), max.max(*y)));
+        let (center, scale) = if points.is_empty() {
+            ((0., 0.), 1.)
+        } else {
+            let scale_x = (width as f32 - 2. * FRAME_MARGIN) / (max_x - min_x).max(1.);
+            let scale_y = (height as f32 - 2. * FRAME_MARGIN) / (max_y - min_y).max(1.);
+            (((min_x + max_x) / 2., (min_y + max_y) / 2.), scale_x.min(scale_y))
+        };
+        let mut vehicle_sprites = HashMap::new();
+        for class in VehicleClass::ALL {
+            let sprite = class.default_type().sprite;
+            if !vehicle_sprites.contains_key(sprite) {
+                vehicle_sprites.insert(sprite, image::open(sprite)?.to_rgba8());
+            }
+        }
+
+        Ok(Self { canvas: Canvas::new(width, height, BLACK), center, scale, vehicle_sprites })
+    }
+
+    pub fn canvas(&self) -> &Canvas { &self.canvas }
+
+    pub fn clear(&mut self) {
+        self.canvas.clear(BLACK);
+    }
+
+    pub fn draw_road_segment(&mut self, start: (f32, f32), end: (f32, f32), style: gui::RoadSegmentStyle) {
+        let (start, end) = (self.to_pixel(start), self.to_pixel(end));
+        self.canvas.draw_line(start.0, start.1, end.0, end.1, gui::ROAD_THICKNESS, gui::road_segment_color(style));
+    }
+
+    pub fn draw_sign(&mut self, xy: (f32, f32), sign_type: &road::SignType) {
+        let (x, y) = self.to_pixel(xy);
+        self.canvas.fill_rectangle(x - gui::SIGN_SIZE / 2., y - gui::SIGN_SIZE / 2., gui::SIGN_SIZE, gui::SIGN_SIZE, gui::sign_color(sign_type));
+    }
+
+    // xy is the center of the vehicle, heading counterclockwise from the x axis in radians
+    pub fn draw_car(&mut self, xy: (f32, f32), heading: f32, vehicle_type: &VehicleType) {
+        let (x, y) = self.to_pixel(xy);
+        let size = ((vehicle_type.length * self.scale).max(gui::MIN_VEHICLE_SIZE), (vehicle_type.width * self.scale).max(gui::MIN_VEHICLE_SIZE));
+        let sprite = &self.vehicle_sprites[vehicle_type.sprite];
+        // Along and across the vehicle, on the screen where y points down
+        let (along, across) = ((heading.cos(), -heading.sin()), (heading.sin(), heading.cos()));
+        let reach = size.0.hypot(size.1) / 2.;
+        for py in (y - reach).floor() as i32..=(y + reach).ceil() as i32 {
+            for px in (x - reach).floor() as i32..=(x + reach).ceil() as i32 {
+                let (dx, dy) = (px as f32 + 0.5 - x, py as f32 + 0.5 - y);
+                let (u, v) = ((dx * along.0 + dy * along.1) / size.0 + 0.5, (dx * across.0 + dy * across.1) / size.1 + 0.5);
+                if !(0. ..1.).contains(&u) || !(0. ..1.).contains(&v) {
+                    continue;
+                }
+                let texel = sprite.get_pixel((u * sprite.width() as f32) as u32, (v * sprite.height() as f32) as u32);
+                let [r, g, b, a] = texel.0.map(|channel| channel as f32 / 255.);
+                let tint = vehicle_type.tint;
+                self.canvas.blend_pixel(px, py, Color::new(r * tint.r, g * tint.g, b * tint.b, a * tint.a));
+            }
+        }
+    }
+
+    // The network, its signs and the cars, as the window shows them
+    pub fn render(&mut self, roads: &road::Roads, cars: &[Car]) {
+        self.clear();
+        for segment in (0..roads.segment_count()).map(road::RoadSegmentIdx) {
+            let style = roads.segment_style(segment);
+            for piece in roads.segment_polyline(segment).windows(2) {
+                self.draw_road_segment(piece[0], piece[1], style);
+            }
+            for sign in roads.signs(segment) {
+                self.draw_sign(roads.get_position_xy(&sign.position), &sign.sign_type);
+            }
+        }
+        for car in cars {
+            self.draw_car(car.center_xy(roads), car.heading(roads), car.vehicle_type());
+        }
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> image::ImageResult<()> { self.canvas.save_png(path) }
+
+    fn to_pixel(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (self.canvas.width() as f32 / 2. + (x - self.center.0) * self.scale, self.canvas.height() as f32 / 2. - (y - self.center.1) * self.scale)
+    }
+}
+
+impl FrameRecorder {
+    pub fn create(directory: impl AsRef<Path>, renderer: FrameRenderer, interval: f32) -> io::Result<Self> {
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self { renderer, directory: directory.as_ref().to_path_buf(), interval, next_record_time: 0., frame_count: 0, gif: None })
+    }
+
+    // Each frame shown for frame_delay, in s of real time
+    pub fn write_gif_to(&mut self, path: impl AsRef<Path>, frame_delay: f32) -> image::ImageResult<()> {
+        let canvas = self.renderer.canvas();
+        let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?), canvas.width() as u16, canvas.height() as u16, &[]).map_err(gif_error)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+        self.gif = Some((encoder, (frame_delay * 100.).round() as u16));
+
+        Ok(())
+    }
+
+    pub fn frame_count(&self) -> usize { self.frame_count }
+
+    pub fn is_due(&self, time: f32) -> bool { time >= self.next_record_time }
+
+    pub fn record(&mut self, time: f32, roads: &road::Roads, cars: &[Car]) -> image::ImageResult<()> {
+        self.renderer.render(roads, cars);
+        self.renderer.save_png(self.directory.join(format!("frame_{:05}.png", self.frame_count)))?;
+        if let Some((encoder, delay)) = &mut self.gif {
+            let canvas = self.renderer.canvas();
+            let mut frame = gif::Frame::from_rgba(canvas.width() as u16, canvas.height() as u16, &mut canvas.pixels().to_vec());
+            frame.delay = *delay;
+            encoder.write_frame(&frame).map_err(gif_error)?;
+        }
+        self.frame_count += 1;
+        while self.next_record_time <= time {
+            self.next_record_time += self.interval;
+        }
+
+        Ok(())
+    }
+
+    // Completes the GIF, whose trailer is only written when its encoder is taken apart
+    pub fn finish(mut self) -> image::ImageResult<()> {
+        if let Some((encoder, _delay)) = self.gif.take() {
+            encoder.into_inner()?.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn gif_error(error: gif::EncodingError) -> image::ImageError {
+    match error {
+        gif::EncodingError::Io(error) => image::ImageError::IoError(error),
+        error => image::ImageError::Encoding(image::error::EncodingError::new(image::ImageFormat::Gif.into(), error)),
+    }
+}
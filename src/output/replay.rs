@@ -0,0 +1,209 @@
+use std::{fs::File, io::{self, BufReader, BufWriter, Read, Write}, path::Path};
+use crate::{agent::{car::Car, pedestrian::Pedestrian, vehicle::{VehicleClass, VehicleType}}, road::{RoadSegmentIdx, Roads}};
+
+const REPLAY_MAGIC: &[u8; 4] = b"TSRP";
+const REPLAY_VERSION: u8 = 2;
+const FRAME_HEADER_SIZE: usize = 16;
+const VEHICLE_RECORD_SIZE: usize = 29;
+const PEDESTRIAN_RECORD_SIZE: usize = 8;
+const SEGMENT_RECORD_SIZE: usize = 9;
+
+// Where a vehicle was drawn at the time of a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VehicleState {
+    pub car: usize,
+    pub class: VehicleClass,
+    pub x: f32, // m, middle of the vehicle
+    pub y: f32, // m
+    pub heading: f32, // rad
+    pub speed: f32, // m/s
+    pub length: f32, // m
+    pub width: f32, // m
+}
+
+// A closed RoadSegment or a work zone at the time of a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SegmentState {
+    pub segment: RoadSegmentIdx,
+    pub closed: bool,
+    pub capacity_factor: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub time: f32,
+    pub vehicles: Vec<VehicleState>,
+    pub pedestrians: Vec<(f32, f32)>, // m
+    // RoadSegments which are not open at full capacity
+    pub segments: Vec<SegmentState>,
+}
+
+// Records the network, the vehicles and the pedestrians at a regular interval, so that a run can be watched later
+// without simulating it again. Little-endian binary file: a 5-byte header, the length and text of the map, then the
+// frames, each being the time, the vehicle, pedestrian and RoadSegment counts, then a record per vehicle, pedestrian
+// and closed or work zone RoadSegment.
+pub struct ReplayWriter {
+    writer: BufWriter<File>,
+    interval: f32,
+    next_record_time: f32,
+}
+
+// A recorded run, loaded in memory
+pub struct Replay {
+    pub roads: Roads,
+    // By time
+    pub frames: Vec<Frame>,
+}
+
+impl VehicleState {
+    pub fn vehicle_type(&self) -> VehicleType {
+        VehicleType { length: self.length, width: self.width, ..self.class.default_type() }
+    }
+}
+
+impl ReplayWriter {
+    pub fn create(path: impl AsRef<Path>, roads: &Roads, interval: f32) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(REPLAY_MAGIC)?;
+        writer.write_all(&[REPLAY_VERSION])?;
+        let mut map = Vec::new();
+        roads.write_map(&mut map)?;
+        writer.write_all(&(map.len() as u32).to_le_bytes())?;
+        writer.write_all(&map)?;
+
+        Ok(Self { writer, interval, next_record_time: 0. })
+    }
+
+    // Half an interval early, so that recording every step isn't thrown off by rounding errors in the clock
+    pub fn is_due(&self, time: f32) -> bool { time + self.interval / 2. >= self.next_record_time }
+
+    pub fn record(&mut self, time: f32, cars: &[Car], pedestrians: &[Pedestrian], roads: &Roads) -> io::Result<()> {
+        let segments: Vec<RoadSegmentIdx> = (0..roads.segment_count()).map(RoadSegmentIdx).filter(|segment| roads.is_closed(*segment) || roads.capacity_factor(*segment) < 1.).collect();
+        let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + cars.len() * VEHICLE_RECORD_SIZE + pedestrians.len() * PEDESTRIAN_RECORD_SIZE + segments.len() * SEGMENT_RECORD_SIZE);
+        frame.extend_from_slice(&time.to_le_bytes());
+        for count in [cars.len(), pedestrians.len(), segments.len()] {
+            frame.extend_from_slice(&(count as u32).to_le_bytes());
+        }
+        for car in cars {
+            let (x, y) = car.center_xy(roads);
+            let vehicle_type = car.vehicle_type();
+            frame.extend_from_slice(&(car.id() as u32).to_le_bytes());
+            frame.push(VehicleClass::ALL.iter().position(|class| *class == vehicle_type.class).expect("Could not find the vehicle class") as u8);
+            for value in [x, y, car.heading(roads), car.speed(), vehicle_type.length, vehicle_type.width] {
+                frame.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        for pedestrian in pedestrians {
+            let (x, y) = pedestrian.position_xy(roads);
+            frame.extend_from_slice(&x.to_le_bytes());
+            frame.extend_from_slice(&y.to_le_bytes());
+        }
+        for segment in segments {
+            frame.extend_from_slice(&(*segment as u32).to_le_bytes());
+            frame.push(roads.is_closed(segment) as u8);
+            frame.extend_from_slice(&roads.capacity_factor(segment).to_le_bytes());
+        }
+        self.writer.write_all(&frame)?;
+        while self.is_due(time) {
+            self.next_record_time += self.interval;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> { self.writer.flush() }
+}
+
+impl Replay {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let file = File::open(path)?;
+        let file_length = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut header = [0; 9];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != REPLAY_MAGIC || header[4] != REPLAY_VERSION {
+            return Err(invalid("Not a replay file, or unsupported version"));
+        }
+        let map_length = u32::from_le_bytes([header[5], header[6], header[7], header[8]]) as u64;
+        if map_length > file_length - header.len() as u64 {
+            return Err(invalid("Map longer than the replay file"));
+        }
+        let mut map = vec![0; map_length as usize];
+        reader.read_exact(&mut map)?;
+        let roads = Roads::read_map(&map[..])?;
+
+        let mut frames = Vec::new();
+        let mut frame_header = [0; FRAME_HEADER_SIZE];
+        let mut vehicle_record = [0; VEHICLE_RECORD_SIZE];
+        let mut pedestrian_record = [0; PEDESTRIAN_RECORD_SIZE];
+        let mut segment_record = [0; SEGMENT_RECORD_SIZE];
+        let bytes = |record: &[u8], i: usize| [record[i], record[i + 1], record[i + 2], record[i + 3]];
+        // A run interrupted while writing leaves a partial last frame, which is dropped. The counts are not trusted to
+        // size the buffers, records being pushed as they are read.
+        'frames: loop {
+            if !read_record(&mut reader, &mut frame_header)? {
+                break;
+            }
+            let time = f32::from_le_bytes(bytes(&frame_header, 0));
+            let [vehicle_count, pedestrian_count, segment_count] = [4, 8, 12].map(|i| u32::from_le_bytes(bytes(&frame_header, i)) as usize);
+            let mut vehicles = Vec::new();
+            for _ in 0..vehicle_count {
+                if !read_record(&mut reader, &mut vehicle_record)? {
+                    break 'frames;
+                }
+                let field = |i: usize| f32::from_le_bytes(bytes(&vehicle_record, 5 + 4 * i));
+                vehicles.push(VehicleState {
+                    car: u32::from_le_bytes(bytes(&vehicle_record, 0)) as usize,
+                    class: *VehicleClass::ALL.get(vehicle_record[4] as usize).ok_or_else(|| invalid("Unknown vehicle class"))?,
+                    x: field(0),
+                    y: field(1),
+                    heading: field(2),
+                    speed: field(3),
+                    length: field(4),
+                    width: field(5),
+                });
+            }
+            let mut pedestrians = Vec::new();
+            for _ in 0..pedestrian_count {
+                if !read_record(&mut reader, &mut pedestrian_record)? {
+                    break 'frames;
+                }
+                pedestrians.push((f32::from_le_bytes(bytes(&pedestrian_record, 0)), f32::from_le_bytes(bytes(&pedestrian_record, 4))));
+            }
+            let mut segments = Vec::new();
+            for _ in 0..segment_count {
+                if !read_record(&mut reader, &mut segment_record)? {
+                    break 'frames;
+                }
+                let segment = RoadSegmentIdx(u32::from_le_bytes(bytes(&segment_record, 0)) as usize);
+                if *segment >= roads.segment_count() {
+                    return Err(invalid("RoadSegment out of the map"));
+                }
+                segments.push(SegmentState { segment, closed: segment_record[4] != 0, capacity_factor: f32::from_le_bytes(bytes(&segment_record, 5)) });
+            }
+            frames.push(Frame { time, vehicles, pedestrians, segments });
+        }
+
+        Ok(Self { roads, frames })
+    }
+
+    pub fn start_time(&self) -> f32 { self.frames.first().map_or(0., |frame| frame.time) }
+
+    pub fn end_time(&self) -> f32 { self.frames.last().map_or(0., |frame| frame.time) }
+
+    // Latest frame at or before time, or the first one
+    pub fn frame_at(&self, time: f32) -> Option<&Frame> {
+        let next = self.frames.partition_point(|frame| frame.time <= time);
+        self.frames.get(next.saturating_sub(1))
+    }
+}
+
+// Fills the record, returning false at the end of the file
+fn read_record(reader: &mut impl Read, record: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(record) {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error),
+    }
+}
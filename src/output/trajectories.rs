@@ -0,0 +1,109 @@
+use std::{fs::File, io::{self, BufReader, BufWriter, Read, Write}, path::Path};
+use crate::road::{RoadPoint, RoadSegmentIdx};
+
+const BINARY_MAGIC: &[u8; 4] = b"TSTJ";
+const BINARY_VERSION: u8 = 1;
+const BINARY_RECORD_SIZE: usize = 36;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrajectoryFormat {
+    Csv,
+    // Little-endian records of 4-byte fields, after a 5-byte header
+    Binary,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrajectoryPoint {
+    pub car: usize,
+    pub time: f32,
+    pub position: RoadPoint,
+    pub x: f32,
+    pub y: f32,
+    pub speed: f32,
+    pub acceleration: f32,
+    pub target_speed: f32,
+}
+
+// Records the state of every car at a regular interval
+pub struct TrajectoryWriter {
+    writer: BufWriter<File>,
+    format: TrajectoryFormat,
+    interval: f32,
+    next_record_time: f32,
+}
+
+impl TrajectoryWriter {
+    pub fn create(path: impl AsRef<Path>, format: TrajectoryFormat, interval: f32) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        match format {
+            TrajectoryFormat::Csv => writeln!(writer, "car,time_s,segment,offset_m,x_m,y_m,speed_m_s,acceleration_m_s2,target_speed_m_s")?,
+            TrajectoryFormat::Binary => {
+                writer.write_all(BINARY_MAGIC)?;
+                writer.write_all(&[BINARY_VERSION])?;
+            },
+        }
+
+        Ok(Self { writer, format, interval, next_record_time: 0. })
+    }
+
+    pub fn is_due(&self, time: f32) -> bool { time >= self.next_record_time }
+
+    pub fn record(&mut self, time: f32, points: &[TrajectoryPoint]) -> io::Result<()> {
+        for point in points {
+            match self.format {
+                TrajectoryFormat::Csv => writeln!(
+                    self.writer, "{},{:.3},{},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3}",
+                    point.car, point.time, point.position.road_segment(), point.position.position(), point.x, point.y, point.speed, point.acceleration, point.target_speed
+                )?,
+                TrajectoryFormat::Binary => {
+                    let mut record = Vec::with_capacity(BINARY_RECORD_SIZE);
+                    record.extend_from_slice(&(point.car as u32).to_le_bytes());
+                    record.extend_from_slice(&point.time.to_le_bytes());
+                    record.extend_from_slice(&(*point.position.road_segment() as u32).to_le_bytes());
+                    for value in [point.position.position(), point.x, point.y, point.speed, point.acceleration, point.target_speed] {
+                        record.extend_from_slice(&value.to_le_bytes());
+                    }
+                    self.writer.write_all(&record)?;
+                },
+            }
+        }
+        while self.next_record_time <= time {
+            self.next_record_time += self.interval;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> { self.writer.flush() }
+}
+
+pub fn read_binary(path: impl AsRef<Path>) -> io::Result<Vec<TrajectoryPoint>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != BINARY_MAGIC || header[4] != BINARY_VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a trajectory file, or unsupported version"));
+    }
+    let mut points = Vec::new();
+    let mut record = [0; BINARY_RECORD_SIZE];
+    loop {
+        match reader.read_exact(&mut record) {
+            Ok(()) => {},
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(error) => return Err(error),
+        }
+        let field = |i: usize| [record[4 * i], record[4 * i + 1], record[4 * i + 2], record[4 * i + 3]];
+        points.push(TrajectoryPoint {
+            car: u32::from_le_bytes(field(0)) as usize,
+            time: f32::from_le_bytes(field(1)),
+            position: RoadPoint::new(RoadSegmentIdx(u32::from_le_bytes(field(2)) as usize), f32::from_le_bytes(field(3))),
+            x: f32::from_le_bytes(field(4)),
+            y: f32::from_le_bytes(field(5)),
+            speed: f32::from_le_bytes(field(6)),
+            acceleration: f32::from_le_bytes(field(7)),
+            target_speed: f32::from_le_bytes(field(8)),
+        });
+    }
+
+    Ok(points)
+}
@@ -0,0 +1,162 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+use crate::{emissions::Emissions, snapshot::{Snapshot, SnapshotReader, SnapshotWriter}};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TripRecord {
+    pub departure_time: f32,
+    pub arrival_time: f32,
+    pub distance: f32,
+    pub stops: usize,
+    // Time the trip would have taken driving at the speed limit all along
+    pub free_flow_time: f32,
+    pub emissions: Emissions,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NetworkSummary {
+    pub trip_count: usize,
+    pub mean_travel_time: f32,
+    pub median_travel_time: f32,
+    pub p85_travel_time: f32,
+    pub p95_travel_time: f32,
+    // Driven by all the cars, including the trips not finished yet
+    pub vehicle_kilometres: f32,
+    pub total_delay: f32,
+    // Emitted by all the cars, including the trips not finished yet
+    pub emissions: Emissions,
+}
+
+#[derive(Default)]
+pub struct TripStatistics {
+    // Index of the car, and its finished trip
+    trips: Vec<(usize, TripRecord)>,
+}
+
+impl TripRecord {
+    pub fn travel_time(&self) -> f32 { self.arrival_time - self.departure_time }
+
+    pub fn delay(&self) -> f32 { (self.travel_time() - self.free_flow_time).max(0.) }
+}
+
+impl TripStatistics {
+    pub fn new() -> Self { Self { trips: Vec::new() } }
+
+    pub fn add(&mut self, car: usize, trip: TripRecord) {
+        self.trips.push((car, trip));
+    }
+
+    pub fn trips(&self) -> &[(usize, TripRecord)] { &self.trips }
+
+    // unfinished_delay and unfinished_emissions are the ones of the trips still in progress
+    pub fn summary(&self, total_distance: f32, unfinished_delay: f32, unfinished_emissions: Emissions) -> NetworkSummary {
+        let mut travel_times: Vec<f32> = self.trips.iter().map(|(_car, trip)| trip.travel_time()).collect();
+        travel_times.sort_by(f32::total_cmp);
+        let mut emissions = unfinished_emissions;
+        for (_car, trip) in &self.trips {
+            emissions += trip.emissions;
+        }
+        let mean_travel_time = if travel_times.is_empty() { 0. } else { travel_times.iter().sum::<f32>() / travel_times.len() as f32 };
+        NetworkSummary {
+            trip_count: self.trips.len(),
+            mean_travel_time,
+            median_travel_time: percentile(&travel_times, 0.5),
+            p85_travel_time: percentile(&travel_times, 0.85),
+            p95_travel_time: percentile(&travel_times, 0.95),
+            vehicle_kilometres: total_distance / 1000.,
+            total_delay: self.trips.iter().map(|(_car, trip)| trip.delay()).sum::<f32>() + unfinished_delay,
+            emissions,
+        }
+    }
+
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "car,departure_s,arrival_s,travel_time_s,distance_m,stops,free_flow_time_s,delay_s,fuel_ml,co2_g,nox_g,pm_g")?;
+        for (car, trip) in &self.trips {
+            writeln!(
+                writer, "{},{:.2},{:.2},{:.2},{:.1},{},{:.2},{:.2},{:.1},{:.2},{:.4},{:.6}",
+                car, trip.departure_time, trip.arrival_time, trip.travel_time(), trip.distance, trip.stops, trip.free_flow_time, trip.delay(),
+                trip.emissions.fuel, trip.emissions.co2, trip.emissions.nox, trip.emissions.pm
+            )?;
+        }
+
+        writer.flush()
+    }
+}
+
+impl NetworkSummary {
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "trips,{}", self.trip_count)?;
+        writeln!(writer, "mean_travel_time_s,{:.2}", self.mean_travel_time)?;
+        writeln!(writer, "median_travel_time_s,{:.2}", self.median_travel_time)?;
+        writeln!(writer, "p85_travel_time_s,{:.2}", self.p85_travel_time)?;
+        writeln!(writer, "p95_travel_time_s,{:.2}", self.p95_travel_time)?;
+        writeln!(writer, "vehicle_kilometres,{:.3}", self.vehicle_kilometres)?;
+        writeln!(writer, "total_delay_s,{:.2}", self.total_delay)?;
+        writeln!(writer, "fuel_l,{:.3}", self.emissions.fuel / 1000.)?;
+        writeln!(writer, "co2_kg,{:.3}", self.emissions.co2 / 1000.)?;
+        writeln!(writer, "nox_g,{:.3}", self.emissions.nox)?;
+        writeln!(writer, "pm_g,{:.5}", self.emissions.pm)?;
+
+        writer.flush()
+    }
+}
+
+// Nearest-rank percentile of sorted values
+fn percentile(sorted_values: &[f32], percentile: f32) -> f32 {
+    if sorted_values.is_empty() {
+        return 0.;
+    }
+    let rank = (percentile * sorted_values.len() as f32).ceil() as usize;
+
+    sorted_values[rank.clamp(1, sorted_values.len()) - 1]
+}
+
+impl Snapshot for TripRecord {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.departure_time);
+        writer.write(&self.arrival_time);
+        writer.write(&self.distance);
+        writer.write(&self.stops);
+        writer.write(&self.free_flow_time);
+        writer.write(&self.emissions);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self { departure_time: reader.read()?, arrival_time: reader.read()?, distance: reader.read()?, stops: reader.read()?, free_flow_time: reader.read()?, emissions: reader.read()? })
+    }
+}
+
+impl Snapshot for TripStatistics {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.trips);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { trips: reader.read()? }) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emissions::Emissions;
+    use super::{TripRecord, TripStatistics};
+
+    fn trip(travel_time: f32, free_flow_time: f32) -> TripRecord {
+        TripRecord { departure_time: 10., arrival_time: 10. + travel_time, distance: 100., stops: 0, free_flow_time, emissions: Emissions::default() }
+    }
+
+    #[test]
+    fn summary_adds_the_delay_of_unfinished_trips() {
+        let mut trips = TripStatistics::new();
+        // Trips faster than free flow have no delay
+        for (car, (travel_time, free_flow_time)) in [(40., 10.), (10., 10.), (20., 10.), (5., 10.)].into_iter().enumerate() {
+            trips.add(car, trip(travel_time, free_flow_time));
+        }
+        let summary = trips.summary(2500., 7., Emissions::default());
+
+        assert_eq!(summary.trip_count, 4);
+        assert_eq!(summary.mean_travel_time, 18.75);
+        assert_eq!((summary.median_travel_time, summary.p85_travel_time, summary.p95_travel_time), (10., 40., 40.));
+        assert_eq!(summary.total_delay, 30. + 0. + 10. + 0. + 7.);
+        assert_eq!(summary.vehicle_kilometres, 2.5);
+    }
+}
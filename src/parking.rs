@@ -0,0 +1,267 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+use crate::{agent::car::Car, gui, output::EventLog, road, snapshot::{Snapshot, SnapshotReader, SnapshotWriter}};
+
+// Cars searching for parking enter a garage when this close to its RoadNode
+const GARAGE_ENTRY_DISTANCE: f32 = 5.; // m
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParkingLocation {
+    // Spaces along a segment, between two positions
+    OnStreet { segment: road::RoadSegmentIdx, start: f32, end: f32 },
+    Garage { node: road::RoadNodeIdx },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParkingFacility {
+    pub location: ParkingLocation,
+    pub capacity: usize,
+    occupied: usize,
+    peak_occupied: usize,
+    // Integral of the occupancy over time, for the mean occupancy
+    occupied_time: f32, // veh.s
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParkingRecord {
+    pub car: usize,
+    pub facility: usize,
+    pub search_start: f32,
+    pub parked_time: f32,
+    // Driven while searching
+    pub cruising_distance: f32, // m
+    pub release_time: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParkingSummary {
+    pub parked_cars: usize,
+    pub mean_search_time: f32, // s
+    pub cruising_kilometres: f32,
+    // Share of the vehicle-kilometres driven to search for parking
+    pub cruising_share: f32,
+}
+
+#[derive(Default)]
+pub struct Parking {
+    facilities: Vec<ParkingFacility>,
+    records: Vec<ParkingRecord>,
+    // Cars still parked, with the index of their record
+    parked: Vec<(usize, Car)>,
+}
+
+impl ParkingFacility {
+    pub fn new(location: ParkingLocation, capacity: usize) -> Self {
+        Self { location, capacity, occupied: 0, peak_occupied: 0, occupied_time: 0. }
+    }
+
+    pub fn occupied(&self) -> usize { self.occupied }
+
+    pub fn is_full(&self) -> bool { self.occupied >= self.capacity }
+
+    fn is_valid_on(&self, roads: &road::Roads) -> bool {
+        match self.location {
+            ParkingLocation::OnStreet { segment, start, end } => roads.contains(&road::RoadPoint::new(segment, start)) && roads.contains(&road::RoadPoint::new(segment, end)),
+            ParkingLocation::Garage { node } => roads.has_node(node),
+        }
+    }
+
+    // Whether a car at position can park in the facility, were there a free space
+    fn is_reachable_from(&self, position: &road::RoadPoint, roads: &road::Roads) -> bool {
+        match self.location {
+            ParkingLocation::OnStreet { segment, start, end } => position.road_segment() == segment && (start..=end).contains(&position.position()),
+            ParkingLocation::Garage { node } => roads.segment_nodes(position.road_segment()).1 == node
+                && roads.segment_length(position.road_segment()) - position.position() <= GARAGE_ENTRY_DISTANCE,
+        }
+    }
+
+    pub fn render(&self, window: &gui::Window, roads: &road::Roads) {
+        match self.location {
+            ParkingLocation::OnStreet { segment, start, end } => window.draw_parking_spaces(
+                roads.get_position_xy(&road::RoadPoint::new(segment, start)), roads.get_position_xy(&road::RoadPoint::new(segment, end)), self.is_full()
+            ),
+            ParkingLocation::Garage { node } => window.draw_garage(roads.node_xy(node), self.is_full()),
+        }
+    }
+}
+
+impl Parking {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn add_facility(&mut self, facility: ParkingFacility) {
+        self.facilities.push(facility);
+    }
+
+    pub fn facilities(&self) -> &[ParkingFacility] { &self.facilities }
+
+    pub fn records(&self) -> &[ParkingRecord] { &self.records }
+
+    pub fn parked_cars(&self) -> impl Iterator<Item = &Car> { self.parked.iter().map(|(_record, car)| car) }
+
+    // Whether the facilities fit in the network, the records and parked cars refer to existing facilities and records,
+    // and each facility counts at least the cars parked in it
+    pub fn is_valid_on(&self, roads: &road::Roads) -> bool {
+        let mut parked_per_facility = vec![0; self.facilities.len()];
+        for (record, _car) in &self.parked {
+            match self.records.get(*record).and_then(|record| parked_per_facility.get_mut(record.facility)) {
+                Some(parked) => *parked += 1,
+                None => return false,
+            }
+        }
+        self.facilities.iter().zip(parked_per_facility).all(|(facility, parked)| facility.is_valid_on(roads) && facility.occupied >= parked)
+            && self.records.iter().all(|record| record.facility < self.facilities.len())
+            && self.parked_cars().all(|car| car.is_valid_on(roads))
+    }
+
+    pub fn update(&mut self, step_size: f32) {
+        for facility in &mut self.facilities {
+            facility.occupied_time += facility.occupied as f32 * step_size;
+        }
+    }
+
+    // Takes out the cars whose parking duration is over, freeing their spaces. can_leave is given each of them and
+    // those already leaving during this call, a car staying parked until it returns true.
+    pub fn release(&mut self, time: f32, log: &mut EventLog, mut can_leave: impl FnMut(&mut Car, &[Car]) -> bool) -> Vec<Car> {
+        let mut leaving = Vec::new();
+        let mut i = 0;
+        while i < self.parked.len() {
+            let (record, car) = &mut self.parked[i];
+            let record = self.records[*record];
+            if record.release_time > time || !can_leave(car, &leaving) {
+                i += 1;
+                continue;
+            }
+            self.facilities[record.facility].occupied -= 1;
+            log.log(time, format!("car {} left parking {}", record.car, record.facility));
+            leaving.push(self.parked.remove(i).1);
+        }
+
+        leaving
+    }
+
+    // The facility where the car can park, if it is searching for parking and one with a free space is within reach
+    pub fn free_facility(&self, car: &Car, roads: &road::Roads) -> Option<usize> {
+        car.parking_search_start()?;
+        self.facilities.iter().position(|facility| !facility.is_full() && facility.is_reachable_from(car.position(), roads))
+    }
+
+    // Parks the car in the facility, found with free_facility, until its parking duration is over
+    pub fn park(&mut self, facility: usize, car: Car, time: f32) {
+        let (search_start, duration) = car.parking_search_start().zip(car.parking_duration()).expect("Could not park a car not searching for parking");
+        let parked_facility = &mut self.facilities[facility];
+        parked_facility.occupied += 1;
+        parked_facility.peak_occupied = parked_facility.peak_occupied.max(parked_facility.occupied);
+        self.records.push(ParkingRecord { car: car.id(), facility, search_start, parked_time: time, cruising_distance: car.cruising_distance(), release_time: time + duration });
+        self.parked.push((self.records.len() - 1, car));
+    }
+
+    // total_distance is the one driven by all the cars, in m
+    pub fn summary(&self, total_distance: f32) -> ParkingSummary {
+        let cruising_distance: f32 = self.records.iter().map(|record| record.cruising_distance).sum();
+        ParkingSummary {
+            parked_cars: self.records.len(),
+            mean_search_time: self.records.iter().map(|record| record.parked_time - record.search_start).sum::<f32>() / self.records.len().max(1) as f32,
+            cruising_kilometres: cruising_distance / 1000.,
+            cruising_share: if total_distance > 0. { cruising_distance / total_distance } else { 0. },
+        }
+    }
+
+    pub fn write_records(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "car,facility,search_start_s,parked_s,search_time_s,cruising_distance_m,release_s")?;
+        for record in &self.records {
+            writeln!(
+                writer, "{},{},{:.2},{:.2},{:.2},{:.1},{:.2}",
+                record.car, record.facility, record.search_start, record.parked_time, record.parked_time - record.search_start, record.cruising_distance, record.release_time
+            )?;
+        }
+
+        writer.flush()
+    }
+
+    // elapsed is the simulated time, for the mean occupancies
+    pub fn write_summary(&self, path: impl AsRef<Path>, total_distance: f32, elapsed: f32) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let summary = self.summary(total_distance);
+        writeln!(writer, "parked_cars,{}", summary.parked_cars)?;
+        writeln!(writer, "mean_search_time_s,{:.2}", summary.mean_search_time)?;
+        writeln!(writer, "cruising_kilometres,{:.3}", summary.cruising_kilometres)?;
+        writeln!(writer, "cruising_share,{:.4}", summary.cruising_share)?;
+        writeln!(writer)?;
+        writeln!(writer, "facility,capacity,occupied,peak_occupied,mean_occupancy")?;
+        for (i, facility) in self.facilities.iter().enumerate() {
+            let mean_occupancy = if elapsed > 0. && facility.capacity > 0 { facility.occupied_time / elapsed / facility.capacity as f32 } else { 0. };
+            writeln!(writer, "{},{},{},{},{:.3}", i, facility.capacity, facility.occupied, facility.peak_occupied, mean_occupancy)?;
+        }
+
+        writer.flush()
+    }
+
+    pub fn render(&self, window: &gui::Window, roads: &road::Roads) {
+        for facility in &self.facilities {
+            facility.render(window, roads);
+        }
+    }
+}
+
+impl Snapshot for Parking {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.facilities);
+        writer.write(&self.records);
+        writer.write(&self.parked);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { facilities: reader.read()?, records: reader.read()?, parked: reader.read()? }) }
+}
+
+impl Snapshot for ParkingFacility {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.location);
+        writer.write(&self.capacity);
+        writer.write(&self.occupied);
+        writer.write(&self.peak_occupied);
+        writer.write(&self.occupied_time);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self { location: reader.read()?, capacity: reader.read()?, occupied: reader.read()?, peak_occupied: reader.read()?, occupied_time: reader.read()? })
+    }
+}
+
+impl Snapshot for ParkingLocation {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        match self {
+            ParkingLocation::OnStreet { segment, start, end } => {
+                writer.write(&0u8);
+                writer.write(segment);
+                writer.write(start);
+                writer.write(end);
+            },
+            ParkingLocation::Garage { node } => {
+                writer.write(&1u8);
+                writer.write(node);
+            },
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(match reader.read_variant(2)? {
+            0 => ParkingLocation::OnStreet { segment: reader.read()?, start: reader.read()?, end: reader.read()? },
+            _ => ParkingLocation::Garage { node: reader.read()? },
+        })
+    }
+}
+
+impl Snapshot for ParkingRecord {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.car);
+        writer.write(&self.facility);
+        writer.write(&self.search_start);
+        writer.write(&self.parked_time);
+        writer.write(&self.cruising_distance);
+        writer.write(&self.release_time);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self { car: reader.read()?, facility: reader.read()?, search_start: reader.read()?, parked_time: reader.read()?, cruising_distance: reader.read()?, release_time: reader.read()? })
+    }
+}
@@ -1,51 +1,89 @@
+pub mod events;
+pub mod map;
 pub mod path;
+pub mod travel_times;
 
-use std::{f32, fmt::Display, hash::Hash};
-use crate::gui;
+use std::{f32, fmt::Display, hash::Hash, io};
+use crate::{gui, snapshot::{self, Snapshot, SnapshotReader, SnapshotWriter}};
 use crate::generate_custom_vec;
 
 generate_custom_vec!(RoadNode, RoadNodeIdx);
 generate_custom_vec!(RoadSegment, RoadSegmentIdx);
 generate_custom_vec!(RoadVisualKeypoint, RoadVisualKeypointIdx);
 
-
+#[derive(Clone)]
 struct RoadSegment {
     from: RoadNodeIdx,
     to: RoadNodeIdx,
     length: f32,
     signs: Vec<Sign>,
     visual_keypoints: Vec<RoadVisualKeypoint>,
+    closed: bool,
+    // Share of the capacity left by a work zone, the time headways of the drivers being divided by it
+    capacity_factor: f32,
+    // Pedestrians can walk along the segment
+    sidewalk: bool,
+    bike_lane: BikeLane,
 }
 
+#[derive(Clone)]
 pub struct Roads {
     segments: Vec<RoadSegment>,
     nodes: Vec<RoadNode>,
+    crossings: Vec<Crossing>,
 }
 
+#[derive(Clone)]
 struct RoadVisualKeypoint {
     position: f32,
     x: f32,
     y: f32,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 struct RoadNode {
     x: f32,
     y: f32,
     road_segments: Vec<RoadSegmentIdx>,
 }
 
+#[derive(Clone)]
 pub struct Sign {
     pub sign_type: SignType,
     pub value: f32,
     pub position: RoadPoint,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum SignType {
     SpeedLimit,
     EndSpeedLimit,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BikeLane {
+    // Cyclists ride in the lane of the cars, which overtake them when it is safe
+    Shared,
+    // Cyclists and cars don't interact
+    Dedicated,
+}
+
+// Pedestrians cross the roads at a RoadNode, cars stop before it on the segments leading to it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crossing {
+    pub node: RoadNodeIdx,
+    pub kind: CrossingKind,
+    pub length: f32, // m, walked by the pedestrians
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CrossingKind {
+    // Cars yield to the pedestrians on or about to step on the crossing
+    Zebra,
+    // Pedestrians walk, and cars stop, during the first walk_time seconds of each cycle
+    Signalised { cycle: f32, walk_time: f32, offset: f32 },
+}
+
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub struct RoadPoint {
     road_segment: RoadSegmentIdx,
@@ -54,30 +92,39 @@ pub struct RoadPoint {
 
 impl Roads {
     pub fn new() -> Self {
-        let mut instance = Self { nodes: Vec::new(), segments: Vec::new() };
+        let mut instance = Self::empty();
         instance.init_roads();
 
         instance
     }
 
+    // A single RoadSegment going around a circle, from and to the same RoadNode
+    pub fn new_ring(circumference: f32) -> Self {
+        let mut instance = Self::empty();
+        instance.init_roads_circle(circumference / (2. * f32::consts::PI));
+
+        instance
+    }
+
+    // Without any RoadNode, to be built with the editing methods or loaded from a map file
+    pub fn empty() -> Self { Self { nodes: Vec::new(), segments: Vec::new(), crossings: Vec::new() } }
+
     pub fn get_position_xy(&self, position: &RoadPoint) -> (f32, f32) {
-        // (position of the keypoint on the segment, position x of the keypoint, position y of the keypoint)
-        let mut start: (f32, f32, f32) = (0., self.nodes[self.segments[position.road_segment].from].x, self.nodes[self.segments[position.road_segment].from].y);
-        let mut end: (f32, f32, f32) = (self.segments[position.road_segment].length, self.nodes[self.segments[position.road_segment].to].x, self.nodes[self.segments[position.road_segment].to].y);
-        for (i, visual_keypoint) in self.segments[position.road_segment].visual_keypoints.iter().enumerate() {
-            end = (visual_keypoint.position, visual_keypoint.x, visual_keypoint.y);
+        let segment = &self.segments[position.road_segment];
+        // (position of the keypoint on the segment, position x of the keypoint, position y of the keypoint), the RoadNodes included
+        let mut start: (f32, f32, f32) = (0., self.nodes[segment.from].x, self.nodes[segment.from].y);
+        let last = (segment.length, self.nodes[segment.to].x, self.nodes[segment.to].y);
+        let mut end = last;
+        for visual_keypoint in &segment.visual_keypoints {
             if visual_keypoint.position > position.position {
-                if i == 0 {
-                    start = (0., self.nodes[self.segments[position.road_segment].from].x, self.nodes[self.segments[position.road_segment].from].y);
-                } else {
-                    start = (self.segments[position.road_segment].visual_keypoints[i - 1].position, self.segments[position.road_segment].visual_keypoints[i - 1].x, self.segments[position.road_segment].visual_keypoints[i - 1].y);
-                }
+                end = (visual_keypoint.position, visual_keypoint.x, visual_keypoint.y);
                 break;
             }
+            start = (visual_keypoint.position, visual_keypoint.x, visual_keypoint.y);
         }
         let diff_x = end.1 - start.1;
         let diff_y = end.2 - start.2;
-        let progression_on_line = (position.position - start.0) / (end.0 - start.0);
+        let progression_on_line = if end.0 > start.0 { (position.position - start.0) / (end.0 - start.0) } else { 0. };
 
         (start.1 + diff_x * progression_on_line, start.2 + diff_y * progression_on_line)
     }
@@ -93,6 +140,228 @@ impl Roads {
         signs
     }
 
+    pub fn segment_count(&self) -> usize { self.segments.len() }
+
+    pub fn segment_length(&self, segment: RoadSegmentIdx) -> f32 { self.segments[segment].length }
+
+    pub fn node_count(&self) -> usize { self.nodes.len() }
+
+    pub fn node_xy(&self, node: RoadNodeIdx) -> (f32, f32) { (self.nodes[node].x, self.nodes[node].y) }
+
+    // Points the segment is drawn through, from its start RoadNode to its end one
+    pub fn segment_polyline(&self, segment: RoadSegmentIdx) -> Vec<(f32, f32)> {
+        let (from, to) = self.segment_nodes(segment);
+        std::iter::once(self.node_xy(from))
+            .chain(self.segments[segment].visual_keypoints.iter().map(|visual_keypoint| (visual_keypoint.x, visual_keypoint.y)))
+            .chain(std::iter::once(self.node_xy(to)))
+            .collect()
+    }
+
+    // Points the part of the segment between the two positions is drawn through
+    pub fn section_polyline(&self, segment: RoadSegmentIdx, start: f32, end: f32) -> Vec<(f32, f32)> {
+        std::iter::once(self.get_position_xy(&RoadPoint::new(segment, start)))
+            .chain(self.segments[segment].visual_keypoints.iter()
+                .filter(|visual_keypoint| start < visual_keypoint.position && visual_keypoint.position < end)
+                .map(|visual_keypoint| (visual_keypoint.x, visual_keypoint.y)))
+            .chain(std::iter::once(self.get_position_xy(&RoadPoint::new(segment, end))))
+            .collect()
+    }
+
+    pub fn visual_keypoints(&self, segment: RoadSegmentIdx) -> Vec<(f32, f32)> {
+        self.segments[segment].visual_keypoints.iter().map(|visual_keypoint| (visual_keypoint.x, visual_keypoint.y)).collect()
+    }
+
+    pub fn signs(&self, segment: RoadSegmentIdx) -> &[Sign] { &self.segments[segment].signs }
+
+    // Closest RoadNode within max_distance of the point
+    pub fn nearest_node(&self, (x, y): (f32, f32), max_distance: f32) -> Option<RoadNodeIdx> {
+        (0..self.nodes.len()).map(RoadNodeIdx)
+            .map(|node| (node, (self.nodes[node].x - x).hypot(self.nodes[node].y - y)))
+            .filter(|(_node, distance)| *distance <= max_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(node, _distance)| node)
+    }
+
+    // Closest point of a segment within max_distance of the point
+    pub fn nearest_point(&self, (x, y): (f32, f32), max_distance: f32) -> Option<RoadPoint> {
+        let mut nearest: Option<(RoadPoint, f32)> = None;
+        for segment in (0..self.segments.len()).map(RoadSegmentIdx) {
+            let polyline = self.segment_polyline(segment);
+            let mut position = 0.;
+            for piece in polyline.windows(2) {
+                let ((x1, y1), (x2, y2)) = (piece[0], piece[1]);
+                let piece_length = (x2 - x1).hypot(y2 - y1);
+                let t = if piece_length > 0. { (((x - x1) * (x2 - x1) + (y - y1) * (y2 - y1)) / piece_length.powi(2)).clamp(0., 1.) } else { 0. };
+                let distance = (x1 + t * (x2 - x1) - x).hypot(y1 + t * (y2 - y1) - y);
+                if distance <= max_distance && nearest.is_none_or(|(_point, nearest_distance)| distance < nearest_distance) {
+                    nearest = Some((RoadPoint::new(segment, (position + t * piece_length).min(self.segments[segment].length)), distance));
+                }
+                position += piece_length;
+            }
+        }
+
+        nearest.map(|(point, _distance)| point)
+    }
+
+    // Editing. Removing an element shifts down the indices of the ones after it.
+
+    pub fn add_node(&mut self, (x, y): (f32, f32)) -> RoadNodeIdx {
+        self.nodes.push(RoadNode { x, y, road_segments: Vec::new() });
+
+        RoadNodeIdx(self.nodes.len() - 1)
+    }
+
+    // The segments connected to the node follow it
+    pub fn move_node(&mut self, node: RoadNodeIdx, (x, y): (f32, f32)) {
+        self.nodes[node].x = x;
+        self.nodes[node].y = y;
+        for segment in (0..self.segments.len()).map(RoadSegmentIdx) {
+            if self.segments[segment].from == node || self.segments[segment].to == node {
+                self.update_length(segment);
+            }
+        }
+    }
+
+    // Along with the segments connected to it and its crossing
+    pub fn remove_node(&mut self, node: RoadNodeIdx) {
+        for segment in (0..self.segments.len()).rev().map(RoadSegmentIdx) {
+            if self.segments[segment].from == node || self.segments[segment].to == node {
+                self.remove_segment(segment);
+            }
+        }
+        self.nodes.remove(*node);
+        for segment in &mut self.segments {
+            for end in [&mut segment.from, &mut segment.to] {
+                if *end > node {
+                    **end -= 1;
+                }
+            }
+        }
+        self.crossings.retain(|crossing| crossing.node != node);
+        for crossing in self.crossings.iter_mut().filter(|crossing| crossing.node > node) {
+            *crossing.node -= 1;
+        }
+    }
+
+    // visual_keypoints are the points the segment is drawn through, between its RoadNodes
+    pub fn add_segment(&mut self, from: RoadNodeIdx, to: RoadNodeIdx, visual_keypoints: &[(f32, f32)]) -> RoadSegmentIdx {
+        let index = RoadSegmentIdx(self.segments.len());
+        let visual_keypoints = visual_keypoints.iter().map(|(x, y)| RoadVisualKeypoint { position: 0., x: *x, y: *y }).collect();
+        // The segment may start and end on the same node, which cannot be borrowed twice
+        let to_node = RoadNode { x: self.nodes[to].x, y: self.nodes[to].y, road_segments: Vec::new() };
+        let segment = RoadSegment::new(index, from, to, &mut self.nodes[from], &to_node, visual_keypoints);
+        self.segments.push(segment);
+
+        index
+    }
+
+    pub fn remove_segment(&mut self, segment: RoadSegmentIdx) {
+        self.segments.remove(*segment);
+        for node in &mut self.nodes {
+            node.road_segments.retain(|road_segment| *road_segment != segment);
+            for road_segment in node.road_segments.iter_mut().filter(|road_segment| **road_segment > segment) {
+                **road_segment -= 1;
+            }
+        }
+        for sign in self.segments.iter_mut().skip(*segment).flat_map(|segment| &mut segment.signs) {
+            *sign.position.road_segment -= 1;
+        }
+    }
+
+    // Inserts a visual keypoint at the given position along the segment, returns its index
+    pub fn insert_visual_keypoint(&mut self, segment: RoadSegmentIdx, position: f32, (x, y): (f32, f32)) -> usize {
+        let visual_keypoints = &mut self.segments[segment].visual_keypoints;
+        let index = visual_keypoints.iter().position(|visual_keypoint| visual_keypoint.position > position).unwrap_or(visual_keypoints.len());
+        visual_keypoints.insert(index, RoadVisualKeypoint { position, x, y });
+        self.update_length(segment);
+
+        index
+    }
+
+    pub fn move_visual_keypoint(&mut self, segment: RoadSegmentIdx, visual_keypoint: usize, (x, y): (f32, f32)) {
+        self.segments[segment].visual_keypoints[visual_keypoint].x = x;
+        self.segments[segment].visual_keypoints[visual_keypoint].y = y;
+        self.update_length(segment);
+    }
+
+    pub fn remove_visual_keypoint(&mut self, segment: RoadSegmentIdx, visual_keypoint: usize) {
+        self.segments[segment].visual_keypoints.remove(visual_keypoint);
+        self.update_length(segment);
+    }
+
+    // Segments ending at the node
+    pub fn incoming_segments(&self, node: RoadNodeIdx) -> Vec<RoadSegmentIdx> {
+        (0..self.segments.len()).map(RoadSegmentIdx).filter(|segment| self.segments[*segment].to == node).collect()
+    }
+
+    // Segments starting at the node
+    pub fn outgoing_segments(&self, node: RoadNodeIdx) -> Vec<RoadSegmentIdx> { self.nodes[node].road_segments.clone() }
+
+    pub fn has_sidewalk(&self, segment: RoadSegmentIdx) -> bool { self.segments[segment].sidewalk }
+
+    pub fn set_sidewalk(&mut self, segment: RoadSegmentIdx, sidewalk: bool) {
+        self.segments[segment].sidewalk = sidewalk;
+    }
+
+    pub fn bike_lane(&self, segment: RoadSegmentIdx) -> BikeLane { self.segments[segment].bike_lane }
+
+    pub fn set_bike_lane(&mut self, segment: RoadSegmentIdx, bike_lane: BikeLane) {
+        self.segments[segment].bike_lane = bike_lane;
+    }
+
+    pub fn add_crossing(&mut self, crossing: Crossing) {
+        self.crossings.push(crossing);
+    }
+
+    pub fn crossings(&self) -> &[Crossing] { &self.crossings }
+
+    pub fn crossing_at(&self, node: RoadNodeIdx) -> Option<&Crossing> { self.crossings.iter().find(|crossing| crossing.node == node) }
+
+    // RoadNodes the segment goes from and to
+    pub fn segment_nodes(&self, segment: RoadSegmentIdx) -> (RoadNodeIdx, RoadNodeIdx) { (self.segments[segment].from, self.segments[segment].to) }
+
+    pub fn is_closed(&self, segment: RoadSegmentIdx) -> bool { self.segments[segment].closed }
+
+    pub fn capacity_factor(&self, segment: RoadSegmentIdx) -> f32 { self.segments[segment].capacity_factor }
+
+    pub fn set_closed(&mut self, segment: RoadSegmentIdx, closed: bool) {
+        self.segments[segment].closed = closed;
+    }
+
+    pub fn set_capacity_factor(&mut self, segment: RoadSegmentIdx, capacity_factor: f32) {
+        self.segments[segment].capacity_factor = capacity_factor;
+    }
+
+    pub fn add_sign(&mut self, sign: Sign) {
+        self.segments[sign.position.road_segment].signs.push(sign);
+    }
+
+    pub fn remove_sign(&mut self, segment: RoadSegmentIdx, sign: usize) {
+        self.segments[segment].signs.remove(sign);
+    }
+
+    // Returns false if there is no such sign
+    pub fn set_sign_type(&mut self, segment: RoadSegmentIdx, sign: usize, sign_type: SignType) -> bool {
+        match self.segments[segment].signs.get_mut(sign) {
+            Some(sign) => {
+                sign.sign_type = sign_type;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Returns false if there is no such sign
+    pub fn set_sign_value(&mut self, segment: RoadSegmentIdx, sign: usize, value: f32) -> bool {
+        match self.segments[segment].signs.get_mut(sign) {
+            Some(sign) => {
+                sign.value = value;
+                true
+            },
+            None => false,
+        }
+    }
+
     pub fn get_distance(&self, point_1: &RoadPoint, point_2: &RoadPoint) -> f32 {
         if point_1.road_segment != point_2.road_segment {
             return f32::INFINITY;
@@ -100,37 +369,76 @@ impl Roads {
         if point_1.position <= point_2.position {
             return point_2.position - point_1.position;
         }
-        return self.segments[point_1.road_segment].length - point_1.position + point_2.position + f32::INFINITY;
+        self.segments[point_1.road_segment].length - point_1.position + point_2.position + f32::INFINITY
+    }
+
+    pub fn has_node(&self, node: RoadNodeIdx) -> bool { *node < self.nodes.len() }
+
+    pub fn has_segment(&self, segment: RoadSegmentIdx) -> bool { *segment < self.segments.len() }
+
+    // Whether the point lies on a RoadSegment of the network, e.g. for one read from a file
+    pub fn contains(&self, point: &RoadPoint) -> bool {
+        self.segments.get(*point.road_segment).is_some_and(|segment| (0. ..=segment.length).contains(&point.position))
+    }
+
+    pub fn segment_between(&self, from: RoadNodeIdx, to: RoadNodeIdx) -> Option<RoadSegmentIdx> {
+        self.nodes[from].road_segments.iter().find(|segment| self.segments[**segment].to == to).copied()
+    }
+
+    pub fn segment_style(&self, segment: RoadSegmentIdx) -> gui::RoadSegmentStyle {
+        let segment = &self.segments[segment];
+        if segment.closed {
+            gui::RoadSegmentStyle::Closed
+        } else if segment.capacity_factor < 1. {
+            gui::RoadSegmentStyle::WorkZone
+        } else if segment.bike_lane == BikeLane::Dedicated {
+            gui::RoadSegmentStyle::BikeLane
+        } else {
+            gui::RoadSegmentStyle::Open
+        }
     }
 
     pub fn render(&self, window: &gui::Window) {
-        for segment in &self.segments {
+        for (i, segment) in self.segments.iter().enumerate() {
+            let style = self.segment_style(RoadSegmentIdx(i));
             let mut start = (self.nodes[segment.from].x, self.nodes[segment.from].y);
             for visual_keypoint in &segment.visual_keypoints {
                 let end = (visual_keypoint.x, visual_keypoint.y);
-                window.draw_road_segment(start, end);
+                window.draw_road_segment(start, end, style);
                 start = end;
             }
-            window.draw_road_segment(start, (self.nodes[segment.to].x, self.nodes[segment.to].y));
+            window.draw_road_segment(start, (self.nodes[segment.to].x, self.nodes[segment.to].y), style);
             for sign in &segment.signs {
                 window.draw_sign(self.get_position_xy(&sign.position), &sign.sign_type);
             }
         }
     }
 
+    // Once a RoadNode or visual keypoint moved, the signs staying on the segment
+    fn update_length(&mut self, segment: RoadSegmentIdx) {
+        let (from, to) = self.segment_nodes(segment);
+        let (start, end) = ((self.nodes[from].x, self.nodes[from].y), (self.nodes[to].x, self.nodes[to].y));
+        let road_segment = &mut self.segments[segment];
+        road_segment.length = measure(start, &mut road_segment.visual_keypoints, end);
+        for sign in &mut road_segment.signs {
+            sign.position.position = sign.position.position.min(road_segment.length);
+        }
+    }
+
     fn init_roads(&mut self) {
         self.init_roads_mesh();
     }
 
-    fn init_roads_circle(&mut self) {
-        self.nodes.push(RoadNode { x: 20., y: 0., road_segments: Vec::new() });
+    fn init_roads_circle(&mut self, radius: f32) {
+        self.nodes.push(RoadNode { x: radius, y: 0., road_segments: Vec::new() });
         let mut visual_keypoints: Vec<RoadVisualKeypoint> = Vec::new();
         for i in 1..100 {
             let angle = i as f32 * 2. * f32::consts::PI / 100.;
-            visual_keypoints.push(RoadVisualKeypoint { position: i as f32 / 100., x: f32::cos(angle) * 20., y: f32::sin(angle) * 20. });
+            visual_keypoints.push(RoadVisualKeypoint { position: i as f32 / 100., x: f32::cos(angle) * radius, y: f32::sin(angle) * radius });
         }
-        let [node1, node2] = self.nodes.get_disjoint_mut([0, 0]).unwrap();
-        self.segments.push(RoadSegment::new(RoadSegmentIdx(0), RoadNodeIdx(0), RoadNodeIdx(0), node1, node2, visual_keypoints));
+        // The segment starts and ends on the same node, which cannot be borrowed twice
+        let end = RoadNode { x: radius, y: 0., road_segments: Vec::new() };
+        self.segments.push(RoadSegment::new(RoadSegmentIdx(0), RoadNodeIdx(0), RoadNodeIdx(0), &mut self.nodes[0], &end, visual_keypoints));
     }
 
     fn init_roads_mesh(&mut self) {
@@ -199,18 +507,13 @@ impl Roads {
     }
 }
 
+impl Default for Roads {
+    fn default() -> Self { Self::new() }
+}
+
 impl RoadSegment {
     fn new(index: RoadSegmentIdx, from: RoadNodeIdx, to: RoadNodeIdx, from_node: &mut RoadNode, to_node: &RoadNode, mut visual_keypoints: Vec<RoadVisualKeypoint>) -> Self {
-        let mut curr_x = from_node.x;
-        let mut curr_y = from_node.y;
-        let mut length: f32 = 0.;
-        for kp in &mut visual_keypoints {
-            length += ((kp.x - curr_x).powi(2) + (kp.y - curr_y).powi(2)).sqrt();
-            kp.position = length;
-            curr_x = kp.x;
-            curr_y = kp.y;
-        }
-        length += ((to_node.x - curr_x).powi(2) + (to_node.y - curr_y).powi(2)).sqrt();
+        let length = measure((from_node.x, from_node.y), &mut visual_keypoints, (to_node.x, to_node.y));
         from_node.road_segments.push(index);
         Self {
             from,
@@ -222,10 +525,28 @@ impl RoadSegment {
             ],
             length,
             visual_keypoints,
+            closed: false,
+            capacity_factor: 1.,
+            sidewalk: true,
+            bike_lane: BikeLane::Shared,
         }
     }
 }
 
+// Length of the polyline, setting the position of each visual keypoint along it
+fn measure(start: (f32, f32), visual_keypoints: &mut [RoadVisualKeypoint], end: (f32, f32)) -> f32 {
+    let (mut curr_x, mut curr_y) = start;
+    let mut length: f32 = 0.;
+    for kp in visual_keypoints {
+        length += ((kp.x - curr_x).powi(2) + (kp.y - curr_y).powi(2)).sqrt();
+        kp.position = length;
+        curr_x = kp.x;
+        curr_y = kp.y;
+    }
+
+    length + ((end.0 - curr_x).powi(2) + (end.1 - curr_y).powi(2)).sqrt()
+}
+
 impl Hash for RoadNode {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.x.to_bits().hash(state);
@@ -235,10 +556,32 @@ impl Hash for RoadNode {
 
 impl Eq for RoadNode {}
 
+impl Crossing {
+    // Whether pedestrians may start crossing. Always true for zebra crossings.
+    pub fn is_walk_phase(&self, time: f32) -> bool {
+        match self.kind {
+            CrossingKind::Zebra => true,
+            CrossingKind::Signalised { cycle, walk_time, offset } => (time + offset).rem_euclid(cycle) < walk_time,
+        }
+    }
+
+    pub fn render(&self, window: &gui::Window, roads: &Roads, time: f32) {
+        let signal = match self.kind {
+            CrossingKind::Zebra => None,
+            CrossingKind::Signalised { .. } => Some(self.is_walk_phase(time)),
+        };
+        window.draw_crossing(roads.node_xy(self.node), signal);
+    }
+}
+
 impl RoadPoint {
     pub fn new(road_segment: RoadSegmentIdx, position: f32) -> Self {
         Self { road_segment, position }
     }
+
+    pub fn road_segment(&self) -> RoadSegmentIdx { self.road_segment }
+
+    pub fn position(&self) -> f32 { self.position }
 }
 
 impl Hash for RoadPoint {
@@ -254,4 +597,159 @@ impl Display for RoadPoint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "({}, {})", self.road_segment, self.position)
     }
-}
\ No newline at end of file
+}
+
+impl Snapshot for RoadNodeIdx {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.0);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self(reader.read()?)) }
+}
+
+impl Snapshot for RoadSegmentIdx {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.0);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self(reader.read()?)) }
+}
+
+impl Snapshot for RoadPoint {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.road_segment);
+        writer.write(&self.position);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { road_segment: reader.read()?, position: reader.read()? }) }
+}
+
+// Saved whole rather than as a map file, as closures, work zones and sign values change during a run
+impl Snapshot for Roads {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.segments);
+        writer.write(&self.nodes);
+        writer.write(&self.crossings);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        let roads = Self { segments: reader.read()?, nodes: reader.read()?, crossings: reader.read()? };
+        let (node_count, segment_count) = (roads.nodes.len(), roads.segments.len());
+        if roads.segments.iter().any(|segment| *segment.from >= node_count || *segment.to >= node_count)
+            || roads.nodes.iter().any(|node| node.road_segments.iter().any(|segment| **segment >= segment_count))
+            || roads.crossings.iter().any(|crossing| *crossing.node >= node_count) {
+            return Err(snapshot::invalid_data("Road network refers to missing elements"));
+        }
+
+        Ok(roads)
+    }
+}
+
+impl Snapshot for RoadSegment {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.from);
+        writer.write(&self.to);
+        writer.write(&self.length);
+        writer.write(&self.signs);
+        writer.write(&self.visual_keypoints);
+        writer.write(&self.closed);
+        writer.write(&self.capacity_factor);
+        writer.write(&self.sidewalk);
+        writer.write(&self.bike_lane);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self {
+            from: reader.read()?,
+            to: reader.read()?,
+            length: reader.read()?,
+            signs: reader.read()?,
+            visual_keypoints: reader.read()?,
+            closed: reader.read()?,
+            capacity_factor: reader.read()?,
+            sidewalk: reader.read()?,
+            bike_lane: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for RoadVisualKeypoint {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.position);
+        writer.write(&self.x);
+        writer.write(&self.y);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { position: reader.read()?, x: reader.read()?, y: reader.read()? }) }
+}
+
+impl Snapshot for RoadNode {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.x);
+        writer.write(&self.y);
+        writer.write(&self.road_segments);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { x: reader.read()?, y: reader.read()?, road_segments: reader.read()? }) }
+}
+
+impl Snapshot for Sign {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.sign_type);
+        writer.write(&self.value);
+        writer.write(&self.position);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { sign_type: reader.read()?, value: reader.read()?, position: reader.read()? }) }
+}
+
+impl Snapshot for SignType {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&(*self as u8));
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(if reader.read_variant(2)? == 0 { SignType::SpeedLimit } else { SignType::EndSpeedLimit })
+    }
+}
+
+impl Snapshot for BikeLane {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&(*self as u8));
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(if reader.read_variant(2)? == 0 { BikeLane::Shared } else { BikeLane::Dedicated })
+    }
+}
+
+impl Snapshot for Crossing {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.node);
+        writer.write(&self.kind);
+        writer.write(&self.length);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { node: reader.read()?, kind: reader.read()?, length: reader.read()? }) }
+}
+
+impl Snapshot for CrossingKind {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        match self {
+            CrossingKind::Zebra => writer.write(&0u8),
+            CrossingKind::Signalised { cycle, walk_time, offset } => {
+                writer.write(&1u8);
+                writer.write(cycle);
+                writer.write(walk_time);
+                writer.write(offset);
+            },
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(match reader.read_variant(2)? {
+            0 => CrossingKind::Zebra,
+            _ => CrossingKind::Signalised { cycle: reader.read()?, walk_time: reader.read()?, offset: reader.read()? },
+        })
+    }
+}
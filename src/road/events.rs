@@ -0,0 +1,126 @@
+use std::{fmt::Display, io};
+use crate::{road::{RoadSegmentIdx, Roads}, snapshot::{Snapshot, SnapshotReader, SnapshotWriter}};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NetworkEventKind {
+    CloseSegment(RoadSegmentIdx),
+    ReopenSegment(RoadSegmentIdx),
+    // Index of the sign in the RoadSegment, and its new value
+    SetSignValue(RoadSegmentIdx, usize, f32),
+    // A capacity factor of 1 ends the work zone
+    SetCapacityFactor(RoadSegmentIdx, f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NetworkEvent {
+    pub time: f32,
+    pub kind: NetworkEventKind,
+}
+
+#[derive(Default)]
+pub struct EventScheduler {
+    // Sorted by decreasing time, so that the next event is at the end
+    events: Vec<NetworkEvent>,
+}
+
+impl EventScheduler {
+    pub fn new() -> Self { Self { events: Vec::new() } }
+
+    pub fn schedule(&mut self, time: f32, kind: NetworkEventKind) {
+        let idx = self.events.partition_point(|event| event.time > time);
+        self.events.insert(idx, NetworkEvent { time, kind });
+    }
+
+    pub fn next_event_time(&self) -> Option<f32> { self.events.last().map(|event| event.time) }
+
+    // Whether the RoadSegments of the events exist in the network
+    pub fn is_valid_on(&self, roads: &Roads) -> bool {
+        self.events.iter().all(|event| match event.kind {
+            NetworkEventKind::CloseSegment(segment) | NetworkEventKind::ReopenSegment(segment) | NetworkEventKind::SetSignValue(segment, ..) | NetworkEventKind::SetCapacityFactor(segment, _) => roads.has_segment(segment),
+        })
+    }
+
+    // Applies to the roads all the events due at the given time, and returns them
+    pub fn apply_due(&mut self, time: f32, roads: &mut Roads) -> Vec<NetworkEvent> {
+        let mut applied = Vec::new();
+        while self.events.last().is_some_and(|event| event.time <= time) {
+            let event = self.events.pop().unwrap();
+            match event.kind {
+                NetworkEventKind::CloseSegment(segment) => roads.set_closed(segment, true),
+                NetworkEventKind::ReopenSegment(segment) => roads.set_closed(segment, false),
+                NetworkEventKind::SetSignValue(segment, sign, value) => {
+                    if !roads.set_sign_value(segment, sign, value) {
+                        continue;
+                    }
+                },
+                NetworkEventKind::SetCapacityFactor(segment, capacity_factor) => roads.set_capacity_factor(segment, capacity_factor),
+            }
+            applied.push(event);
+        }
+
+        applied
+    }
+}
+
+impl Display for NetworkEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkEventKind::CloseSegment(segment) => write!(f, "close segment {}", segment),
+            NetworkEventKind::ReopenSegment(segment) => write!(f, "reopen segment {}", segment),
+            NetworkEventKind::SetSignValue(segment, sign, value) => write!(f, "set sign {} of segment {} to {}", sign, segment, value),
+            NetworkEventKind::SetCapacityFactor(segment, capacity_factor) => write!(f, "set capacity factor of segment {} to {}", segment, capacity_factor),
+        }
+    }
+}
+
+impl Snapshot for EventScheduler {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.events);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { events: reader.read()? }) }
+}
+
+impl Snapshot for NetworkEvent {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.time);
+        writer.write(&self.kind);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { time: reader.read()?, kind: reader.read()? }) }
+}
+
+impl Snapshot for NetworkEventKind {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        match self {
+            NetworkEventKind::CloseSegment(segment) => {
+                writer.write(&0u8);
+                writer.write(segment);
+            },
+            NetworkEventKind::ReopenSegment(segment) => {
+                writer.write(&1u8);
+                writer.write(segment);
+            },
+            NetworkEventKind::SetSignValue(segment, sign, value) => {
+                writer.write(&2u8);
+                writer.write(segment);
+                writer.write(sign);
+                writer.write(value);
+            },
+            NetworkEventKind::SetCapacityFactor(segment, factor) => {
+                writer.write(&3u8);
+                writer.write(segment);
+                writer.write(factor);
+            },
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(match reader.read_variant(4)? {
+            0 => NetworkEventKind::CloseSegment(reader.read()?),
+            1 => NetworkEventKind::ReopenSegment(reader.read()?),
+            2 => NetworkEventKind::SetSignValue(reader.read()?, reader.read()?, reader.read()?),
+            _ => NetworkEventKind::SetCapacityFactor(reader.read()?, reader.read()?),
+        })
+    }
+}
@@ -0,0 +1,133 @@
+use std::{fs::File, io::{self, BufRead, BufReader, BufWriter, Write}, path::Path};
+use crate::road::{BikeLane, Crossing, CrossingKind, RoadNodeIdx, RoadPoint, RoadSegmentIdx, Roads, Sign, SignType};
+
+// Text file, one element per line, the indices being the order of the lines of each kind:
+//   node <x> <y>
+//   segment <from node> <to node> <sidewalk|no_sidewalk> <shared|dedicated> [<keypoint x> <keypoint y>]...
+//   sign <segment> <position> speed_limit <km/h>
+//   sign <segment> <position> end_speed_limit
+//   crossing <node> zebra <length>
+//   crossing <node> signalised <length> <cycle> <walk time> <offset>
+// Empty lines and the ones starting with # are ignored. Coordinates, positions and lengths are in m, times in s.
+
+impl Roads {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_map(BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_map(&mut writer)?;
+
+        writer.flush()
+    }
+
+    pub fn read_map(reader: impl BufRead) -> io::Result<Self> {
+        let mut roads = Roads::empty();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.first().is_none_or(|field| field.starts_with('#')) {
+                continue;
+            }
+            roads.parse_line(&fields).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", i + 1, error)))?;
+        }
+
+        Ok(roads)
+    }
+
+    pub fn write_map(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "# traffic-simulator map")?;
+        for node in &self.nodes {
+            writeln!(writer, "node {} {}", node.x, node.y)?;
+        }
+        for segment in &self.segments {
+            write!(
+                writer, "segment {} {} {} {}",
+                segment.from, segment.to, if segment.sidewalk { "sidewalk" } else { "no_sidewalk" },
+                match segment.bike_lane { BikeLane::Shared => "shared", BikeLane::Dedicated => "dedicated" }
+            )?;
+            for visual_keypoint in &segment.visual_keypoints {
+                write!(writer, " {} {}", visual_keypoint.x, visual_keypoint.y)?;
+            }
+            writeln!(writer)?;
+        }
+        for (i, segment) in self.segments.iter().enumerate() {
+            for sign in &segment.signs {
+                match sign.sign_type {
+                    SignType::SpeedLimit => writeln!(writer, "sign {} {} speed_limit {:.1}", i, sign.position.position, sign.value * 3.6)?,
+                    SignType::EndSpeedLimit => writeln!(writer, "sign {} {} end_speed_limit", i, sign.position.position)?,
+                }
+            }
+        }
+        for crossing in &self.crossings {
+            match crossing.kind {
+                CrossingKind::Zebra => writeln!(writer, "crossing {} zebra {}", crossing.node, crossing.length)?,
+                CrossingKind::Signalised { cycle, walk_time, offset } => writeln!(writer, "crossing {} signalised {} {} {} {}", crossing.node, crossing.length, cycle, walk_time, offset)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_line(&mut self, fields: &[&str]) -> Result<(), String> {
+        let number = |i: usize| -> Result<f32, String> {
+            let field = fields.get(i).ok_or(format!("missing field {}", i))?;
+            field.parse().map_err(|_error| format!("{} is not a number", field))
+        };
+        let node = |i: usize| -> Result<RoadNodeIdx, String> {
+            let node = number(i)? as usize;
+            if node < self.nodes.len() { Ok(RoadNodeIdx(node)) } else { Err(format!("no node {}", node)) }
+        };
+        match fields[0] {
+            "node" => {
+                self.add_node((number(1)?, number(2)?));
+            },
+            "segment" => {
+                let (from, to) = (node(1)?, node(2)?);
+                let sidewalk = match fields.get(3) {
+                    Some(&"sidewalk") => true,
+                    Some(&"no_sidewalk") => false,
+                    _ => return Err("expected sidewalk or no_sidewalk".to_string()),
+                };
+                let bike_lane = match fields.get(4) {
+                    Some(&"shared") => BikeLane::Shared,
+                    Some(&"dedicated") => BikeLane::Dedicated,
+                    _ => return Err("expected shared or dedicated".to_string()),
+                };
+                if fields.len().is_multiple_of(2) {
+                    return Err("a keypoint coordinate is missing".to_string());
+                }
+                let visual_keypoints = (5..fields.len()).step_by(2).map(|i| Ok((number(i)?, number(i + 1)?))).collect::<Result<Vec<_>, String>>()?;
+                let segment = self.add_segment(from, to, &visual_keypoints);
+                self.set_sidewalk(segment, sidewalk);
+                self.set_bike_lane(segment, bike_lane);
+            },
+            "sign" => {
+                let segment = number(1)? as usize;
+                if segment >= self.segments.len() {
+                    return Err(format!("no segment {}", segment));
+                }
+                let position = RoadPoint::new(RoadSegmentIdx(segment), number(2)?.clamp(0., self.segments[segment].length));
+                let sign = match fields.get(3) {
+                    Some(&"speed_limit") => Sign { sign_type: SignType::SpeedLimit, value: number(4)? / 3.6, position },
+                    Some(&"end_speed_limit") => Sign { sign_type: SignType::EndSpeedLimit, value: 0., position },
+                    _ => return Err("expected speed_limit or end_speed_limit".to_string()),
+                };
+                self.add_sign(sign);
+            },
+            "crossing" => {
+                let node = node(1)?;
+                let kind = match fields.get(2) {
+                    Some(&"zebra") => CrossingKind::Zebra,
+                    Some(&"signalised") => CrossingKind::Signalised { cycle: number(4)?, walk_time: number(5)?, offset: number(6)? },
+                    _ => return Err("expected zebra or signalised".to_string()),
+                };
+                self.add_crossing(Crossing { node, kind, length: number(3)? });
+            },
+            kind => return Err(format!("unknown element {}", kind)),
+        }
+
+        Ok(())
+    }
+}
@@ -1,32 +1,55 @@
 pub mod pathfinding;
+pub mod route_choice;
 // pub use pathfinding;
 
-use crate::{generate_custom_vec, road::{RoadNodeIdx, RoadPoint, RoadSegmentIdx, Roads}};
+use std::io;
+use crate::{generate_custom_vec, road::{RoadNodeIdx, RoadPoint, RoadSegmentIdx, Roads}, snapshot::{Snapshot, SnapshotReader, SnapshotWriter}};
+use pathfinding::pathfind_between_nodes;
 
 generate_custom_vec!(RoadNodeIdx, NodePathIdx);
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Path {
     road_nodes: Vec<RoadNodeIdx>,
+    // Indices of the last RoadNode of each appended trip, so that rerouting never skips a destination
+    leg_ends: Vec<NodePathIdx>,
+    finished_legs: usize,
 }
 
 impl Path {
-    pub fn new() -> Self { Self { road_nodes: Vec::new() }}
+    pub fn new() -> Self { Self { road_nodes: Vec::new(), leg_ends: Vec::new(), finished_legs: 0 }}
 
-    pub fn move_by(&mut self, position: &mut RoadPoint, mut amount: f32, roads: &Roads) {
+    // Returns the distance actually moved, which is lower than amount if the end of the path is reached
+    pub fn move_by(&mut self, position: &mut RoadPoint, mut amount: f32, roads: &Roads) -> f32 {
+        let mut moved = 0.;
         while amount > 0. {
             let segment_length = roads.segments[position.road_segment].length;
             let amount_on_segment = amount.min(segment_length - position.position);
             position.position += amount_on_segment;
             amount -= amount_on_segment;
+            moved += amount_on_segment;
             if amount > 0. {
-                let current_node_path_index = self.get_node_path_idx(roads.segments[position.road_segment].to).expect("Node not found in path");
-                position.road_segment = self.get_segment_following_node_path(current_node_path_index, roads);
-                position.position = 0.;
+                let current_node_path_index = self.get_node_path_idx(roads.segments[position.road_segment].to);
+                match current_node_path_index {
+                    Some(current_node_path_index) if *current_node_path_index + 1 < self.road_nodes.len() => {
+                        if self.leg_ends.contains(&current_node_path_index) {
+                            self.finished_legs += 1;
+                        }
+                        position.road_segment = self.get_segment_following_node_path(current_node_path_index, roads);
+                        position.position = 0.;
+                    },
+                    // Wait at the end of the segment until the path is extended
+                    _ => break,
+                }
             }
         }
+
+        moved
     }
 
+    // Number of trips whose end has been reached since the last call
+    pub fn take_finished_legs(&mut self) -> usize { std::mem::take(&mut self.finished_legs) }
+
     pub fn total_distance(&self, roads: &Roads) -> f32 {
         if self.road_nodes.len() < 2 { // Having only 1 RoadNode makes no sense
             return 0.;
@@ -42,7 +65,10 @@ impl Path {
 
     pub fn distance_left(&self, node_point: &RoadPoint, roads: &Roads) -> f32 {
         let mut distance_left = roads.segments[node_point.road_segment].length - node_point.position;
-        for node_path_idx in self.get_node_path_idx(roads.segments[node_point.road_segment].to).expect("Node not found in path").into()..(self.road_nodes.len() - 1) {
+        let Some(current_node_path_idx) = self.get_node_path_idx(roads.segments[node_point.road_segment].to) else {
+            return distance_left;
+        };
+        for node_path_idx in current_node_path_idx.into()..(self.road_nodes.len() - 1) {
             let segment_idx = self.get_segment_following_node_path(NodePathIdx(node_path_idx), roads);
             distance_left += roads.segments[segment_idx].length;
         }
@@ -52,6 +78,86 @@ impl Path {
 
     pub fn append(&mut self, mut path: Vec<RoadNodeIdx>) {
         self.road_nodes.append(&mut path);
+        if !self.road_nodes.is_empty() {
+            self.leg_ends.push(NodePathIdx(self.road_nodes.len() - 1));
+        }
+    }
+
+    // Continues the current trip along the RoadNodes, without ending it
+    pub fn extend(&mut self, mut path: Vec<RoadNodeIdx>) {
+        self.road_nodes.append(&mut path);
+    }
+
+    pub fn nodes(&self) -> &[RoadNodeIdx] { &self.road_nodes }
+
+    // Whether the RoadNodes exist and each one is linked to the next by a RoadSegment
+    pub fn is_valid_on(&self, roads: &Roads) -> bool {
+        self.road_nodes.iter().all(|node| roads.has_node(*node))
+            && self.road_nodes.windows(2).all(|nodes| roads.segment_between(nodes[0], nodes[1]).is_some())
+            && self.leg_ends.iter().all(|leg_end| **leg_end < self.road_nodes.len())
+    }
+
+    pub fn append_path(&mut self, path: Path) {
+        self.append(path.road_nodes);
+    }
+
+    pub fn segments(&self, roads: &Roads) -> Vec<RoadSegmentIdx> {
+        (0..self.road_nodes.len().saturating_sub(1)).map(|node_path_idx| self.get_segment_following_node_path(NodePathIdx(node_path_idx), roads)).collect()
+    }
+
+    // Segments left to drive until the end of the current trip, the current segment excluded
+    pub fn segments_ahead(&self, position: &RoadPoint, roads: &Roads) -> Vec<RoadSegmentIdx> {
+        let Some((current_idx, leg_end_idx)) = self.current_leg(position, roads) else {
+            return Vec::new();
+        };
+        (*current_idx..*leg_end_idx).map(|node_path_idx| self.get_segment_following_node_path(NodePathIdx(node_path_idx), roads)).collect()
+    }
+
+    // Segments left to drive until the end of the path, the current segment excluded
+    pub fn remaining_segments(&self, position: &RoadPoint, roads: &Roads) -> Vec<RoadSegmentIdx> {
+        let Some(current_idx) = self.get_node_path_idx(roads.segments[position.road_segment].to) else {
+            return Vec::new();
+        };
+        (*current_idx..self.road_nodes.len().saturating_sub(1)).map(|node_path_idx| self.get_segment_following_node_path(NodePathIdx(node_path_idx), roads)).collect()
+    }
+
+    // Distance to drive along the path to reach the given point, None if it is not on the path ahead
+    pub fn distance_to(&self, from: &RoadPoint, to: &RoadPoint, roads: &Roads) -> Option<f32> {
+        if from.road_segment == to.road_segment && from.position <= to.position {
+            return Some(to.position - from.position);
+        }
+        let mut distance = roads.segments[from.road_segment].length - from.position;
+        for segment in self.remaining_segments(from, roads) {
+            if segment == to.road_segment {
+                return Some(distance + to.position);
+            }
+            distance += roads.segments[segment].length;
+        }
+
+        None
+    }
+
+    // Replaces the rest of the current trip by the cheapest one according to segment_cost. Returns whether the path changed.
+    pub fn reroute(&mut self, position: &RoadPoint, roads: &Roads, segment_cost: impl Fn(RoadSegmentIdx) -> Option<f32>, heuristic_factor: f32) -> bool {
+        let Some((current_idx, leg_end_idx)) = self.current_leg(position, roads) else {
+            return false;
+        };
+        if current_idx == leg_end_idx {
+            return false;
+        }
+        let Some((new_nodes, _cost)) = pathfind_between_nodes(self.road_nodes[current_idx], self.road_nodes[leg_end_idx], roads, segment_cost, heuristic_factor) else {
+            return false;
+        };
+        if new_nodes[..] == self.road_nodes[*current_idx..=*leg_end_idx] {
+            return false;
+        }
+        let shift = new_nodes.len() as isize - (*leg_end_idx - *current_idx + 1) as isize;
+        self.road_nodes.splice(*current_idx..=*leg_end_idx, new_nodes);
+        for leg_end in self.leg_ends.iter_mut().filter(|leg_end| **leg_end >= leg_end_idx) {
+            *leg_end = NodePathIdx((**leg_end as isize + shift) as usize);
+        }
+
+        true
     }
 
     pub fn forget_before(&mut self, position: &RoadPoint, roads: &Roads) {
@@ -59,21 +165,57 @@ impl Path {
         if delete_up_to_idx.is_none() {
             return;
         }
-        for _ in 0..delete_up_to_idx.unwrap().into() {
-            self.road_nodes.remove(0);
+        let deleted = *delete_up_to_idx.unwrap();
+        self.road_nodes.drain(0..deleted);
+        self.leg_ends.retain(|leg_end| **leg_end >= deleted);
+        for leg_end in self.leg_ends.iter_mut() {
+            **leg_end -= deleted;
         }
     }
 
+    // Index of the RoadNode the position is heading to, and of the end of the trip it belongs to
+    fn current_leg(&self, position: &RoadPoint, roads: &Roads) -> Option<(NodePathIdx, NodePathIdx)> {
+        let current_idx = self.get_node_path_idx(roads.segments[position.road_segment].to)?;
+        let leg_end_idx = *self.leg_ends.iter().find(|leg_end| **leg_end >= current_idx)?;
+
+        Some((current_idx, leg_end_idx))
+    }
+
     fn get_node_path_idx(&self, node: RoadNodeIdx) -> Option<NodePathIdx> {
-        self.road_nodes.iter().position(|elt| elt == &node).and_then(|i| Some(NodePathIdx(i)))
+        self.road_nodes.iter().position(|elt| elt == &node).map(NodePathIdx)
     }
 
     fn get_segment_following_node_path(&self, node: NodePathIdx, roads: &Roads) -> RoadSegmentIdx {
-        for segment in &roads.nodes[self.road_nodes[node]].road_segments {
-            if roads.segments[*segment].to == self.road_nodes[node + NodePathIdx(1)] {
-                return *segment;
-            }
+        if let Some(segment) = roads.segment_between(self.road_nodes[node], self.road_nodes[node + NodePathIdx(1)]) {
+            return segment;
         }
         panic!("Could not find a RoadSegment going from RoadNodeIdx({}) to RoadNodeIdx({})", node, node + NodePathIdx(1));
     }
-}
\ No newline at end of file
+}
+
+impl From<Vec<RoadNodeIdx>> for Path {
+    fn from(road_nodes: Vec<RoadNodeIdx>) -> Self {
+        let mut path = Self::new();
+        path.append(road_nodes);
+
+        path
+    }
+}
+
+impl Snapshot for NodePathIdx {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.0);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self(reader.read()?)) }
+}
+
+impl Snapshot for Path {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.road_nodes);
+        writer.write(&self.leg_ends);
+        writer.write(&self.finished_legs);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { road_nodes: reader.read()?, leg_ends: reader.read()?, finished_legs: reader.read()? }) }
+}
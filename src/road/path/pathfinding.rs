@@ -1,6 +1,6 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::{cmp::Ordering, collections::{HashMap, HashSet}};
 use sortedlist_rs::SortedList;
-use crate::road::{RoadNode, RoadNodeIdx, RoadPoint, Roads};
+use crate::road::{RoadNode, RoadNodeIdx, RoadPoint, RoadSegmentIdx, Roads};
 
 
 #[derive(Clone, Copy, Debug)]
@@ -35,7 +35,8 @@ impl Ord for OpenedNode {
         } else if self.cost > other.cost {
             Ordering::Greater
         } else {
-            Ordering::Equal
+            // Break ties on the node, so that binary_search finds exactly this node
+            self.road_node.cmp(&other.road_node)
         }
     }
 }
@@ -49,6 +50,62 @@ fn get_distance(point1: &RoadNode, point2: &RoadNode) -> f32 { ((point1.x - poin
 pub fn pathfind(start: &RoadPoint, end: &RoadPoint, roads: &Roads) -> Option<Vec<RoadNodeIdx>> {
     let start_road_node_index = roads.segments[start.road_segment].to;
     let end_road_node_index = roads.segments[end.road_segment].from;
+    pathfind_between_nodes(start_road_node_index, end_road_node_index, roads, |segment_idx| Some(roads.segments[segment_idx].length), 1.).map(|(path, _cost)| path)
+}
+
+// Returns the k shortest loopless paths from start to end, sorted by cost, using Yen's algorithm.
+pub fn k_shortest_paths(start: &RoadPoint, end: &RoadPoint, k: usize, roads: &Roads) -> Vec<(Vec<RoadNodeIdx>, f32)> {
+    let start_road_node_index = roads.segments[start.road_segment].to;
+    let end_road_node_index = roads.segments[end.road_segment].from;
+    let segment_length = |segment_idx: RoadSegmentIdx| Some(roads.segments[segment_idx].length);
+    let mut paths: Vec<(Vec<RoadNodeIdx>, f32)> = Vec::new();
+    match pathfind_between_nodes(start_road_node_index, end_road_node_index, roads, segment_length, 1.) {
+        Some(path) => paths.push(path),
+        None => return paths,
+    }
+    let mut candidates: Vec<(Vec<RoadNodeIdx>, f32)> = Vec::new();
+    while paths.len() < k {
+        let last_path = &paths[paths.len() - 1].0;
+        for spur_idx in 0..(last_path.len() - 1) {
+            let spur_node = last_path[spur_idx];
+            let root_path = &last_path[..=spur_idx];
+            // Forbid the edges already used after this root, and the nodes of the root to keep the path loopless
+            let mut removed_edges: HashSet<(RoadNodeIdx, RoadNodeIdx)> = HashSet::new();
+            for (path, _cost) in &paths {
+                if path.len() > spur_idx + 1 && &path[..=spur_idx] == root_path {
+                    removed_edges.insert((path[spur_idx], path[spur_idx + 1]));
+                }
+            }
+            let removed_nodes: HashSet<RoadNodeIdx> = root_path[..spur_idx].iter().copied().collect();
+            let spur_path = pathfind_between_nodes(spur_node, end_road_node_index, roads, |segment_idx| {
+                let segment = &roads.segments[segment_idx];
+                if removed_edges.contains(&(segment.from, segment.to)) || removed_nodes.contains(&segment.to) {
+                    return None;
+                }
+                Some(segment.length)
+            }, 1.);
+            if let Some((spur_path, spur_cost)) = spur_path {
+                let root_cost: f32 = root_path.windows(2).map(|edge| roads.segments[roads.segment_between(edge[0], edge[1]).unwrap()].length).sum();
+                let mut candidate = root_path.to_vec();
+                candidate.extend_from_slice(&spur_path[1..]);
+                if !paths.iter().chain(candidates.iter()).any(|(path, _cost)| path == &candidate) {
+                    candidates.push((candidate, root_cost + spur_cost));
+                }
+            }
+        }
+        if candidates.is_empty() {
+            break;
+        }
+        let best_candidate_idx = (0..candidates.len()).min_by(|a, b| candidates[*a].1.total_cmp(&candidates[*b].1)).unwrap();
+        paths.push(candidates.remove(best_candidate_idx));
+    }
+
+    paths
+}
+
+// segment_cost returns None for segments that must not be used. The heuristic is the straight line distance to the end
+// multiplied by heuristic_factor, which must not be higher than the lowest cost per meter of the segments.
+pub(crate) fn pathfind_between_nodes(start_road_node_index: RoadNodeIdx, end_road_node_index: RoadNodeIdx, roads: &Roads, segment_cost: impl Fn(RoadSegmentIdx) -> Option<f32>, heuristic_factor: f32) -> Option<(Vec<RoadNodeIdx>, f32)> {
     let mut nodes: HashMap<RoadNodeIdx, Node> = HashMap::new();
     let mut opened_nodes: SortedList<OpenedNode> = SortedList::new();
     
@@ -56,7 +113,7 @@ pub fn pathfind(start: &RoadPoint, end: &RoadPoint, roads: &Roads) -> Option<Vec
     let start_node = Node {
         road_node: start_road_node_index,
         distance_from_start: 0.,
-        distance_to_end: get_distance(&roads.nodes[start_road_node_index], &roads.nodes[start_road_node_index]),
+        distance_to_end: get_distance(&roads.nodes[start_road_node_index], &roads.nodes[end_road_node_index]) * heuristic_factor,
         parent_road_node: RoadNodeIdx(0),
         opened: true,
     };
@@ -65,7 +122,7 @@ pub fn pathfind(start: &RoadPoint, end: &RoadPoint, roads: &Roads) -> Option<Vec
 
     let mut found = false;
 
-    while !found && opened_nodes.len() != 0 {
+    while !found && !opened_nodes.is_empty() {
         // Get the best opened node
         let mut node = *nodes.get(&opened_nodes.remove(0).road_node).unwrap();
         node.opened = false;
@@ -77,28 +134,34 @@ pub fn pathfind(start: &RoadPoint, end: &RoadPoint, roads: &Roads) -> Option<Vec
         // List all the neighbours of that node
         for road_segment_idx in &roads.nodes[node.road_node].road_segments {
             let segment = &roads.segments[*road_segment_idx];
+            if segment.closed {
+                continue;
+            }
+            let Some(segment_cost) = segment_cost(*road_segment_idx) else {
+                continue;
+            };
             let neighbour_road_node_index = segment.to;
             let neighbour_road_node = &roads.nodes[neighbour_road_node_index];
             let old_neighbour_node = nodes.get(&segment.to);
-            let old_neighbour_node_opened = old_neighbour_node.and_then(|node| Some(node.opened));
+            let old_neighbour_node_opened = old_neighbour_node.map(|node| node.opened);
             if old_neighbour_node_opened == Some(false) {
                 // If the neighbour is already closed, skip it
                 continue;
             }
             let new_neighbour_node = Node {
                 road_node: neighbour_road_node_index,
-                distance_from_start: node.distance_from_start + segment.length,
-                distance_to_end: get_distance(&roads.nodes[node.road_node], neighbour_road_node),
+                distance_from_start: node.distance_from_start + segment_cost,
+                distance_to_end: get_distance(neighbour_road_node, &roads.nodes[end_road_node_index]) * heuristic_factor,
                 parent_road_node: node.road_node,
                 opened: true,
             };
-            if old_neighbour_node_opened == None {
+            if old_neighbour_node_opened.is_none() {
                 // Create a new value in opened_nodes and in nodes
                 opened_nodes.insert(OpenedNode::from(&new_neighbour_node));
                 nodes.insert(neighbour_road_node_index, new_neighbour_node);
             } else if old_neighbour_node.unwrap().cost() > new_neighbour_node.cost() {
                 // Remove the old value from opened_nodes, and create a new one. In nodes, update the value.
-                opened_nodes.remove(opened_nodes.binary_search(&OpenedNode::from(&old_neighbour_node.unwrap())).unwrap());
+                opened_nodes.remove(opened_nodes.binary_search(&OpenedNode::from(old_neighbour_node.unwrap())).unwrap());
                 opened_nodes.insert(OpenedNode::from(&new_neighbour_node));
                 nodes.insert(neighbour_road_node_index, new_neighbour_node);
             }
@@ -120,5 +183,53 @@ pub fn pathfind(start: &RoadPoint, end: &RoadPoint, roads: &Roads) -> Option<Vec
     }
     reversed_path.reverse();
 
-    Some(reversed_path)
-}
\ No newline at end of file
+    Some((reversed_path, nodes[&end_road_node_index].distance_from_start))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::road::{RoadNodeIdx, RoadPoint, Roads};
+    use super::{k_shortest_paths, pathfind};
+
+    // From the bottom left corner of the mesh to the top right one, 180 m along any of the 20 monotone paths
+    fn corner_points(roads: &Roads) -> (RoadPoint, RoadPoint) {
+        let start = roads.segment_between(RoadNodeIdx(1), RoadNodeIdx(0)).expect("Could not find the start segment");
+        let end = roads.segment_between(RoadNodeIdx(15), RoadNodeIdx(14)).expect("Could not find the end segment");
+        (RoadPoint::new(start, 0.), RoadPoint::new(end, 0.))
+    }
+
+    fn path_length(path: &[RoadNodeIdx], roads: &Roads) -> f32 {
+        path.windows(2).map(|edge| roads.segment_length(roads.segment_between(edge[0], edge[1]).expect("Could not follow the path"))).sum()
+    }
+
+    #[test]
+    fn k_shortest_paths_are_distinct_loopless_and_sorted() {
+        let roads = Roads::new();
+        let (start, end) = corner_points(&roads);
+        let paths = k_shortest_paths(&start, &end, 25, &roads);
+
+        assert_eq!(paths.len(), 25);
+        assert_eq!(paths[0].0, pathfind(&start, &end, &roads).expect("Could not find a path"));
+        for (i, (path, cost)) in paths.iter().enumerate() {
+            assert_eq!(path.first(), Some(&RoadNodeIdx(0)));
+            assert_eq!(path.last(), Some(&RoadNodeIdx(15)));
+            assert_eq!(path.iter().collect::<HashSet<_>>().len(), path.len(), "Path {} has a loop", i);
+            assert!((path_length(path, &roads) - cost).abs() < 1e-3, "Path {} has a wrong cost", i);
+            assert!(paths[..i].iter().all(|(other, _cost)| other != path), "Path {} is found twice", i);
+        }
+        assert!(paths.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        // All the monotone paths come first
+        assert!(paths[..20].iter().all(|(_path, cost)| (cost - 180.).abs() < 1e-3));
+        assert!(paths[20].1 > 180. + 1.);
+    }
+
+    #[test]
+    fn k_shortest_paths_stops_when_there_are_no_more() {
+        let roads = Roads::new_ring(100.);
+        let segment = crate::road::RoadSegmentIdx(0);
+        let paths = k_shortest_paths(&RoadPoint::new(segment, 0.), &RoadPoint::new(segment, 50.), 3, &roads);
+
+        assert_eq!(paths.len(), 1);
+    }
+}
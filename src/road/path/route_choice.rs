@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use crate::{road::{path::{pathfinding, Path}, RoadPoint, RoadSegmentIdx, Roads}, utils::Rng};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RouteChoiceModel {
+    Logit,
+    PathSizeLogit,
+}
+
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub path: Path,
+    pub cost: f32,
+    // Highest share of this route's length that is shared with another route of the same set
+    pub overlap: f32,
+    // Path size factor, 1 for a route that shares no segment with the others
+    pub path_size: f32,
+}
+
+pub fn generate_routes(start: &RoadPoint, end: &RoadPoint, k: usize, roads: &Roads) -> Vec<Route> {
+    let paths: Vec<(Path, f32)> = pathfinding::k_shortest_paths(start, end, k, roads)
+        .into_iter()
+        .map(|(road_nodes, cost)| (Path::from(road_nodes), cost))
+        .collect();
+    let segments: Vec<Vec<RoadSegmentIdx>> = paths.iter().map(|(path, _cost)| path.segments(roads)).collect();
+    let mut segment_usage: HashMap<RoadSegmentIdx, usize> = HashMap::new();
+    for route_segments in &segments {
+        for segment in route_segments {
+            *segment_usage.entry(*segment).or_insert(0) += 1;
+        }
+    }
+    let mut routes = Vec::new();
+    for (i, (path, cost)) in paths.iter().enumerate() {
+        let length = route_length(&segments[i], roads);
+        let mut path_size = 1.;
+        if length > 0. {
+            path_size = segments[i].iter().map(|segment| roads.segments[*segment].length / length / segment_usage[segment] as f32).sum();
+        }
+        let overlap = (0..paths.len())
+            .filter(|j| *j != i)
+            .map(|j| overlap(&segments[i], &segments[j], roads))
+            .fold(0., f32::max);
+        routes.push(Route { path: path.clone(), cost: *cost, overlap, path_size });
+    }
+
+    routes
+}
+
+// Length shared by both routes, relative to the length of the shortest one
+pub fn overlap(route_1: &[RoadSegmentIdx], route_2: &[RoadSegmentIdx], roads: &Roads) -> f32 {
+    let shortest_length = route_length(route_1, roads).min(route_length(route_2, roads));
+    if shortest_length <= 0. {
+        return 0.;
+    }
+    let shared_length: f32 = route_1.iter().filter(|segment| route_2.contains(segment)).map(|segment| roads.segments[*segment].length).sum();
+
+    shared_length / shortest_length
+}
+
+// theta is the sensitivity to the cost, in 1 / meter
+pub fn route_probabilities(routes: &[Route], model: RouteChoiceModel, theta: f32) -> Vec<f32> {
+    let min_cost = routes.iter().map(|route| route.cost).fold(f32::INFINITY, f32::min);
+    let weights: Vec<f32> = routes.iter().map(|route| {
+        let utility = -theta * (route.cost - min_cost);
+        match model {
+            RouteChoiceModel::Logit => utility.exp(),
+            RouteChoiceModel::PathSizeLogit => (utility + route.path_size.ln()).exp(),
+        }
+    }).collect();
+    let total_weight: f32 = weights.iter().sum();
+
+    weights.iter().map(|weight| weight / total_weight).collect()
+}
+
+pub fn choose_route(routes: &[Route], model: RouteChoiceModel, theta: f32, rng: &mut Rng) -> Option<usize> {
+    if routes.is_empty() {
+        return None;
+    }
+    let mut draw = rng.next_f32();
+    for (i, probability) in route_probabilities(routes, model, theta).iter().enumerate() {
+        if draw < *probability {
+            return Some(i);
+        }
+        draw -= probability;
+    }
+
+    Some(routes.len() - 1)
+}
+
+fn route_length(route: &[RoadSegmentIdx], roads: &Roads) -> f32 {
+    route.iter().map(|segment| roads.segments[*segment].length).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::road::{path::Path, RoadNodeIdx, RoadPoint, Roads};
+    use super::{generate_routes, route_probabilities, Route, RouteChoiceModel};
+
+    fn route(cost: f32, path_size: f32) -> Route {
+        Route { path: Path::from(vec![RoadNodeIdx(0)]), cost, overlap: 1. - path_size, path_size }
+    }
+
+    #[test]
+    fn probabilities_sum_to_one() {
+        let roads = Roads::new();
+        let start = RoadPoint::new(roads.segment_between(RoadNodeIdx(1), RoadNodeIdx(0)).expect("Could not find the start segment"), 0.);
+        let end = RoadPoint::new(roads.segment_between(RoadNodeIdx(15), RoadNodeIdx(14)).expect("Could not find the end segment"), 0.);
+        let routes = generate_routes(&start, &end, 25, &roads);
+
+        for model in [RouteChoiceModel::Logit, RouteChoiceModel::PathSizeLogit] {
+            let probabilities = route_probabilities(&routes, model, 0.05);
+            assert_eq!(probabilities.len(), routes.len());
+            assert!((probabilities.iter().sum::<f32>() - 1.).abs() < 1e-4);
+            assert!(probabilities.iter().all(|probability| *probability >= 0.));
+        }
+        for route in &routes {
+            assert!(route.path_size > 0. && route.path_size <= 1. + 1e-4);
+            if route.overlap > 0. {
+                assert!(route.path_size < 1.);
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_routes_are_penalised() {
+        // Two routes sharing everything and a distinct one, all of the same cost
+        let routes = [route(100., 0.5), route(100., 0.5), route(100., 1.)];
+
+        let logit = route_probabilities(&routes, RouteChoiceModel::Logit, 0.1);
+        assert!(logit.iter().all(|probability| (probability - 1. / 3.).abs() < 1e-4));
+        let path_size_logit = route_probabilities(&routes, RouteChoiceModel::PathSizeLogit, 0.1);
+        assert!((path_size_logit[0] - 0.25).abs() < 1e-4);
+        assert!((path_size_logit[1] - 0.25).abs() < 1e-4);
+        assert!((path_size_logit[2] - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cheaper_routes_are_more_likely() {
+        let routes = [route(100., 1.), route(120., 1.)];
+
+        for model in [RouteChoiceModel::Logit, RouteChoiceModel::PathSizeLogit] {
+            let probabilities = route_probabilities(&routes, model, 0.1);
+            assert!(probabilities[0] > probabilities[1]);
+            assert!((probabilities[1] / probabilities[0] - (-2f32).exp()).abs() < 1e-4);
+        }
+    }
+}
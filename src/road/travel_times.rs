@@ -0,0 +1,63 @@
+use std::io;
+use crate::{road::{RoadSegmentIdx, Roads}, snapshot::{Snapshot, SnapshotReader, SnapshotWriter}};
+
+const SMOOTHING_TIME: f32 = 30.; // s, time constant of the moving average
+const MIN_SPEED: f32 = 1.; // m/s, so that stopped cars do not give infinite travel times
+
+// Current travel time estimation on each RoadSegment, measured from the speed of the cars driving on it
+pub struct TravelTimes {
+    free_flow_times: Vec<f32>,
+    times: Vec<f32>,
+    free_flow_speed: f32,
+}
+
+impl TravelTimes {
+    pub fn new(roads: &Roads, free_flow_speed: f32) -> Self {
+        let free_flow_times: Vec<f32> = roads.segments.iter().map(|segment| segment.length / free_flow_speed).collect();
+        Self { times: free_flow_times.clone(), free_flow_times, free_flow_speed }
+    }
+
+    // Whether there is a travel time for each RoadSegment of the network
+    pub fn matches(&self, roads: &Roads) -> bool { self.times.len() == roads.segment_count() && self.free_flow_times.len() == roads.segment_count() }
+
+    pub fn update(&mut self, step_size: f32, roads: &Roads, speeds: impl IntoIterator<Item = (RoadSegmentIdx, f32)>) {
+        let mut speed_sums = vec![0.; self.times.len()];
+        let mut car_counts = vec![0; self.times.len()];
+        for (segment, speed) in speeds {
+            speed_sums[*segment] += speed.max(MIN_SPEED);
+            car_counts[*segment] += 1;
+        }
+        let weight = 1. - (-step_size / SMOOTHING_TIME).exp();
+        for (i, segment) in roads.segments.iter().enumerate() {
+            let measured_time = if car_counts[i] == 0 {
+                self.free_flow_times[i]
+            } else {
+                segment.length / (speed_sums[i] / car_counts[i] as f32)
+            };
+            self.times[i] += (measured_time - self.times[i]) * weight;
+        }
+    }
+
+    pub fn get(&self, segment: RoadSegmentIdx) -> f32 { self.times[*segment] }
+
+    pub fn free_flow(&self, segment: RoadSegmentIdx) -> f32 { self.free_flow_times[*segment] }
+
+    // Lowest current cost per meter over all the segments, to be used as pathfinding heuristic factor. Vehicles may
+    // drive faster than free_flow_speed, so 1 / free_flow_speed would overestimate the remaining cost.
+    pub fn min_time_per_meter(&self) -> f32 {
+        self.times.iter().zip(&self.free_flow_times)
+            .filter(|(_time, free_flow_time)| **free_flow_time > 0.)
+            .map(|(time, free_flow_time)| time / (free_flow_time * self.free_flow_speed))
+            .fold(1. / self.free_flow_speed, f32::min)
+    }
+}
+
+impl Snapshot for TravelTimes {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.free_flow_times);
+        writer.write(&self.times);
+        writer.write(&self.free_flow_speed);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { free_flow_times: reader.read()?, times: reader.read()?, free_flow_speed: reader.read()? }) }
+}
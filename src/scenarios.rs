@@ -0,0 +1,165 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+use crate::{agent::{car::{Car, TripPlan}, driver::{DriverDistribution, DriverProfile}, vehicle::VehicleClass}, road, simulation::SimulationData, utils::Rng};
+
+const SAMPLE_INTERVAL: f32 = 1.; // s
+const PROFILE_BIN_SIZE: f32 = 1.; // m
+// Below this speed difference between the cars, there is no wave to measure
+const MIN_WAVE_AMPLITUDE: f32 = 0.5; // m/s
+
+// Sugiyama et al. (2008) experiment: identical drivers evenly spaced on a ring road, the first one slowed down at the start.
+// Any car-following model should let the perturbation either fade away or grow into a stop-and-go wave moving upstream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RingBenchmark {
+    pub circumference: f32, // m
+    pub car_count: usize,
+    pub driver: DriverProfile,
+    pub initial_speed: f32, // m/s
+    // Speed removed from the first car at the start
+    pub perturbation: f32, // m/s
+    pub duration: f32, // s
+    // The waves are measured after this time, once established
+    pub warm_up: f32, // s
+    pub step_size: f32, // s
+    pub seed: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RingBenchmarkResult {
+    pub mean_speed: f32, // m/s
+    pub speed_standard_deviation: f32, // m/s
+    // Difference between the highest and lowest speeds of the cars, averaged over time
+    pub amplitude: f32, // m/s
+    // Negative when moving against the traffic, None if there is no wave
+    pub wave_speed: Option<f32>, // m/s
+}
+
+// Cars evenly spaced on a single-lane ring road, driving around forever. With delayed reactions, small
+// disturbances grow into stop-and-go waves without any bottleneck.
+pub fn ring_road(circumference: f32, car_count: usize, drivers: DriverDistribution, seed: u64) -> SimulationData {
+    let mut sd = SimulationData::new(road::Roads::new_ring(circumference));
+    sd.rng = Rng::new(seed);
+    let spacing = circumference / car_count as f32;
+    for i in 0..car_count {
+        let driver = drivers.sample(&mut sd.rng);
+        let car = Car::new(road::RoadPoint::new(road::RoadSegmentIdx(0), i as f32 * spacing), VehicleClass::Car.default_type(), driver, TripPlan::Loop(vec![road::RoadNodeIdx(0)]), false, sd.rng.fork());
+        sd.add_car(car);
+    }
+
+    sd
+}
+
+impl RingBenchmark {
+    pub fn create_simulation(&self) -> SimulationData {
+        let mut sd = ring_road(self.circumference, self.car_count, DriverDistribution::constant(self.driver), self.seed);
+        for car in &mut sd.cars {
+            car.set_speed(self.initial_speed);
+        }
+        if let Some(car) = sd.cars.first_mut() {
+            car.set_speed((self.initial_speed - self.perturbation).max(0.));
+        }
+
+        sd
+    }
+
+    pub fn run(&self) -> RingBenchmarkResult {
+        self.run_simulation(&mut self.create_simulation())
+    }
+
+    // Runs the given simulation, created by create_simulation, so that outputs can be added to it beforehand
+    pub fn run_simulation(&self, sd: &mut SimulationData) -> RingBenchmarkResult {
+        // Position and speed of each car, at each sample
+        let mut samples: Vec<Vec<(f32, f32)>> = Vec::new();
+        let mut next_sample = self.warm_up;
+        while sd.time < self.duration {
+            sd.step(self.step_size);
+            if sd.time >= next_sample {
+                samples.push(sd.cars.iter().map(|car| (car.position().position(), car.speed())).collect());
+                next_sample += SAMPLE_INTERVAL;
+            }
+        }
+        let speeds: Vec<f32> = samples.iter().flatten().map(|(_position, speed)| *speed).collect();
+        let mean_speed = speeds.iter().sum::<f32>() / speeds.len().max(1) as f32;
+        let variance = speeds.iter().map(|speed| (speed - mean_speed).powi(2)).sum::<f32>() / speeds.len().max(1) as f32;
+        let amplitude = samples.iter().map(|sample| {
+            let max = sample.iter().map(|(_position, speed)| *speed).fold(0., f32::max);
+            let min = sample.iter().map(|(_position, speed)| *speed).fold(f32::INFINITY, f32::min);
+            max - min
+        }).sum::<f32>() / samples.len().max(1) as f32;
+        let wave_speed = if amplitude >= MIN_WAVE_AMPLITUDE { Some(self.wave_speed(&samples)) } else { None };
+
+        RingBenchmarkResult { mean_speed, speed_standard_deviation: variance.sqrt(), amplitude, wave_speed }
+    }
+
+    // Shift of the speed profile along the ring between two samples that matches it best
+    fn wave_speed(&self, samples: &[Vec<(f32, f32)>]) -> f32 {
+        let profiles: Vec<Vec<f32>> = samples.iter().map(|sample| self.speed_profile(sample)).collect();
+        let bin_count = profiles.first().map_or(0, Vec::len) as isize;
+        let mut best = (0, f32::NEG_INFINITY);
+        for shift in -bin_count / 2..bin_count / 2 {
+            let correlation: f32 = profiles.windows(2).map(|pair| (0..bin_count)
+                .map(|bin| pair[1][bin as usize] * pair[0][(bin - shift).rem_euclid(bin_count) as usize])
+                .sum::<f32>()).sum();
+            if correlation > best.1 {
+                best = (shift, correlation);
+            }
+        }
+
+        best.0 as f32 * PROFILE_BIN_SIZE / SAMPLE_INTERVAL
+    }
+
+    // Speed along the ring, interpolated between the cars, minus its mean
+    fn speed_profile(&self, sample: &[(f32, f32)]) -> Vec<f32> {
+        let mut cars = sample.to_vec();
+        cars.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let bin_count = (self.circumference / PROFILE_BIN_SIZE).round() as usize;
+        let mut profile: Vec<f32> = (0..bin_count).map(|bin| {
+            let x = (bin as f32 + 0.5) * PROFILE_BIN_SIZE;
+            let ahead = cars.iter().position(|(position, _speed)| *position >= x).unwrap_or(0);
+            let (behind_position, behind_speed) = cars[(ahead + cars.len() - 1) % cars.len()];
+            let (ahead_position, ahead_speed) = cars[ahead];
+            let gap = (ahead_position - behind_position).rem_euclid(self.circumference);
+            if gap == 0. {
+                return ahead_speed;
+            }
+            let t = (x - behind_position).rem_euclid(self.circumference) / gap;
+            behind_speed + (ahead_speed - behind_speed) * t
+        }).collect();
+        let mean = profile.iter().sum::<f32>() / bin_count as f32;
+        for speed in &mut profile {
+            *speed -= mean;
+        }
+
+        profile
+    }
+}
+
+impl Default for RingBenchmark {
+    fn default() -> Self {
+        Self {
+            circumference: 230.,
+            car_count: 22,
+            driver: DriverProfile::default(),
+            initial_speed: 2.5,
+            perturbation: 2.,
+            duration: 600.,
+            warm_up: 300.,
+            step_size: 0.05,
+            seed: 1,
+        }
+    }
+}
+
+impl RingBenchmarkResult {
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "mean_speed_kmh,{:.2}", self.mean_speed * 3.6)?;
+        writeln!(writer, "speed_standard_deviation_kmh,{:.2}", self.speed_standard_deviation * 3.6)?;
+        writeln!(writer, "amplitude_kmh,{:.2}", self.amplitude * 3.6)?;
+        match self.wave_speed {
+            Some(wave_speed) => writeln!(writer, "wave_speed_kmh,{:.2}", wave_speed * 3.6)?,
+            None => writeln!(writer, "wave_speed_kmh,")?,
+        }
+
+        writer.flush()
+    }
+}
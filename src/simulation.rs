@@ -0,0 +1,463 @@
+use std::{collections::{HashMap, HashSet}, fs, io, path::Path};
+use crate::{agent::{car::{self, Car, Leader}, pedestrian::Pedestrian, spawner::Spawner}, emissions::{EmissionModel, Emissions}, gui, parking::Parking, output::{detectors::{CarMove, Detectors}, frames::FrameRecorder, replay::ReplayWriter, trajectories::{TrajectoryPoint, TrajectoryWriter}, trips::{NetworkSummary, TripStatistics}, EventLog}, road::{self, events::EventScheduler, travel_times::TravelTimes}, snapshot::{self, Snapshot, SnapshotReader, SnapshotWriter}, transit::Transit, utils::Rng};
+
+const DETECTOR_INTERVAL: f32 = 60.; // s
+const DEFAULT_SEED: u64 = 42;
+const SPAWN_GAP: f32 = 2.; // m, kept free around a spawned vehicle
+const CROSSING_SETBACK: f32 = 3.; // m, between the stop line and the RoadNode of a crossing
+const OVERTAKING_MARGIN: f32 = 2.; // m, kept behind and in front of an overtaken cyclist or incident
+const SNAPSHOT_MAGIC: &[u8; 4] = b"TSSN";
+const SNAPSHOT_VERSION: u8 = 1;
+
+pub struct SimulationData {
+    pub roads: road::Roads,
+    pub cars: Vec<Car>,
+    pub pedestrians: Vec<Pedestrian>,
+    pub spawners: Vec<Spawner>,
+    pub transit: Transit,
+    pub parking: Parking,
+    pub travel_times: TravelTimes,
+    pub events: EventScheduler,
+    pub blockages: Vec<Blockage>,
+    pub log: EventLog,
+    pub detectors: Detectors,
+    pub trips: TripStatistics,
+    pub trajectory_writers: Vec<TrajectoryWriter>,
+    pub replay_writer: Option<ReplayWriter>,
+    pub frame_recorder: Option<FrameRecorder>,
+    pub emission_model: EmissionModel,
+    // Indexed by RoadSegmentIdx
+    pub segment_emissions: Vec<Emissions>,
+    pub time: f32,
+    // Source of all the random draws, set the seed before adding spawners to reproduce a run
+    pub rng: Rng,
+    next_car_id: usize,
+}
+
+// Part of a RoadSegment that cannot be driven on. Segments have a single lane, so cars queue behind it, or pass it on the
+// opposite lane of two-way roads.
+pub struct Blockage {
+    pub start: road::RoadPoint,
+    pub end: road::RoadPoint,
+    pub clear_time: f32,
+}
+
+// Something cars cannot drive through, on a RoadSegment
+struct Obstacle {
+    front: f32,
+    rear: f32,
+    speed: f32,
+    car: Option<usize>,
+    // Stalled cars and blockages, which can be passed on the opposite lane
+    incident: bool,
+}
+
+impl SimulationData {
+    pub fn new(roads: road::Roads) -> Self {
+        let travel_times = TravelTimes::new(&roads, car::SPEED);
+        let segment_emissions = vec![Emissions::default(); roads.segment_count()];
+        Self {
+            roads,
+            cars: Vec::new(),
+            pedestrians: Vec::new(),
+            spawners: Vec::new(),
+            transit: Transit::new(),
+            parking: Parking::new(),
+            travel_times,
+            events: EventScheduler::new(),
+            blockages: Vec::new(),
+            log: EventLog::new(),
+            detectors: Detectors::new(DETECTOR_INTERVAL),
+            trips: TripStatistics::new(),
+            trajectory_writers: Vec::new(),
+            replay_writer: None,
+            frame_recorder: None,
+            emission_model: EmissionModel::new(),
+            segment_emissions,
+            time: 0.,
+            rng: Rng::new(DEFAULT_SEED),
+            next_car_id: 0,
+        }
+    }
+
+    // Returns the id given to the car
+    pub fn add_car(&mut self, mut car: Car) -> usize {
+        let id = self.next_car_id;
+        self.next_car_id += 1;
+        car.set_id(id);
+        self.cars.push(car);
+
+        id
+    }
+
+    // Returns false if there is no car with this id, or if the position is not on the path ahead of it
+    pub fn add_breakdown(&mut self, car: usize, position: road::RoadPoint, duration: f32) -> bool {
+        match self.cars.iter_mut().find(|other| other.id() == car) {
+            Some(car) => car.break_down_at(position, duration, &self.roads),
+            None => false,
+        }
+    }
+
+    pub fn add_blockage(&mut self, start: road::RoadPoint, length: f32, duration: f32) {
+        let end = road::RoadPoint::new(start.road_segment(), (start.position() + length).min(self.roads.segment_length(start.road_segment())));
+        self.log.log(self.time, format!("blockage from {} to {} started", start, end));
+        self.blockages.push(Blockage { start, end, clear_time: self.time + duration });
+    }
+
+    pub fn step(&mut self, step_size: f32) {
+        for event in self.events.apply_due(self.time, &mut self.roads) {
+            self.log.log(self.time, event.kind);
+        }
+        let (time, log) = (self.time, &mut self.log);
+        self.blockages.retain(|blockage| {
+            if blockage.clear_time <= time {
+                log.log(time, format!("blockage from {} to {} cleared", blockage.start, blockage.end));
+                return false;
+            }
+            true
+        });
+        self.spawn_cars();
+        // Measured on the cars only, cyclists being much slower
+        self.travel_times.update(step_size, &self.roads, self.cars.iter().filter(|car| !car.is_bicycle()).map(|car| (car.position().road_segment(), car.speed())));
+        for pedestrian in &mut self.pedestrians {
+            pedestrian.update(self.time, step_size, &self.roads);
+        }
+        let leaders = self.find_leaders();
+        let mut moves = Vec::new();
+        for (i, car) in self.cars.iter_mut().enumerate() {
+            let from = *car.position();
+            let was_broken_down = car.is_broken_down();
+            let emissions = car.update(self.time, step_size, &self.roads, &self.travel_times, &self.emission_model, leaders[i]);
+            self.segment_emissions[*car.position().road_segment()] += emissions;
+            for trip in car.take_finished_trips() {
+                self.trips.add(car.id(), trip);
+            }
+            if !was_broken_down && car.is_broken_down() {
+                self.log.log(self.time, format!("car {} broke down at {}", car.id(), car.position()));
+            } else if was_broken_down && !car.is_broken_down() {
+                self.log.log(self.time, format!("car {} breakdown cleared", car.id()));
+            }
+            moves.push(CarMove { from, to: *car.position(), speed: car.speed(), length: car.length() });
+        }
+        self.time += step_size;
+        self.transit.update(self.time, step_size, &mut self.cars, &mut self.log);
+        self.parking.update(step_size);
+        self.release_parked_cars();
+        self.remove_arrived_cars();
+        self.detectors.update(self.time, step_size, &moves);
+        if self.trajectory_writers.iter().any(|writer| writer.is_due(self.time)) {
+            let points = self.trajectory_points();
+            for writer in self.trajectory_writers.iter_mut().filter(|writer| writer.is_due(self.time)) {
+                writer.record(self.time, &points).expect("Could not write the trajectories");
+            }
+        }
+        if let Some(writer) = self.replay_writer.as_mut().filter(|writer| writer.is_due(self.time)) {
+            writer.record(self.time, &self.cars, &self.pedestrians, &self.roads).expect("Could not write the replay");
+        }
+        if let Some(recorder) = self.frame_recorder.as_mut().filter(|recorder| recorder.is_due(self.time)) {
+            recorder.record(self.time, &self.roads, &self.cars).expect("Could not write the frame");
+        }
+    }
+
+    // The state of the run, to carry on from it later with restore, e.g. to branch several experiments from a warmed up
+    // network. The signals follow the clock, so they are restored with it. The outputs (log, detectors, trajectory,
+    // replay and frame recorders) and the emission model are not part of it.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut writer = SnapshotWriter::new();
+        writer.write_bytes(SNAPSHOT_MAGIC);
+        writer.write(&SNAPSHOT_VERSION);
+        writer.write(&self.roads);
+        writer.write(&self.cars);
+        writer.write(&self.pedestrians);
+        writer.write(&self.spawners);
+        writer.write(&self.transit);
+        writer.write(&self.parking);
+        writer.write(&self.travel_times);
+        writer.write(&self.events);
+        writer.write(&self.blockages);
+        writer.write(&self.trips);
+        writer.write(&self.segment_emissions);
+        writer.write(&self.time);
+        writer.write(&self.rng);
+        writer.write(&self.next_car_id);
+
+        writer.into_bytes()
+    }
+
+    // Replaces the state of the run with the one of the snapshot, keeping the outputs. Nothing changes on an error.
+    pub fn restore(&mut self, snapshot: &[u8]) -> io::Result<()> {
+        let mut reader = SnapshotReader::new(snapshot);
+        if reader.read_bytes(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC || reader.read::<u8>()? != SNAPSHOT_VERSION {
+            return Err(snapshot::invalid_data("Not a snapshot file, or unsupported version"));
+        }
+        let roads: road::Roads = reader.read()?;
+        let cars: Vec<Car> = reader.read()?;
+        let pedestrians: Vec<Pedestrian> = reader.read()?;
+        let spawners: Vec<Spawner> = reader.read()?;
+        let transit: Transit = reader.read()?;
+        let parking: Parking = reader.read()?;
+        let travel_times: TravelTimes = reader.read()?;
+        let events: EventScheduler = reader.read()?;
+        let blockages: Vec<Blockage> = reader.read()?;
+        let trips = reader.read()?;
+        let segment_emissions: Vec<Emissions> = reader.read()?;
+        let (time, rng, next_car_id) = (reader.read()?, reader.read()?, reader.read()?);
+        if !reader.is_at_end() {
+            return Err(snapshot::invalid_data("Unexpected data after the snapshot"));
+        }
+        if segment_emissions.len() != roads.segment_count() {
+            return Err(snapshot::invalid_data("Segment emissions don't match the road network"));
+        }
+        if !cars.iter().all(|car| car.is_valid_on(&roads)) {
+            return Err(snapshot::invalid_data("Cars don't match the road network"));
+        }
+        if !pedestrians.iter().all(|pedestrian| pedestrian.is_valid_on(&roads)) {
+            return Err(snapshot::invalid_data("Pedestrians don't match the road network"));
+        }
+        if !spawners.iter().all(|spawner| spawner.is_valid_on(&roads)) {
+            return Err(snapshot::invalid_data("Spawners don't match the road network"));
+        }
+        if !transit.is_valid_on(&roads) {
+            return Err(snapshot::invalid_data("Bus lines don't match the road network"));
+        }
+        if !parking.is_valid_on(&roads) {
+            return Err(snapshot::invalid_data("Parking doesn't match the road network"));
+        }
+        if !travel_times.matches(&roads) {
+            return Err(snapshot::invalid_data("Travel times don't match the road network"));
+        }
+        if !events.is_valid_on(&roads) || !blockages.iter().all(|blockage| blockage.is_valid_on(&roads)) {
+            return Err(snapshot::invalid_data("Network events don't match the road network"));
+        }
+        (self.roads, self.cars, self.pedestrians, self.spawners, self.transit, self.parking) = (roads, cars, pedestrians, spawners, transit, parking);
+        (self.travel_times, self.events, self.blockages, self.trips) = (travel_times, events, blockages, trips);
+        (self.segment_emissions, self.time, self.rng, self.next_car_id) = (segment_emissions, time, rng, next_car_id);
+        self.log.log(self.time, "restored from a snapshot");
+
+        Ok(())
+    }
+
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> io::Result<()> { fs::write(path, self.snapshot()) }
+
+    pub fn load_snapshot(&mut self, path: impl AsRef<Path>) -> io::Result<()> { self.restore(&fs::read(path)?) }
+
+    pub fn trajectory_points(&self) -> Vec<TrajectoryPoint> {
+        self.cars.iter().map(|car| {
+            let (x, y) = self.roads.get_position_xy(car.position());
+            TrajectoryPoint { car: car.id(), time: self.time, position: *car.position(), x, y, speed: car.speed(), acceleration: car.acceleration(), target_speed: car.target_speed() }
+        }).collect()
+    }
+
+    pub fn network_summary(&self) -> NetworkSummary {
+        // Cars which left the simulation only remain in the trips
+        let total_distance = self.trips.trips().iter().map(|(_car, trip)| trip.distance).sum::<f32>()
+            + self.cars.iter().filter_map(|car| car.current_trip()).map(|trip| trip.distance).sum::<f32>();
+        let unfinished_delay = self.cars.iter().filter_map(|car| car.current_trip()).map(|trip| trip.delay()).sum();
+        let mut unfinished_emissions = Emissions::default();
+        for trip in self.cars.iter().filter_map(|car| car.current_trip()) {
+            unfinished_emissions += trip.emissions;
+        }
+        self.trips.summary(total_distance, unfinished_delay, unfinished_emissions)
+    }
+
+    // Spawners and bus lines wait while the vehicle they create would overlap another one
+    fn spawn_cars(&mut self) {
+        for i in 0..self.spawners.len() {
+            let Some(car) = self.spawners[i].spawn(self.time, &mut self.rng) else {
+                continue;
+            };
+            if has_room(&self.cars, car.position(), car.length(), None) {
+                let (class, position) = (car.vehicle_type().class, *car.position());
+                let id = self.add_car(car);
+                self.log.log(self.time, format!("car {} ({:?}) spawned at {}", id, class, position));
+            } else {
+                self.spawners[i].postpone(car);
+            }
+        }
+        for (run, bus) in self.transit.spawn(self.time, &mut self.rng) {
+            if has_room(&self.cars, bus.position(), bus.length(), None) {
+                let id = self.add_car(bus);
+                self.transit.register(id, run);
+                self.log.log(self.time, format!("car {} departed as bus {} run {}", id, self.transit.lines()[run.line].name, run.run));
+            } else {
+                self.transit.postpone(run, bus);
+            }
+        }
+        // Buses in bays wait for a gap to get back in the lane
+        for i in 0..self.cars.len() {
+            let car = &self.cars[i];
+            if car.is_leaving_bay() && has_room(&self.cars, car.position(), car.length(), Some(car.id())) {
+                self.cars[i].leave_stop();
+            }
+        }
+    }
+
+    // Cars which reached the end of their Route, or found a parking space, leave the simulation
+    // Parked cars are kept by Parking until they leave
+    fn remove_arrived_cars(&mut self) {
+        let (roads, parking) = (&self.roads, &self.parking);
+        let removed: Vec<Car> = self.cars.extract_if(.., |car| car.has_arrived(roads) || parking.free_facility(car, roads).is_some()).collect();
+        for mut car in removed {
+            if let Some(trip) = car.end_trip() {
+                self.trips.add(car.id(), trip);
+            }
+            match self.parking.free_facility(&car, &self.roads) {
+                Some(facility) => {
+                    self.log.log(self.time, format!("car {} parked in parking {} after cruising {:.0} m", car.id(), facility, car.cruising_distance()));
+                    self.parking.park(facility, car, self.time);
+                },
+                None => {
+                    self.log.log(self.time, format!("car {} arrived at {}", car.id(), car.position()));
+                    self.transit.unregister(car.id());
+                },
+            }
+        }
+    }
+
+    // Back where they parked, once there is room, to drive back to where they came from
+    fn release_parked_cars(&mut self) {
+        let (roads, cars) = (&self.roads, &self.cars);
+        let leaving = self.parking.release(self.time, &mut self.log, |car, leaving| {
+            has_room(cars, car.position(), car.length(), None) && has_room(leaving, car.position(), car.length(), None) && car.start_return_trip(roads)
+        });
+        self.cars.extend(leaving);
+    }
+
+    // RoadNodes of the crossings where cars must stop: during the walk phase of signalised ones, and when
+    // pedestrians are on or about to step on zebra ones
+    fn blocked_crossings(&self) -> HashSet<road::RoadNodeIdx> {
+        let mut blocked: HashSet<road::RoadNodeIdx> = self.pedestrians.iter().filter_map(|pedestrian| pedestrian.blocked_crossing(&self.roads)).collect();
+        for crossing in self.roads.crossings() {
+            if matches!(crossing.kind, road::CrossingKind::Signalised { .. }) && crossing.is_walk_phase(self.time) {
+                blocked.insert(crossing.node);
+            }
+        }
+
+        blocked
+    }
+
+    fn find_leaders(&self) -> Vec<Option<Leader>> {
+        let mut obstacles: HashMap<road::RoadSegmentIdx, Vec<Obstacle>> = HashMap::new();
+        // Buses in bays are out of the lane
+        for (i, car) in self.cars.iter().enumerate().filter(|(_i, car)| !car.is_in_bay()) {
+            let position = car.position();
+            obstacles.entry(position.road_segment()).or_default().push(Obstacle { front: position.position(), rear: position.position() - car.length(), speed: car.speed(), car: Some(i), incident: car.is_broken_down() });
+        }
+        for blockage in &self.blockages {
+            obstacles.entry(blockage.start.road_segment()).or_default().push(Obstacle { front: blockage.end.position(), rear: blockage.start.position(), speed: 0., car: None, incident: true });
+        }
+        // Cars stop at the stop line, those already past it go on
+        for node in self.blocked_crossings() {
+            for segment in self.roads.incoming_segments(node) {
+                let stop_line = (self.roads.segment_length(segment) - CROSSING_SETBACK).max(0.);
+                obstacles.entry(segment).or_default().push(Obstacle { front: stop_line, rear: stop_line, speed: 0., car: None, incident: false });
+            }
+        }
+        let mut leaders = Vec::new();
+        for (i, car) in self.cars.iter().enumerate() {
+            let position = car.position();
+            let is_ahead = |obstacle: &&Obstacle| match obstacle.car {
+                Some(j) => j != i && (obstacle.front > position.position() || (obstacle.front == position.position() && j < i)),
+                None => obstacle.front > position.position(),
+            };
+            let closest = |obstacles: &mut dyn Iterator<Item = &Obstacle>, distance: f32| obstacles
+                .map(|obstacle| Leader { gap: (distance + obstacle.rear).max(0.), speed: obstacle.speed })
+                .min_by(|a, b| a.gap.total_cmp(&b.gap));
+            let mut leader = obstacles.get(&position.road_segment())
+                .and_then(|segment_obstacles| closest(&mut segment_obstacles.iter().filter(is_ahead).filter(|obstacle| self.interacts(i, obstacle, position.road_segment(), true)), -position.position()));
+            let mut distance = self.roads.segment_length(position.road_segment()) - position.position();
+            if leader.is_none() {
+                for segment in car.remaining_segments(&self.roads) {
+                    if distance > car::SEEING_DISTANCE {
+                        break;
+                    }
+                    leader = obstacles.get(&segment).and_then(|segment_obstacles| closest(&mut segment_obstacles.iter().filter(|obstacle| self.interacts(i, obstacle, segment, false)), distance));
+                    if leader.is_some() {
+                        break;
+                    }
+                    distance += self.roads.segment_length(segment);
+                }
+            }
+            leaders.push(leader);
+        }
+
+        leaders
+    }
+
+    // Whether car i must stay behind the obstacle, on the given segment. Cyclists and cars ignore each other on dedicated
+    // bike lanes, and cars overtake cyclists when there is a safe gap on shared ones. Incidents are passed the same way on
+    // two-way roads, where the opposite lane is the only other one, the car entering their segment before judging the gap.
+    fn interacts(&self, i: usize, obstacle: &Obstacle, segment: road::RoadSegmentIdx, on_same_segment: bool) -> bool {
+        if obstacle.incident {
+            let (from, to) = self.roads.segment_nodes(segment);
+            if self.roads.segment_between(to, from).is_none() {
+                return true;
+            }
+            let car = &self.cars[i];
+            return on_same_segment && obstacle.rear >= car.position().position() && !self.can_overtake(car, obstacle, segment);
+        }
+        let Some(j) = obstacle.car else {
+            return true;
+        };
+        let (car, other) = (&self.cars[i], &self.cars[j]);
+        match self.roads.bike_lane(segment) {
+            road::BikeLane::Dedicated => car.is_bicycle() == other.is_bicycle(),
+            road::BikeLane::Shared if !on_same_segment => true,
+            road::BikeLane::Shared => match (car.is_bicycle(), other.is_bicycle()) {
+                // A car alongside, or pulling away right in front, is overtaking the cyclist
+                (true, false) => obstacle.rear >= car.position().position() + OVERTAKING_MARGIN || obstacle.speed <= car.speed(),
+                // Once alongside, the car goes on with the overtaking
+                (false, true) => obstacle.rear >= car.position().position() && !self.can_overtake(car, obstacle, segment),
+                _ => true,
+            },
+        }
+    }
+
+    // The car must be able to pass the obstacle before the end of the segment, with no oncoming vehicle reaching it meanwhile.
+    // Vehicles entering the opposite segment during the overtaking are not anticipated.
+    fn can_overtake(&self, car: &Car, obstacle: &Obstacle, segment: road::RoadSegmentIdx) -> bool {
+        let speed = car.desired_speed();
+        let passing_time = (obstacle.front - obstacle.rear + car.length() + 2. * OVERTAKING_MARGIN) / (speed - obstacle.speed).max(1.);
+        let passing_distance = passing_time * speed;
+        let position = car.position().position();
+        if self.roads.segment_length(segment) - position < passing_distance {
+            return false;
+        }
+        let (from, to) = self.roads.segment_nodes(segment);
+        let Some(opposite) = self.roads.segment_between(to, from) else {
+            return true;
+        };
+        let opposite_length = self.roads.segment_length(opposite);
+        !self.cars.iter().filter(|other| other.position().road_segment() == opposite && !other.is_bicycle()).any(|other| {
+            let distance = opposite_length - other.position().position() - position;
+            distance > 0. && distance < passing_distance + passing_time * other.speed()
+        })
+    }
+}
+
+// Whether a vehicle of the given length can be placed at position without overlapping a car other than except
+fn has_room(cars: &[Car], position: &road::RoadPoint, length: f32, except: Option<usize>) -> bool {
+    cars.iter().filter(|other| Some(other.id()) != except).all(|other| other.position().road_segment() != position.road_segment()
+        || other.position().position() - other.length() >= position.position() + SPAWN_GAP
+        || other.position().position() + SPAWN_GAP <= position.position() - length)
+}
+
+impl Blockage {
+    pub fn is_valid_on(&self, roads: &road::Roads) -> bool {
+        roads.contains(&self.start) && roads.contains(&self.end) && self.start.road_segment() == self.end.road_segment()
+    }
+
+    pub fn render(&self, window: &gui::Window, roads: &road::Roads) {
+        window.draw_blockage(roads.get_position_xy(&self.start), roads.get_position_xy(&self.end));
+    }
+}
+
+impl Snapshot for Blockage {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.start);
+        writer.write(&self.end);
+        writer.write(&self.clear_time);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { start: reader.read()?, end: reader.read()?, clear_time: reader.read()? }) }
+}
@@ -0,0 +1,176 @@
+use std::{collections::{HashMap, VecDeque}, hash::Hash, io};
+
+// Little-endian binary encoding of the state of a simulation, see SimulationData::snapshot. Each type writes its
+// fields in the order they are declared in, sequences being preceded by their length and enums by the index of the
+// variant. Nothing describes the layout in the file, so a snapshot is only meant to be read by the same version.
+pub trait Snapshot: Sized {
+    fn save(&self, writer: &mut SnapshotWriter);
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self>;
+}
+
+#[derive(Default)]
+pub struct SnapshotWriter {
+    bytes: Vec<u8>,
+}
+
+pub struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl SnapshotWriter {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn into_bytes(self) -> Vec<u8> { self.bytes }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn write<T: Snapshot>(&mut self, value: &T) {
+        value.save(self);
+    }
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self { Self { bytes, position: 0 } }
+
+    pub fn is_at_end(&self) -> bool { self.position >= self.bytes.len() }
+
+    pub fn read_bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let end = self.position.checked_add(count).filter(|end| *end <= self.bytes.len()).ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Snapshot ended early"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    pub fn read<T: Snapshot>(&mut self) -> io::Result<T> { T::load(self) }
+
+    // Index of an enum variant, among count of them
+    pub fn read_variant(&mut self, count: u8) -> io::Result<u8> {
+        let variant = self.read::<u8>()?;
+        if variant < count { Ok(variant) } else { Err(invalid_data(&format!("Unknown variant {}", variant))) }
+    }
+}
+
+pub fn invalid_data(message: &str) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, message.to_string()) }
+
+macro_rules! impl_snapshot_for_number {
+    ( $( $Type: ty ),* ) => {
+        $(
+            impl Snapshot for $Type {
+                fn save(&self, writer: &mut SnapshotWriter) {
+                    writer.write_bytes(&self.to_le_bytes());
+                }
+
+                fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+                    let bytes = reader.read_bytes(std::mem::size_of::<$Type>())?;
+                    Ok(<$Type>::from_le_bytes(bytes.try_into().expect("Could not read a number")))
+                }
+            }
+        )*
+    };
+}
+
+impl_snapshot_for_number!(u8, u32, u64, f32);
+
+// As 8 bytes, whatever the platform
+impl Snapshot for usize {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&(*self as u64));
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        usize::try_from(reader.read::<u64>()?).map_err(|_error| invalid_data("Index too large"))
+    }
+}
+
+impl Snapshot for bool {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&(*self as u8));
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(reader.read_variant(2)? == 1) }
+}
+
+impl Snapshot for String {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.len());
+        writer.write_bytes(self.as_bytes());
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        let length = reader.read()?;
+        String::from_utf8(reader.read_bytes(length)?.to_vec()).map_err(|_error| invalid_data("Invalid text"))
+    }
+}
+
+impl<T: Snapshot> Snapshot for Option<T> {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.is_some());
+        if let Some(value) = self {
+            writer.write(value);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        if reader.read()? { Ok(Some(reader.read()?)) } else { Ok(None) }
+    }
+}
+
+impl<T: Snapshot> Snapshot for Vec<T> {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.len());
+        for value in self {
+            writer.write(value);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        let length: usize = reader.read()?;
+        (0..length).map(|_i| reader.read()).collect()
+    }
+}
+
+impl<T: Snapshot> Snapshot for VecDeque<T> {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.len());
+        for value in self {
+            writer.write(value);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(reader.read::<Vec<T>>()?.into()) }
+}
+
+impl<A: Snapshot, B: Snapshot> Snapshot for (A, B) {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.0);
+        writer.write(&self.1);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok((reader.read()?, reader.read()?)) }
+}
+
+// Sorted by the encoding of the entries, so that equal maps give the same bytes whatever their iteration order
+impl<K: Snapshot + Eq + Hash, V: Snapshot> Snapshot for HashMap<K, V> {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        let mut entries: Vec<Vec<u8>> = self.iter().map(|(key, value)| {
+            let mut entry = SnapshotWriter::new();
+            entry.write(key);
+            entry.write(value);
+            entry.into_bytes()
+        }).collect();
+        entries.sort();
+        writer.write(&entries.len());
+        for entry in entries {
+            writer.write_bytes(&entry);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        let length: usize = reader.read()?;
+        (0..length).map(|_i| Ok((reader.read()?, reader.read()?))).collect()
+    }
+}
@@ -0,0 +1,398 @@
+use std::{collections::HashMap, fs::File, io::{self, BufWriter, Write}, path::Path};
+use crate::{agent::{car::{Car, Stop, TripPlan}, driver::DriverProfile, vehicle::VehicleClass}, gui, output::EventLog, road, snapshot::{Snapshot, SnapshotReader, SnapshotWriter}, utils::Rng};
+
+const DEAD_TIME: f32 = 4.; // s, to open and close the doors
+const BOARDING_TIME: f32 = 2.5; // s per passenger
+const EARLY_TOLERANCE: f32 = 60.; // s, a bus is on time up to this much early...
+const LATE_TOLERANCE: f32 = 180.; // s, ...and this much late
+// Buses arriving at a stop less than this share of the scheduled headway after the previous one are bunched
+const BUNCHING_HEADWAY_SHARE: f32 = 0.25;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopKind {
+    // Out of the lane, the traffic can pass the bus
+    Bay,
+    // In the lane, the traffic queues behind the bus
+    InLane,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BusStop {
+    pub name: String,
+    pub position: road::RoadPoint,
+    pub kind: StopKind,
+    pub passenger_arrival_rate: f32, // passengers/s
+    // Scheduled arrival at the stop, after the departure of the bus
+    pub scheduled_offset: f32, // s
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Departures {
+    Timetable(Vec<f32>),
+    Headway { first: f32, headway: f32, count: usize },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BusLine {
+    pub name: String,
+    // Connected, each one starting where the previous one ends. Buses start at the beginning of the first one.
+    pub segments: Vec<road::RoadSegmentIdx>,
+    // In the order of the segments
+    pub stops: Vec<BusStop>,
+    pub departures: Departures,
+}
+
+// A departure of a bus on a line
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BusRun {
+    pub line: usize,
+    pub run: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StopRecord {
+    pub run: BusRun,
+    pub stop: usize,
+    pub scheduled_arrival: f32,
+    pub arrival: f32,
+    pub boardings: usize,
+    pub dwell_time: f32,
+    // Since the previous bus of the line at this stop
+    pub headway: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineSummary {
+    pub stop_arrivals: usize,
+    // Absolute difference between the scheduled and actual arrivals
+    pub mean_schedule_deviation: f32, // s
+    pub on_time_share: f32,
+    pub mean_headway: f32, // s
+    // Standard deviation of the headways over their mean, 0 for a perfectly regular service
+    pub headway_variation: f32,
+    pub bunched_arrivals: usize,
+}
+
+#[derive(Default)]
+pub struct Transit {
+    lines: Vec<BusLine>,
+    // Indexed like lines
+    routes: Vec<Vec<road::RoadNodeIdx>>,
+    departure_times: Vec<Vec<f32>>,
+    next_runs: Vec<usize>,
+    waiting_buses: Vec<Option<(BusRun, Car)>>,
+    // Indexed by line then stop
+    waiting_passengers: Vec<Vec<f32>>,
+    last_arrivals: Vec<Vec<Option<f32>>>,
+    // Run and index of the next stop of the buses on the road, by car id
+    buses: HashMap<usize, (BusRun, usize)>,
+    records: Vec<StopRecord>,
+}
+
+impl Departures {
+    pub fn times(&self) -> Vec<f32> {
+        match self {
+            Departures::Timetable(times) => times.clone(),
+            Departures::Headway { first, headway, count } => (0..*count).map(|run| first + run as f32 * headway).collect(),
+        }
+    }
+}
+
+impl Transit {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn lines(&self) -> &[BusLine] { &self.lines }
+
+    pub fn records(&self) -> &[StopRecord] { &self.records }
+
+    pub fn add_line(&mut self, line: BusLine, roads: &road::Roads) {
+        let first_segment = *line.segments.first().expect("Could not add a bus line without segments");
+        let mut route = vec![roads.segment_nodes(first_segment).0];
+        for segment in &line.segments {
+            let (from, to) = roads.segment_nodes(*segment);
+            assert!(route.last() == Some(&from), "Could not add bus line {}: its segments are not connected", line.name);
+            route.push(to);
+        }
+        let mut departure_times = line.departures.times();
+        departure_times.sort_by(f32::total_cmp);
+        self.routes.push(route);
+        self.departure_times.push(departure_times);
+        self.next_runs.push(0);
+        self.waiting_buses.push(None);
+        self.waiting_passengers.push(vec![0.; line.stops.len()]);
+        self.last_arrivals.push(vec![None; line.stops.len()]);
+        self.lines.push(line);
+    }
+
+    // Whether the lines fit in the network, and the state of the buses, stops and records refers to existing lines, runs
+    // and stops
+    pub fn is_valid_on(&self, roads: &road::Roads) -> bool {
+        let line_count = self.lines.len();
+        if [self.routes.len(), self.departure_times.len(), self.next_runs.len(), self.waiting_buses.len(), self.waiting_passengers.len(), self.last_arrivals.len()].iter().any(|len| *len != line_count) {
+            return false;
+        }
+        let run_exists = |run: &BusRun| self.departure_times.get(run.line).is_some_and(|departure_times| run.run < departure_times.len());
+        let lines_are_valid = self.lines.iter().enumerate().all(|(i, line)| {
+            !line.segments.is_empty()
+                && line.segments.iter().all(|segment| roads.has_segment(*segment))
+                && line.stops.iter().all(|stop| roads.contains(&stop.position))
+                && road::path::Path::from(self.routes[i].clone()).is_valid_on(roads)
+                && self.waiting_passengers[i].len() == line.stops.len()
+                && self.last_arrivals[i].len() == line.stops.len()
+                && self.waiting_buses[i].as_ref().is_none_or(|(run, bus)| run.line == i && run_exists(run) && bus.is_valid_on(roads))
+        });
+        lines_are_valid
+            && self.buses.values().all(|(run, next_stop)| run_exists(run) && *next_stop <= self.lines[run.line].stops.len())
+            && self.records.iter().all(|record| run_exists(&record.run) && record.stop < self.lines[record.run.line].stops.len())
+    }
+
+    pub fn run_of(&self, car_id: usize) -> Option<BusRun> { self.buses.get(&car_id).map(|(run, _next_stop)| *run) }
+
+    // The buses due to depart. They must be registered once added to the simulation, or given back with postpone if there is no room for them yet.
+    pub fn spawn(&mut self, time: f32, rng: &mut Rng) -> Vec<(BusRun, Car)> {
+        let mut buses = Vec::new();
+        for line in 0..self.lines.len() {
+            if let Some(bus) = self.waiting_buses[line].take() {
+                buses.push(bus);
+                continue;
+            }
+            let run = self.next_runs[line];
+            if self.departure_times[line].get(run).is_none_or(|departure| time < *departure) {
+                continue;
+            }
+            self.next_runs[line] += 1;
+            let start = road::RoadPoint::new(self.lines[line].segments[0], 0.);
+            let mut bus = Car::new(start, VehicleClass::Bus.default_type(), DriverProfile::default(), TripPlan::Route(self.routes[line].clone()), false, rng.fork());
+            for stop in &self.lines[line].stops {
+                bus.add_stop(Stop { position: stop.position, in_bay: stop.kind == StopKind::Bay });
+            }
+            buses.push((BusRun { line, run }, bus));
+        }
+
+        buses
+    }
+
+    pub fn postpone(&mut self, run: BusRun, bus: Car) {
+        self.waiting_buses[run.line] = Some((run, bus));
+    }
+
+    pub fn register(&mut self, car_id: usize, run: BusRun) {
+        self.buses.insert(car_id, (run, 0));
+    }
+
+    // The bus left the simulation
+    pub fn unregister(&mut self, car_id: usize) {
+        self.buses.remove(&car_id);
+    }
+
+    // Lets the passengers arrive at the stops, and board the buses which just arrived
+    pub fn update(&mut self, time: f32, step_size: f32, cars: &mut [Car], log: &mut EventLog) {
+        for (line, waiting_passengers) in self.lines.iter().zip(&mut self.waiting_passengers) {
+            for (stop, waiting) in line.stops.iter().zip(waiting_passengers.iter_mut()) {
+                *waiting += stop.passenger_arrival_rate * step_size;
+            }
+        }
+        for car in cars.iter_mut().filter(|car| car.has_arrived_at_stop()) {
+            let Some((run, next_stop)) = self.buses.get_mut(&car.id()) else {
+                continue;
+            };
+            let (line, stop) = (&self.lines[run.line], *next_stop);
+            let waiting = &mut self.waiting_passengers[run.line][stop];
+            let boardings = waiting.floor();
+            *waiting -= boardings;
+            let dwell_time = DEAD_TIME + BOARDING_TIME * boardings;
+            car.dwell(dwell_time);
+            let last_arrival = &mut self.last_arrivals[run.line][stop];
+            self.records.push(StopRecord {
+                run: *run,
+                stop,
+                scheduled_arrival: self.departure_times[run.line][run.run] + line.stops[stop].scheduled_offset,
+                arrival: time,
+                boardings: boardings as usize,
+                dwell_time,
+                headway: last_arrival.map(|last_arrival| time - last_arrival),
+            });
+            *last_arrival = Some(time);
+            log.log(time, format!("bus {} run {} at stop {}, {} boardings", line.name, run.run, line.stops[stop].name, boardings));
+            *next_stop += 1;
+        }
+    }
+
+    pub fn summary(&self, line: usize) -> LineSummary {
+        let records: Vec<&StopRecord> = self.records.iter().filter(|record| record.run.line == line).collect();
+        let deviations: Vec<f32> = records.iter().map(|record| record.arrival - record.scheduled_arrival).collect();
+        let headways: Vec<f32> = records.iter().filter_map(|record| record.headway).collect();
+        let mean = |values: &[f32]| if values.is_empty() { 0. } else { values.iter().sum::<f32>() / values.len() as f32 };
+        let mean_headway = mean(&headways);
+        let headway_deviation = mean(&headways.iter().map(|headway| (headway - mean_headway).powi(2)).collect::<Vec<_>>()).sqrt();
+        let scheduled_headway = |record: &StopRecord| match record.run.run {
+            0 => None,
+            run => Some(self.departure_times[line][run] - self.departure_times[line][run - 1]),
+        };
+        LineSummary {
+            stop_arrivals: records.len(),
+            mean_schedule_deviation: mean(&deviations.iter().map(|deviation| deviation.abs()).collect::<Vec<_>>()),
+            on_time_share: deviations.iter().filter(|deviation| (-EARLY_TOLERANCE..=LATE_TOLERANCE).contains(*deviation)).count() as f32 / records.len().max(1) as f32,
+            mean_headway,
+            headway_variation: if mean_headway > 0. { headway_deviation / mean_headway } else { 0. },
+            bunched_arrivals: records.iter()
+                .filter(|record| record.headway.zip(scheduled_headway(record)).is_some_and(|(headway, scheduled)| headway < BUNCHING_HEADWAY_SHARE * scheduled))
+                .count(),
+        }
+    }
+
+    pub fn write_stop_records(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "line,run,stop,scheduled_arrival_s,arrival_s,deviation_s,boardings,dwell_time_s,headway_s")?;
+        for record in &self.records {
+            let line = &self.lines[record.run.line];
+            writeln!(
+                writer, "{},{},{},{:.1},{:.1},{:.1},{},{:.1},{}",
+                line.name, record.run.run, line.stops[record.stop].name, record.scheduled_arrival, record.arrival, record.arrival - record.scheduled_arrival,
+                record.boardings, record.dwell_time, record.headway.map(|headway| format!("{:.1}", headway)).unwrap_or_default()
+            )?;
+        }
+
+        writer.flush()
+    }
+
+    pub fn write_summary(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "line,stop_arrivals,mean_schedule_deviation_s,on_time_share,mean_headway_s,headway_variation,bunched_arrivals")?;
+        for (i, line) in self.lines.iter().enumerate() {
+            let summary = self.summary(i);
+            writeln!(
+                writer, "{},{},{:.1},{:.3},{:.1},{:.3},{}",
+                line.name, summary.stop_arrivals, summary.mean_schedule_deviation, summary.on_time_share, summary.mean_headway, summary.headway_variation, summary.bunched_arrivals
+            )?;
+        }
+
+        writer.flush()
+    }
+
+    pub fn render(&self, window: &gui::Window, roads: &road::Roads) {
+        for stop in self.lines.iter().flat_map(|line| &line.stops) {
+            window.draw_bus_stop(roads.get_position_xy(&stop.position), stop.kind == StopKind::Bay);
+        }
+    }
+}
+
+impl Snapshot for Transit {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.lines);
+        writer.write(&self.routes);
+        writer.write(&self.departure_times);
+        writer.write(&self.next_runs);
+        writer.write(&self.waiting_buses);
+        writer.write(&self.waiting_passengers);
+        writer.write(&self.last_arrivals);
+        writer.write(&self.buses);
+        writer.write(&self.records);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self {
+            lines: reader.read()?,
+            routes: reader.read()?,
+            departure_times: reader.read()?,
+            next_runs: reader.read()?,
+            waiting_buses: reader.read()?,
+            waiting_passengers: reader.read()?,
+            last_arrivals: reader.read()?,
+            buses: reader.read()?,
+            records: reader.read()?,
+        })
+    }
+}
+
+impl Snapshot for BusLine {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.name);
+        writer.write(&self.segments);
+        writer.write(&self.stops);
+        writer.write(&self.departures);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { name: reader.read()?, segments: reader.read()?, stops: reader.read()?, departures: reader.read()? }) }
+}
+
+impl Snapshot for BusStop {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.name);
+        writer.write(&self.position);
+        writer.write(&self.kind);
+        writer.write(&self.passenger_arrival_rate);
+        writer.write(&self.scheduled_offset);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self { name: reader.read()?, position: reader.read()?, kind: reader.read()?, passenger_arrival_rate: reader.read()?, scheduled_offset: reader.read()? })
+    }
+}
+
+impl Snapshot for StopKind {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&(*self as u8));
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(if reader.read_variant(2)? == 0 { StopKind::Bay } else { StopKind::InLane })
+    }
+}
+
+impl Snapshot for Departures {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        match self {
+            Departures::Timetable(times) => {
+                writer.write(&0u8);
+                writer.write(times);
+            },
+            Departures::Headway { first, headway, count } => {
+                writer.write(&1u8);
+                writer.write(first);
+                writer.write(headway);
+                writer.write(count);
+            },
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(match reader.read_variant(2)? {
+            0 => Departures::Timetable(reader.read()?),
+            _ => Departures::Headway { first: reader.read()?, headway: reader.read()?, count: reader.read()? },
+        })
+    }
+}
+
+impl Snapshot for BusRun {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.line);
+        writer.write(&self.run);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self { line: reader.read()?, run: reader.read()? }) }
+}
+
+impl Snapshot for StopRecord {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.run);
+        writer.write(&self.stop);
+        writer.write(&self.scheduled_arrival);
+        writer.write(&self.arrival);
+        writer.write(&self.boardings);
+        writer.write(&self.dwell_time);
+        writer.write(&self.headway);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> {
+        Ok(Self {
+            run: reader.read()?,
+            stop: reader.read()?,
+            scheduled_arrival: reader.read()?,
+            arrival: reader.read()?,
+            boardings: reader.read()?,
+            dwell_time: reader.read()?,
+            headway: reader.read()?,
+        })
+    }
+}
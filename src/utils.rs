@@ -1,3 +1,6 @@
+use std::io;
+use crate::snapshot::{Snapshot, SnapshotReader, SnapshotWriter};
+
 #[macro_export]
 macro_rules! generate_custom_vec {
     ( $Type: ident, $IdxType: ident ) => {
@@ -33,4 +36,47 @@ macro_rules! generate_custom_vec {
         }
 
     };
-}
\ No newline at end of file
+}
+
+// Small seeded pseudo-random generator (SplitMix64), so that runs can be reproduced and the state saved
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self { Self { state: seed } }
+
+    pub fn state(&self) -> u64 { self.state }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 { (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32 }
+
+    pub fn gen_range(&mut self, low: f32, high: f32) -> f32 { low + (high - low) * self.next_f32() }
+
+    // Box-Muller transform
+    pub fn normal(&mut self, mean: f32, standard_deviation: f32) -> f32 {
+        let u1 = 1. - self.next_f32();
+        let u2 = self.next_f32();
+        mean + standard_deviation * (-2. * u1.ln()).sqrt() * (2. * std::f32::consts::PI * u2).cos()
+    }
+
+    // A generator for a sub-system, independent from this one's future draws
+    pub fn fork(&mut self) -> Self { Self::new(self.next_u64()) }
+}
+
+impl Snapshot for Rng {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.state);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> io::Result<Self> { Ok(Self::new(reader.read()?)) }
+}
@@ -0,0 +1,75 @@
+use traffic_simulator::{agent::{car::{Car, TripPlan}, driver::DriverProfile, vehicle::VehicleClass}, road, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+const ROAD_LENGTH: f32 = 300.; // m
+const DURATION: f32 = 30.; // s
+// On the westbound road, 80 m from its end: close enough to delay the overtaking
+const ONCOMING_POSITION: f32 = ROAD_LENGTH - 80.; // m
+
+// A two-way road, with a cyclist ahead of a car in one direction and optionally an oncoming car
+fn road_with_cyclist(bike_lane: road::BikeLane, oncoming_position: Option<f32>) -> SimulationData {
+    let mut roads = road::Roads::empty();
+    let (west, east) = (roads.add_node((0., 0.)), roads.add_node((ROAD_LENGTH, 0.)));
+    let eastbound = roads.add_segment(west, east, &[]);
+    let westbound = roads.add_segment(east, west, &[]);
+    roads.set_bike_lane(eastbound, bike_lane);
+    let mut sd = SimulationData::new(roads);
+    let add = |sd: &mut SimulationData, position: road::RoadPoint, class: VehicleClass, destination: road::RoadNodeIdx, speed: f32| {
+        let trip_plan = TripPlan::Route(vec![sd.roads.segment_nodes(position.road_segment()).0, destination]);
+        let mut car = Car::new(position, class.default_type(), DriverProfile::default(), trip_plan, false, sd.rng.fork());
+        car.set_speed(speed);
+        sd.add_car(car)
+    };
+    add(&mut sd, road::RoadPoint::new(eastbound, 30.), VehicleClass::Bicycle, east, 5.);
+    add(&mut sd, road::RoadPoint::new(eastbound, 10.), VehicleClass::Car, east, 10.);
+    if let Some(position) = oncoming_position {
+        add(&mut sd, road::RoadPoint::new(westbound, position), VehicleClass::Car, west, 10.);
+    }
+
+    sd
+}
+
+// Runs the simulation until the car is ahead of the cyclist, and returns the time at which it got there along with the
+// position of the oncoming car, if any, along the eastbound road
+fn overtaking(sd: &mut SimulationData) -> Option<(f32, Option<f32>)> {
+    while sd.time < DURATION {
+        sd.step(STEP_SIZE);
+        let position = |id: usize| sd.cars.iter().find(|car| car.id() == id).map(|car| *car.position());
+        let (Some(cyclist), Some(car)) = (position(0), position(1)) else {
+            return None;
+        };
+        assert_eq!(car.road_segment(), cyclist.road_segment());
+        if car.position() - sd.cars[1].length() > cyclist.position() {
+            return Some((sd.time, position(2).map(|oncoming| ROAD_LENGTH - oncoming.position())));
+        }
+    }
+
+    None
+}
+
+#[test]
+fn cars_overtake_cyclists_on_an_empty_road() {
+    let mut sd = road_with_cyclist(road::BikeLane::Shared, None);
+
+    assert!(overtaking(&mut sd).is_some_and(|(time, _oncoming)| time < 10.), "The car stayed behind the cyclist");
+}
+
+#[test]
+fn cars_wait_for_oncoming_traffic_before_overtaking_cyclists() {
+    let mut sd = road_with_cyclist(road::BikeLane::Shared, Some(ONCOMING_POSITION));
+    let (_time, oncoming) = overtaking(&mut sd).expect("The car stayed behind the cyclist");
+
+    // Passed by the oncoming car, or gone
+    let car_position = sd.cars[1].position().position();
+    assert!(oncoming.is_none_or(|oncoming| oncoming < car_position), "Overtook at {} m with an oncoming car at {:?} m", car_position, oncoming);
+}
+
+#[test]
+fn cars_and_cyclists_ignore_each_other_on_bike_lanes() {
+    let (mut shared, mut dedicated) = (road_with_cyclist(road::BikeLane::Shared, Some(ONCOMING_POSITION)), road_with_cyclist(road::BikeLane::Dedicated, Some(ONCOMING_POSITION)));
+    let (shared_time, _oncoming) = overtaking(&mut shared).expect("The car stayed behind the cyclist");
+    let (dedicated_time, oncoming) = overtaking(&mut dedicated).expect("The car stayed behind the cyclist");
+
+    assert!(oncoming.is_some(), "The oncoming car left before the car passed the cyclist");
+    assert!(dedicated_time < shared_time, "Passed the cyclist after {} s on the bike lane, {} s without it", dedicated_time, shared_time);
+}
@@ -0,0 +1,24 @@
+use traffic_simulator::{agent::{car::TripPlan, spawner::Spawner, vehicle::{FleetMix, VehicleClass}}, road, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+const CARS: usize = 5;
+
+#[test]
+fn detector_counts_every_car_of_a_run_once() {
+    let mut sd = SimulationData::new(road::Roads::new());
+    let route = TripPlan::Route(vec![road::RoadNodeIdx(0), road::RoadNodeIdx(1), road::RoadNodeIdx(2), road::RoadNodeIdx(3)]);
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), 720., FleetMix::new().with(VehicleClass::Car.default_type(), 1.), route, 0., Some(CARS)));
+    let detector = sd.detectors.add(road::RoadPoint::new(road::RoadSegmentIdx(2), 15.)).expect("Could not add the detector");
+    while sd.time < 150. {
+        sd.step(STEP_SIZE);
+    }
+    sd.detectors.finish(sd.time);
+
+    let records = sd.detectors.records(detector);
+    assert_eq!(records.iter().map(|record| record.count).sum::<usize>(), CARS);
+    assert_eq!(sd.trips.trips().len(), CARS);
+    for record in records.iter().filter(|record| record.count > 0) {
+        assert!(record.time_mean_speed.is_some_and(|speed| speed > 0.), "{:?}", record);
+        assert!(record.occupancy > 0. && record.occupancy < 1., "{:?}", record);
+    }
+}
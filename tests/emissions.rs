@@ -0,0 +1,25 @@
+use traffic_simulator::{agent::{car::TripPlan, spawner::Spawner, vehicle::{FleetMix, VehicleClass}}, emissions::EmissionClass, road, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+const BREAKDOWN_DURATION: f32 = 20.; // s
+
+#[test]
+fn segment_emissions_add_up_to_the_trips_and_include_idling() {
+    let mut sd = SimulationData::new(road::Roads::new());
+    let route = TripPlan::Route(vec![road::RoadNodeIdx(0), road::RoadNodeIdx(1), road::RoadNodeIdx(2), road::RoadNodeIdx(3)]);
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), 720., FleetMix::new().with(VehicleClass::Car.default_type(), 1.), route, 0., Some(1)));
+    sd.step(STEP_SIZE);
+    assert!(sd.add_breakdown(sd.cars[0].id(), road::RoadPoint::new(road::RoadSegmentIdx(2), 15.), BREAKDOWN_DURATION));
+    while sd.time < 120. {
+        sd.step(STEP_SIZE);
+    }
+
+    assert!(sd.cars.is_empty());
+    let trip = sd.trips.trips()[0].1;
+    let total_fuel: f32 = sd.segment_emissions.iter().map(|emissions| emissions.fuel).sum();
+    assert!((total_fuel - trip.emissions.fuel).abs() < 1e-3 * trip.emissions.fuel, "{} mL on the segments, {} mL for the trip", total_fuel, trip.emissions.fuel);
+    // The car idles on segment 2 while broken down
+    let idling = sd.emission_model.parameters(EmissionClass::PetrolCar).idle_fuel_rate * BREAKDOWN_DURATION;
+    assert!(sd.segment_emissions[2].fuel > idling, "{} mL on the segment of the breakdown", sd.segment_emissions[2].fuel);
+    assert!(sd.segment_emissions.iter().enumerate().all(|(segment, emissions)| [0, 2, 4].contains(&segment) || emissions.fuel == 0.));
+}
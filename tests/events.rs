@@ -0,0 +1,54 @@
+use traffic_simulator::{agent::{car::TripPlan, spawner::Spawner, vehicle::{FleetMix, VehicleClass}}, road::{self, events::NetworkEventKind}, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+
+fn cars(flow: f32, trip_plan: TripPlan, count: Option<usize>) -> Spawner {
+    Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), flow, FleetMix::new().with(VehicleClass::Car.default_type(), 1.), trip_plan, 0., count)
+}
+
+fn run(sd: &mut SimulationData, duration: f32, mut each_step: impl FnMut(&SimulationData)) {
+    while sd.time < duration {
+        sd.step(STEP_SIZE);
+        each_step(sd);
+    }
+}
+
+#[test]
+fn scheduled_closure_reroutes_cars_around_it() {
+    let mut sd = SimulationData::new(road::Roads::new());
+    // Across the mesh
+    sd.spawners.push(cars(360., TripPlan::BackAndForth(road::RoadPoint::new(road::RoadSegmentIdx(0), 1.), road::RoadPoint::new(road::RoadSegmentIdx(23), 1.)), Some(1)));
+    run(&mut sd, 1., |_sd| {});
+    let ahead = sd.cars[0].remaining_segments(&sd.roads);
+    let (closed, last) = (ahead[1], *ahead.last().unwrap());
+    sd.events.schedule(sd.time, NetworkEventKind::CloseSegment(closed));
+    sd.step(STEP_SIZE);
+
+    assert!(sd.roads.is_closed(closed));
+    let ahead = sd.cars[0].remaining_segments(&sd.roads);
+    assert!(!ahead.contains(&closed), "The path still goes through the closed RoadSegment {}", closed);
+    assert_eq!(ahead.last(), Some(&last));
+    let mut reached_destination = false;
+    run(&mut sd, 60., |sd| {
+        let segment = sd.cars[0].position().road_segment();
+        assert_ne!(segment, closed);
+        reached_destination |= segment == last;
+    });
+    assert!(reached_destination);
+}
+
+#[test]
+fn work_zone_lowers_throughput() {
+    let throughput = |capacity_factor: f32| {
+        let mut sd = SimulationData::new(road::Roads::new());
+        // Along the bottom row of the mesh, at a flow above what the work zone lets through
+        sd.spawners.push(cars(1800., TripPlan::Route(vec![road::RoadNodeIdx(0), road::RoadNodeIdx(1), road::RoadNodeIdx(2), road::RoadNodeIdx(3)]), None));
+        sd.events.schedule(0., NetworkEventKind::SetCapacityFactor(road::RoadSegmentIdx(2), capacity_factor));
+        run(&mut sd, 300., |_sd| {});
+        sd.trips.trips().len()
+    };
+    let (open, work_zone) = (throughput(1.), throughput(0.3));
+
+    assert!(open > 0);
+    assert!((work_zone as f32) < 0.9 * open as f32, "{} cars through the work zone, {} without it", work_zone, open);
+}
@@ -0,0 +1,50 @@
+use traffic_simulator::{agent::{car::TripPlan, spawner::Spawner, vehicle::FleetMix}, output::frames::{FrameRecorder, FrameRenderer}, road, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+const INTERVAL: f32 = 1.; // s
+const DURATION: f32 = 5.; // s
+const FRAME_DELAY: f32 = 0.1; // s
+const WIDTH: u32 = 200; // px
+const HEIGHT: u32 = 150; // px
+
+#[test]
+fn frames_are_written_as_pngs_and_a_complete_gif() {
+    let directory = std::env::temp_dir().join(format!("traffic_simulator_frames_{}", std::process::id()));
+    let gif_path = directory.join("frames.gif");
+    let mut sd = SimulationData::new(road::Roads::new());
+    let trip_plan = TripPlan::Route(vec![road::RoadNodeIdx(0), road::RoadNodeIdx(1), road::RoadNodeIdx(2), road::RoadNodeIdx(3)]);
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), 1800., FleetMix::default(), trip_plan, 0., None));
+    let renderer = FrameRenderer::new(WIDTH, HEIGHT, &sd.roads).expect("Could not load the vehicle sprites");
+    let mut recorder = FrameRecorder::create(&directory, renderer, INTERVAL).expect("Could not create the frame directory");
+    recorder.write_gif_to(&gif_path, FRAME_DELAY).expect("Could not create the GIF");
+    sd.frame_recorder = Some(recorder);
+    while sd.time < DURATION {
+        sd.step(STEP_SIZE);
+    }
+    let recorder = sd.frame_recorder.take().unwrap();
+    // After the first step, then every interval
+    let frame_count = recorder.frame_count();
+    assert_eq!(frame_count, (DURATION / INTERVAL) as usize + 1);
+    recorder.finish().expect("Could not complete the GIF");
+
+    let pngs: Vec<image::RgbaImage> = (0..frame_count)
+        .map(|i| image::open(directory.join(format!("frame_{:05}.png", i))).expect("Could not open a frame").to_rgba8())
+        .collect();
+    let gif_bytes = std::fs::read(&gif_path).expect("Could not read the GIF");
+    std::fs::remove_dir_all(&directory).expect("Could not remove the frames");
+
+    for png in &pngs {
+        assert_eq!(png.dimensions(), (WIDTH, HEIGHT));
+        assert!(png.pixels().any(|pixel| pixel.0 != [0, 0, 0, 255]), "Nothing drawn on a frame");
+    }
+    assert!(pngs.windows(2).all(|pair| pair[0] != pair[1]), "The cars didn't move between frames");
+    assert_eq!(gif_bytes.last(), Some(&0x3B), "The GIF has no trailer");
+    let mut decoder = gif::DecodeOptions::new().read_info(gif_bytes.as_slice()).expect("Could not decode the GIF");
+    assert_eq!((decoder.width(), decoder.height()), (WIDTH as u16, HEIGHT as u16));
+    let mut gif_frames = 0;
+    while let Some(frame) = decoder.read_next_frame().expect("Could not decode a GIF frame") {
+        assert_eq!(frame.delay, (FRAME_DELAY * 100.) as u16);
+        gif_frames += 1;
+    }
+    assert_eq!(gif_frames, frame_count);
+}
@@ -0,0 +1,19 @@
+use traffic_simulator::{agent::{car::TripPlan, spawner::Spawner, vehicle::{FleetMix, VehicleClass}}, road, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+const DURATION: f32 = 60.; // s
+
+#[test]
+fn cars_pass_a_blockage_at_the_start_of_a_two_way_segment() {
+    let mut sd = SimulationData::new(road::Roads::new());
+    // From RoadNode 0 to RoadNode 2 along the bottom row of the mesh, whose second segment is blocked from its start
+    let trip_plan = TripPlan::Route(vec![road::RoadNodeIdx(0), road::RoadNodeIdx(1), road::RoadNodeIdx(2)]);
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), 360., FleetMix::new().with(VehicleClass::Car.default_type(), 1.), trip_plan, 0., Some(1)));
+    sd.add_blockage(road::RoadPoint::new(road::RoadSegmentIdx(2), 0.), 10., 2. * DURATION);
+    while sd.time < DURATION {
+        sd.step(STEP_SIZE);
+    }
+
+    assert!(sd.cars.is_empty(), "The car is still held at {}", sd.cars[0].position());
+    assert_eq!(sd.trips.trips().len(), 1);
+}
@@ -0,0 +1,42 @@
+use traffic_simulator::road::{Crossing, CrossingKind, RoadNodeIdx, RoadPoint, RoadSegmentIdx, Roads, Sign, SignType};
+
+fn assert_same_network(read: &Roads, written: &Roads) {
+    assert_eq!(read.node_count(), written.node_count());
+    assert_eq!(read.segment_count(), written.segment_count());
+    for segment in (0..written.segment_count()).map(RoadSegmentIdx) {
+        assert_eq!(read.segment_nodes(segment), written.segment_nodes(segment));
+        assert!((read.segment_length(segment) - written.segment_length(segment)).abs() < 1e-3, "Segment {} has another length", segment);
+        assert_eq!(read.signs(segment).len(), written.signs(segment).len());
+        for (read_sign, written_sign) in read.signs(segment).iter().zip(written.signs(segment)) {
+            assert_eq!(read_sign.sign_type, written_sign.sign_type);
+            assert_eq!(read_sign.position, written_sign.position);
+            // Written in km/h with one decimal
+            assert!((read_sign.value - written_sign.value).abs() < 0.05 / 3.6);
+        }
+    }
+}
+
+#[test]
+fn map_round_trips_through_the_file_format() {
+    let mut roads = Roads::new();
+    let segment = roads.segment_between(RoadNodeIdx(0), RoadNodeIdx(1)).expect("Could not find the segment");
+    roads.add_sign(Sign { sign_type: SignType::SpeedLimit, value: 30. / 3.6, position: RoadPoint::new(segment, 5.) });
+    roads.add_sign(Sign { sign_type: SignType::EndSpeedLimit, value: 0., position: RoadPoint::new(segment, 25.) });
+    roads.add_crossing(Crossing { node: RoadNodeIdx(5), kind: CrossingKind::Signalised { cycle: 60., walk_time: 15., offset: 5. }, length: 7. });
+    roads.insert_visual_keypoint(segment, 0.5, (15., 5.));
+
+    let mut map = Vec::new();
+    roads.write_map(&mut map).expect("Could not write the map");
+    let read = Roads::read_map(map.as_slice()).expect("Could not read the map");
+
+    assert_same_network(&read, &roads);
+    assert_eq!(read.visual_keypoints(segment), roads.visual_keypoints(segment));
+    assert_eq!(read.crossings(), roads.crossings());
+}
+
+#[test]
+fn mesh_map_matches_the_default_network() {
+    let read = Roads::load("resources/maps/mesh.map").expect("Could not load the mesh map");
+
+    assert_same_network(&read, &Roads::new());
+}
@@ -0,0 +1,47 @@
+use traffic_simulator::{agent::{car::TripPlan, spawner::Spawner, vehicle::{FleetMix, VehicleClass}}, parking::{ParkingFacility, ParkingLocation}, road, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+const DURATION: f32 = 300.; // s
+const CARS: usize = 3;
+const PARKING_DURATION: f32 = 60.; // s
+
+#[test]
+fn cars_cruise_until_a_space_is_released_then_drive_back() {
+    let mut sd = SimulationData::new(road::Roads::new());
+    // A space on a segment leading to RoadNode 10 and one in a garage nearby, for three cars
+    sd.parking.add_facility(ParkingFacility::new(ParkingLocation::OnStreet { segment: road::RoadSegmentIdx(14), start: 5., end: 25. }, 1));
+    sd.parking.add_facility(ParkingFacility::new(ParkingLocation::Garage { node: road::RoadNodeIdx(14) }, 1));
+    let trip_plan = TripPlan::Park { destination: road::RoadNodeIdx(10), duration: PARKING_DURATION };
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), 720., FleetMix::new().with(VehicleClass::Car.default_type(), 1.), trip_plan, 0., Some(CARS)));
+    while sd.time < DURATION {
+        sd.step(STEP_SIZE);
+        assert!(sd.parking.facilities().iter().all(|facility| facility.occupied() <= facility.capacity));
+    }
+
+    let records = sd.parking.records();
+    assert_eq!(records.len(), CARS);
+    for record in records {
+        assert!(record.search_start <= record.parked_time, "{:?}", record);
+        assert!((record.release_time - record.parked_time - PARKING_DURATION).abs() < 1e-3, "{:?}", record);
+    }
+    // The last car cruised until one of the others left
+    let last = records.iter().max_by(|a, b| a.parked_time.total_cmp(&b.parked_time)).unwrap();
+    assert!(records.iter().any(|record| record.release_time <= last.parked_time), "{:?}", records);
+    assert!(last.cruising_distance > records.iter().map(|record| record.cruising_distance).fold(0., f32::min));
+    // A trip to the parking and one back for each car, the latter starting when it left
+    let trips = sd.trips.trips();
+    assert_eq!(trips.len(), 2 * CARS);
+    for record in records {
+        let car_trips: Vec<_> = trips.iter().filter(|(car, _trip)| *car == record.car).map(|(_car, trip)| trip).collect();
+        assert_eq!(car_trips.len(), 2);
+        assert!((car_trips[0].arrival_time - record.parked_time).abs() < STEP_SIZE, "{:?} {:?}", car_trips, record);
+        assert!((car_trips[1].departure_time - record.release_time).abs() < 2. * STEP_SIZE, "{:?} {:?}", car_trips, record);
+    }
+    assert!(sd.cars.is_empty());
+    assert_eq!(sd.parking.parked_cars().count(), 0);
+    assert!(sd.parking.facilities().iter().all(|facility| facility.occupied() == 0));
+    let summary = sd.parking.summary(sd.network_summary().vehicle_kilometres * 1000.);
+    assert_eq!(summary.parked_cars, CARS);
+    assert!(summary.cruising_share > 0. && summary.cruising_share < 1., "{:?}", summary);
+    assert!((summary.cruising_kilometres - records.iter().map(|record| record.cruising_distance).sum::<f32>() / 1000.).abs() < 1e-4);
+}
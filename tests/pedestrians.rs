@@ -0,0 +1,63 @@
+use traffic_simulator::{agent::{car::TripPlan, pedestrian::{self, Pedestrian}, spawner::Spawner, vehicle::{FleetMix, VehicleClass}}, road, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+const DURATION: f32 = 120.; // s
+const STOPPED_SPEED: f32 = 0.1; // m/s
+const CROSSING: road::RoadNodeIdx = road::RoadNodeIdx(1);
+
+// Cars along the bottom row of the mesh, through the crossing
+fn traffic(crossing: road::CrossingKind) -> SimulationData {
+    let mut sd = SimulationData::new(road::Roads::new());
+    let route = TripPlan::Route(vec![road::RoadNodeIdx(0), CROSSING, road::RoadNodeIdx(2), road::RoadNodeIdx(3)]);
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), 1800., FleetMix::new().with(VehicleClass::Car.default_type(), 1.), route, 0., None));
+    sd.roads.add_crossing(road::Crossing { node: CROSSING, kind: crossing, length: 7. });
+
+    sd
+}
+
+// Runs the simulation, and returns the times at which cars drove through the crossing along with whether it was blocked
+// to them before the step
+fn run(sd: &mut SimulationData, mut is_blocked: impl FnMut(&SimulationData) -> bool) -> Vec<(f32, bool)> {
+    let mut passages = Vec::new();
+    while sd.time < DURATION {
+        let blocked = is_blocked(sd);
+        let segments: Vec<(usize, road::RoadSegmentIdx)> = sd.cars.iter().map(|car| (car.id(), car.position().road_segment())).collect();
+        sd.step(STEP_SIZE);
+        for car in &sd.cars {
+            let segment = car.position().road_segment();
+            let entered = segments.iter().any(|(id, previous)| *id == car.id() && *previous != segment);
+            if entered && sd.roads.segment_nodes(segment).0 == CROSSING {
+                passages.push((sd.time, blocked));
+            }
+        }
+    }
+
+    passages
+}
+
+#[test]
+fn cars_yield_to_pedestrians_on_zebra_crossings() {
+    let mut sd = traffic(road::CrossingKind::Zebra);
+    // Walks to the crossing, and back and forth across it
+    sd.pedestrians.push(Pedestrian::new(road::RoadNodeIdx(5), CROSSING, pedestrian::WALKING_SPEED));
+    let mut cars_waited = false;
+    let passages = run(&mut sd, |sd| {
+        let crossing = sd.pedestrians[0].is_crossing();
+        cars_waited |= crossing && sd.cars.iter().any(|car| car.position().road_segment() == road::RoadSegmentIdx(0) && car.speed() < STOPPED_SPEED);
+        crossing
+    });
+
+    assert!(cars_waited);
+    assert!(passages.iter().all(|(_time, blocked)| !blocked), "A car drove through the crossing while the pedestrian was on it: {:?}", passages);
+    assert!(passages.len() > 20, "Only {} cars drove through the crossing", passages.len());
+}
+
+#[test]
+fn cars_stop_during_the_walk_phase_of_signalised_crossings() {
+    let crossing = road::CrossingKind::Signalised { cycle: 30., walk_time: 10., offset: 0. };
+    let mut sd = traffic(crossing);
+    let passages = run(&mut sd, |sd| sd.roads.crossing_at(CROSSING).is_some_and(|crossing| crossing.is_walk_phase(sd.time)));
+
+    assert!(passages.iter().all(|(_time, blocked)| !blocked), "A car drove through the crossing during the walk phase: {:?}", passages);
+    assert!(passages.len() > 20, "Only {} cars drove through the crossing", passages.len());
+}
@@ -0,0 +1,84 @@
+use traffic_simulator::{agent::{car::TripPlan, pedestrian::Pedestrian, spawner::Spawner, vehicle::FleetMix}, output::replay::{Replay, ReplayWriter}, road, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+const INTERVAL: f32 = 1.; // s
+const DURATION: f32 = 30.; // s
+
+fn temp_path(name: &str) -> std::path::PathBuf { std::env::temp_dir().join(format!("traffic_simulator_{}_{}.bin", name, std::process::id())) }
+
+// Cars, a pedestrian and a closure, recorded to the path
+fn record(path: &std::path::Path) -> SimulationData {
+    let mut sd = SimulationData::new(road::Roads::new());
+    let trip_plan = TripPlan::Route(vec![road::RoadNodeIdx(0), road::RoadNodeIdx(1), road::RoadNodeIdx(2), road::RoadNodeIdx(3)]);
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), 720., FleetMix::default(), trip_plan, 0., None));
+    sd.pedestrians.push(Pedestrian::new(road::RoadNodeIdx(0), road::RoadNodeIdx(15), 1.3));
+    sd.roads.set_closed(road::RoadSegmentIdx(30), true);
+    sd.roads.set_capacity_factor(road::RoadSegmentIdx(31), 0.5);
+    sd.replay_writer = Some(ReplayWriter::create(path, &sd.roads, INTERVAL).expect("Could not create the replay"));
+    while sd.time < DURATION {
+        sd.step(STEP_SIZE);
+    }
+    sd.replay_writer.as_mut().unwrap().flush().expect("Could not write the replay");
+
+    sd
+}
+
+#[test]
+fn replay_round_trips_the_recorded_state() {
+    let path = temp_path("replay");
+    let sd = record(&path);
+    let replay = Replay::load(&path).expect("Could not load the replay");
+    std::fs::remove_file(&path).expect("Could not remove the replay");
+
+    assert_eq!(replay.roads.segment_count(), sd.roads.segment_count());
+    // After the first step, then every interval
+    assert_eq!(replay.frames.len(), (DURATION / INTERVAL) as usize + 1);
+    let last = replay.frames.last().unwrap();
+    assert!((last.time - sd.time).abs() < INTERVAL);
+    assert!(!last.vehicles.is_empty());
+    assert!(last.vehicles.iter().all(|vehicle| sd.cars.iter().any(|car| car.id() == vehicle.car)));
+    assert_eq!(last.pedestrians.len(), 1);
+    let states: Vec<_> = last.segments.iter().map(|state| (*state.segment, state.closed, state.capacity_factor)).collect();
+    assert_eq!(states, vec![(30, true, 1.), (31, false, 0.5)]);
+}
+
+#[test]
+fn replay_drops_a_partial_last_frame_and_rejects_a_corrupt_header() {
+    let path = temp_path("replay_truncated");
+    record(&path);
+    let bytes = std::fs::read(&path).expect("Could not read the replay");
+    let frame_count = Replay::load(&path).expect("Could not load the replay").frames.len();
+
+    std::fs::write(&path, &bytes[..bytes.len() - 3]).expect("Could not write the replay");
+    let truncated = Replay::load(&path).expect("Could not load the truncated replay");
+    assert_eq!(truncated.frames.len(), frame_count - 1);
+
+    // A map length beyond the end of the file
+    let mut corrupt = bytes.clone();
+    corrupt[5..9].copy_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(&path, &corrupt).expect("Could not write the replay");
+    let error = Replay::load(&path).err().expect("Loaded a corrupt replay");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    // Huge counts in the last frame header, the frame being dropped without allocating them
+    let last_frame_start = bytes.len() - frame_size(&bytes);
+    let mut corrupt = bytes.clone();
+    corrupt[last_frame_start + 4..last_frame_start + 16].copy_from_slice(&[0xff; 12]);
+    std::fs::write(&path, &corrupt).expect("Could not write the replay");
+    assert_eq!(Replay::load(&path).expect("Could not load the replay").frames.len(), frame_count - 1);
+    std::fs::remove_file(&path).expect("Could not remove the replay");
+}
+
+// Size of the last frame, from the layout of ReplayWriter::record
+fn frame_size(bytes: &[u8]) -> usize {
+    let map_end = 9 + u32::from_le_bytes(bytes[5..9].try_into().unwrap()) as usize;
+    let mut start = map_end;
+    let mut last = 0;
+    while start < bytes.len() {
+        let count = |i: usize| u32::from_le_bytes(bytes[start + i..start + i + 4].try_into().unwrap()) as usize;
+        last = 16 + 29 * count(4) + 8 * count(8) + 9 * count(12);
+        start += last;
+    }
+
+    last
+}
@@ -0,0 +1,16 @@
+use traffic_simulator::scenarios::RingBenchmark;
+
+const DURATION: f32 = 300.; // s
+const MIN_SPEED_STANDARD_DEVIATION: f32 = 1.; // m/s
+// Observed stop-and-go waves move upstream at around 15 to 20 km/h
+const WAVE_SPEED_RANGE: (f32, f32) = (-30. / 3.6, -5. / 3.6); // m/s
+
+#[test]
+fn stop_and_go_waves_travel_upstream_on_the_ring() {
+    let benchmark = RingBenchmark { duration: DURATION, warm_up: DURATION / 2., ..Default::default() };
+    let result = benchmark.run();
+
+    assert!(result.speed_standard_deviation > MIN_SPEED_STANDARD_DEVIATION, "No stop-and-go waves: speed standard deviation of {} m/s", result.speed_standard_deviation);
+    let wave_speed = result.wave_speed.expect("Could not measure the speed of the waves");
+    assert!((WAVE_SPEED_RANGE.0..=WAVE_SPEED_RANGE.1).contains(&wave_speed), "Implausible wave speed of {} m/s", wave_speed);
+}
@@ -0,0 +1,136 @@
+use traffic_simulator::{agent::{car::{Car, TripPlan}, pedestrian::Pedestrian, spawner::Spawner, vehicle::{FleetMix, VehicleClass}}, parking::{ParkingFacility, ParkingLocation, ParkingRecord}, road, scenarios::RingBenchmark, simulation::SimulationData, snapshot::SnapshotWriter, transit::{BusLine, BusStop, Departures, StopKind}};
+
+const STEP_SIZE: f32 = 0.05; // s
+const WARM_UP: f32 = 120.; // s
+const CONTINUATION: f32 = 120.; // s
+
+// Cars, cyclists, buses, pedestrians at crossings and drivers searching for parking, so that most of the state is used
+fn create_simulation() -> SimulationData {
+    let mut sd = SimulationData::new(road::Roads::new());
+    sd.transit.add_line(BusLine {
+        name: "1".to_string(),
+        segments: vec![road::RoadSegmentIdx(0), road::RoadSegmentIdx(2), road::RoadSegmentIdx(4)],
+        stops: vec![BusStop { name: "A".to_string(), position: road::RoadPoint::new(road::RoadSegmentIdx(2), 15.), kind: StopKind::InLane, passenger_arrival_rate: 0.05, scheduled_offset: 20. }],
+        departures: Departures::Headway { first: 10., headway: 60., count: 5 },
+    }, &sd.roads);
+    sd.roads.add_crossing(road::Crossing { node: road::RoadNodeIdx(1), kind: road::CrossingKind::Zebra, length: 7. });
+    sd.roads.add_crossing(road::Crossing { node: road::RoadNodeIdx(5), kind: road::CrossingKind::Signalised { cycle: 60., walk_time: 15., offset: 0. }, length: 7. });
+    for i in 0..4 {
+        sd.pedestrians.push(Pedestrian::new(road::RoadNodeIdx(i), road::RoadNodeIdx(15 - i), 1.3));
+    }
+    let trip_plan = TripPlan::Park { destination: road::RoadNodeIdx(14), duration: 600. };
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), 600., FleetMix::default().with(VehicleClass::Bicycle.default_type(), 0.2), trip_plan, 0.5, Some(20)));
+    sd.parking.add_facility(ParkingFacility::new(ParkingLocation::OnStreet { segment: road::RoadSegmentIdx(14), start: 5., end: 25. }, 1));
+    let trip_plan = TripPlan::Park { destination: road::RoadNodeIdx(10), duration: 60. };
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(24), 0.), 300., FleetMix::new().with(VehicleClass::Car.default_type(), 1.), trip_plan, 0., Some(5)));
+
+    sd
+}
+
+fn run(sd: &mut SimulationData, duration: f32) {
+    let end = sd.time + duration;
+    while sd.time < end {
+        sd.step(STEP_SIZE);
+    }
+}
+
+#[test]
+fn stepping_after_restore_matches_stepping_without_interruption() {
+    let mut uninterrupted = create_simulation();
+    run(&mut uninterrupted, WARM_UP);
+    let snapshot = uninterrupted.snapshot();
+    run(&mut uninterrupted, CONTINUATION);
+
+    let mut restored = SimulationData::new(road::Roads::empty());
+    restored.restore(&snapshot).expect("Could not restore the snapshot");
+    run(&mut restored, CONTINUATION);
+
+    assert!(!uninterrupted.cars.is_empty());
+    assert_eq!(restored.time, uninterrupted.time);
+    assert_eq!(restored.rng, uninterrupted.rng);
+    let states = |sd: &SimulationData| sd.cars.iter().map(|car| (car.id(), *car.position(), car.speed(), car.acceleration())).collect::<Vec<_>>();
+    assert_eq!(states(&restored), states(&uninterrupted));
+    assert_eq!(restored.trips.trips(), uninterrupted.trips.trips());
+    assert_eq!(restored.transit.records(), uninterrupted.transit.records());
+    assert_eq!(restored.parking.records(), uninterrupted.parking.records());
+    assert!(restored.snapshot() == uninterrupted.snapshot(), "The whole states differ");
+}
+
+#[test]
+fn snapshot_round_trips_through_a_file() {
+    let mut sd = create_simulation();
+    run(&mut sd, WARM_UP);
+    let path = std::env::temp_dir().join(format!("traffic_simulator_snapshot_{}.bin", std::process::id()));
+    sd.save_snapshot(&path).expect("Could not save the snapshot");
+    let mut restored = SimulationData::new(road::Roads::empty());
+    restored.load_snapshot(&path).expect("Could not load the snapshot");
+    std::fs::remove_file(&path).expect("Could not remove the snapshot");
+
+    assert!(restored.snapshot() == sd.snapshot());
+}
+
+#[test]
+fn restore_rejects_invalid_snapshots() {
+    let mut sd = create_simulation();
+    run(&mut sd, WARM_UP);
+    let snapshot = sd.snapshot();
+    let car_count = sd.cars.len();
+
+    let mut restored = create_simulation();
+    assert!(restored.restore(b"not a snapshot").is_err());
+    assert!(restored.restore(&snapshot[..snapshot.len() / 2]).is_err());
+    // Left as it was
+    assert_eq!(restored.time, 0.);
+    assert!(restored.cars.is_empty());
+    restored.restore(&snapshot).expect("Could not restore the snapshot");
+    assert_eq!(restored.cars.len(), car_count);
+
+    // Cars on RoadSegments which the network doesn't have
+    sd.roads = road::Roads::empty();
+    sd.segment_emissions.clear();
+    let error = restored.restore(&sd.snapshot()).expect_err("Restored cars out of the network");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(restored.cars.len(), car_count);
+}
+
+// Restoring must fail without touching the current state
+fn assert_rejected(snapshot: &[u8]) {
+    let mut restored = create_simulation();
+    run(&mut restored, STEP_SIZE);
+    let (time, cars) = (restored.time, restored.cars.len());
+    let error = restored.restore(snapshot).expect_err("Restored an invalid snapshot");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(restored.time, time);
+    assert_eq!(restored.cars.len(), cars);
+    assert_eq!(restored.roads.segment_count(), road::Roads::new().segment_count());
+}
+
+#[test]
+fn restore_rejects_a_snapshot_whose_state_is_from_another_map() {
+    let mut sd = create_simulation();
+    run(&mut sd, WARM_UP);
+    // The network of the ring, with its own travel times and emissions, under the agents of the mesh
+    let ring = RingBenchmark::default().create_simulation();
+    (sd.roads, sd.travel_times, sd.segment_emissions) = (ring.roads, ring.travel_times, ring.segment_emissions);
+
+    assert_rejected(&sd.snapshot());
+}
+
+#[test]
+fn restore_rejects_a_parking_record_of_a_missing_facility() {
+    let mut sd = create_simulation();
+    run(&mut sd, WARM_UP);
+    let snapshot = sd.snapshot();
+    let mut parking = SnapshotWriter::new();
+    parking.write(&sd.parking);
+    let parking = parking.into_bytes();
+    let start = snapshot.windows(parking.len()).position(|bytes| bytes == parking).expect("Could not find the parking in the snapshot");
+    let mut invalid_parking = SnapshotWriter::new();
+    invalid_parking.write(&sd.parking.facilities().to_vec());
+    let record = ParkingRecord { car: 0, facility: sd.parking.facilities().len(), search_start: 0., parked_time: 0., cruising_distance: 0., release_time: f32::INFINITY };
+    invalid_parking.write(&vec![record]);
+    invalid_parking.write(&Vec::<(usize, Car)>::new());
+    let invalid = [&snapshot[..start], &invalid_parking.into_bytes(), &snapshot[start + parking.len()..]].concat();
+
+    assert_rejected(&invalid);
+}
@@ -0,0 +1,65 @@
+use traffic_simulator::{agent::{car::TripPlan, spawner::Spawner, vehicle::FleetMix}, output::trajectories::{self, TrajectoryFormat, TrajectoryPoint, TrajectoryWriter}, road, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+const INTERVAL: f32 = 0.5; // s
+const DURATION: f32 = 20.; // s
+
+fn temp_path(name: &str) -> std::path::PathBuf { std::env::temp_dir().join(format!("traffic_simulator_{}_{}", name, std::process::id())) }
+
+// Fields written with 3 decimals
+fn assert_close(csv_line: &str, point: &TrajectoryPoint) {
+    let fields: Vec<f32> = csv_line.split(',').map(|field| field.parse().expect("Could not parse a CSV field")).collect();
+    let expected = [point.car as f32, point.time, *point.position.road_segment() as f32, point.position.position(), point.x, point.y, point.speed, point.acceleration, point.target_speed];
+    assert_eq!(fields.len(), expected.len());
+    for (field, value) in fields.iter().zip(expected) {
+        assert!((field - value).abs() <= 1e-3, "{} in the CSV, {} recorded", field, value);
+    }
+}
+
+#[test]
+fn csv_and_binary_trajectories_hold_the_same_points() {
+    let (csv_path, binary_path) = (temp_path("trajectories.csv"), temp_path("trajectories.bin"));
+    let mut sd = SimulationData::new(road::Roads::new());
+    let trip_plan = TripPlan::Route(vec![road::RoadNodeIdx(0), road::RoadNodeIdx(1), road::RoadNodeIdx(2), road::RoadNodeIdx(3)]);
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), 1800., FleetMix::default(), trip_plan, 0., None));
+    sd.trajectory_writers.push(TrajectoryWriter::create(&csv_path, TrajectoryFormat::Csv, INTERVAL).expect("Could not create the CSV trajectories"));
+    sd.trajectory_writers.push(TrajectoryWriter::create(&binary_path, TrajectoryFormat::Binary, INTERVAL).expect("Could not create the binary trajectories"));
+    let mut recorded = Vec::new();
+    let mut next_record_time = 0.;
+    while sd.time < DURATION {
+        sd.step(STEP_SIZE);
+        if sd.time >= next_record_time {
+            recorded.extend(sd.trajectory_points());
+            while next_record_time <= sd.time {
+                next_record_time += INTERVAL;
+            }
+        }
+    }
+    for writer in &mut sd.trajectory_writers {
+        writer.flush().expect("Could not write the trajectories");
+    }
+    let points = trajectories::read_binary(&binary_path).expect("Could not read the binary trajectories");
+    let csv = std::fs::read_to_string(&csv_path).expect("Could not read the CSV trajectories");
+    std::fs::remove_file(&csv_path).expect("Could not remove the CSV trajectories");
+    std::fs::remove_file(&binary_path).expect("Could not remove the binary trajectories");
+
+    assert!(recorded.iter().map(|point| point.car).max() > Some(1), "Too few cars recorded");
+    assert_eq!(points, recorded);
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("car,time_s,segment,offset_m,x_m,y_m,speed_m_s,acceleration_m_s2,target_speed_m_s"));
+    let lines: Vec<&str> = lines.collect();
+    assert_eq!(lines.len(), recorded.len());
+    for (line, point) in lines.iter().zip(&recorded) {
+        assert_close(line, point);
+    }
+}
+
+#[test]
+fn read_binary_rejects_other_files() {
+    let path = temp_path("not_trajectories.bin");
+    std::fs::write(&path, b"TSRP\x02").expect("Could not write the file");
+    let error = trajectories::read_binary(&path).expect_err("Read a file of another format");
+    std::fs::remove_file(&path).expect("Could not remove the file");
+
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use traffic_simulator::{road, simulation::SimulationData, transit::{BusLine, BusStop, Departures, StopKind}};
+
+const STEP_SIZE: f32 = 0.05; // s
+const DURATION: f32 = 200.; // s
+const HEADWAY: f32 = 60.; // s
+const PASSENGER_ARRIVAL_RATE: f32 = 0.1; // passengers/s
+// Opening and closing the doors, then per passenger
+const DEAD_TIME: f32 = 4.; // s
+const BOARDING_TIME: f32 = 2.5; // s
+const STOPPED_SPEED: f32 = 0.1; // m/s
+
+// Three buses along the bottom row of the mesh, serving an in-lane stop halfway. Returns the simulation and how long
+// each bus stood still, by car id.
+fn run(scheduled_offset: f32) -> (SimulationData, HashMap<usize, f32>) {
+    let mut sd = SimulationData::new(road::Roads::new());
+    let stop = BusStop { name: "Halfway".to_string(), position: road::RoadPoint::new(road::RoadSegmentIdx(2), 15.), kind: StopKind::InLane, passenger_arrival_rate: PASSENGER_ARRIVAL_RATE, scheduled_offset };
+    let line = BusLine {
+        name: "1".to_string(),
+        segments: vec![road::RoadSegmentIdx(0), road::RoadSegmentIdx(2), road::RoadSegmentIdx(4)],
+        stops: vec![stop],
+        departures: Departures::Headway { first: 10., headway: HEADWAY, count: 3 },
+    };
+    sd.transit.add_line(line, &sd.roads);
+    let mut stopped_times: HashMap<usize, f32> = HashMap::new();
+    while sd.time < DURATION {
+        sd.step(STEP_SIZE);
+        for bus in sd.cars.iter().filter(|car| car.speed() < STOPPED_SPEED) {
+            *stopped_times.entry(bus.id()).or_default() += STEP_SIZE;
+        }
+    }
+
+    (sd, stopped_times)
+}
+
+#[test]
+fn buses_dwell_for_their_boardings() {
+    let (sd, stopped_times) = run(5.);
+    let records = sd.transit.records();
+
+    assert_eq!(records.len(), 3);
+    assert!(sd.cars.is_empty());
+    let (mut waiting, mut previous_arrival) = (0., 0.);
+    for (i, record) in records.iter().enumerate() {
+        assert_eq!(record.run.run, i);
+        // The passengers arrived since the previous bus, plus the fraction of a passenger left behind by it
+        waiting += PASSENGER_ARRIVAL_RATE * (record.arrival - previous_arrival);
+        previous_arrival = record.arrival;
+        assert_eq!(record.boardings, waiting.floor() as usize, "{:?}", record);
+        waiting -= record.boardings as f32;
+        assert_eq!(record.dwell_time, DEAD_TIME + BOARDING_TIME * record.boardings as f32);
+    }
+    assert!(records.iter().any(|record| record.boardings > 1));
+    // Each bus stood still at the stop for its dwell time, and nowhere else
+    let mut stopped_times: Vec<f32> = stopped_times.into_values().collect();
+    stopped_times.sort_by(f32::total_cmp);
+    let mut dwell_times: Vec<f32> = records.iter().map(|record| record.dwell_time).collect();
+    dwell_times.sort_by(f32::total_cmp);
+    for (stopped_time, dwell_time) in stopped_times.iter().zip(dwell_times) {
+        assert!(*stopped_time >= dwell_time && *stopped_time < dwell_time + 1., "Stood still {} s for a dwell time of {} s", stopped_time, dwell_time);
+    }
+}
+
+#[test]
+fn schedule_adherence_compares_arrivals_with_the_timetable() {
+    let (on_schedule, _stopped_times) = run(5.);
+    let summary = on_schedule.transit.summary(0);
+    assert_eq!(summary.stop_arrivals, 3);
+    assert_eq!(summary.on_time_share, 1.);
+    assert!(summary.mean_schedule_deviation < 1., "{:?}", summary);
+    assert!((summary.mean_headway - HEADWAY).abs() < 1., "{:?}", summary);
+    assert_eq!(summary.bunched_arrivals, 0);
+
+    // Scheduled 3 minutes later than the buses can arrive, beyond the early tolerance
+    let (early, _stopped_times) = run(185.);
+    let summary = early.transit.summary(0);
+    assert_eq!(summary.on_time_share, 0.);
+    assert!((summary.mean_schedule_deviation - 180.).abs() < 1., "{:?}", summary);
+}
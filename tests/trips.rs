@@ -0,0 +1,37 @@
+use traffic_simulator::{agent::{car::TripPlan, spawner::Spawner, vehicle::{FleetMix, VehicleClass}}, output::trips::TripRecord, road, simulation::SimulationData};
+
+const STEP_SIZE: f32 = 0.05; // s
+const BREAKDOWN_DURATION: f32 = 20.; // s
+
+// Trip of a lone car along the bottom row of the mesh, breaking down halfway if asked
+fn trip(breakdown: bool) -> TripRecord {
+    let mut sd = SimulationData::new(road::Roads::new());
+    let route = TripPlan::Route(vec![road::RoadNodeIdx(0), road::RoadNodeIdx(1), road::RoadNodeIdx(2), road::RoadNodeIdx(3)]);
+    sd.spawners.push(Spawner::new(road::RoadPoint::new(road::RoadSegmentIdx(0), 0.), 720., FleetMix::new().with(VehicleClass::Car.default_type(), 1.), route, 0., Some(1)));
+    sd.step(STEP_SIZE);
+    if breakdown {
+        assert!(sd.add_breakdown(sd.cars[0].id(), road::RoadPoint::new(road::RoadSegmentIdx(2), 15.), BREAKDOWN_DURATION));
+    }
+    while sd.time < 120. {
+        sd.step(STEP_SIZE);
+    }
+    assert_eq!(sd.trips.trips().len(), 1);
+
+    sd.trips.trips()[0].1
+}
+
+#[test]
+fn breakdown_adds_a_stop_and_its_duration_to_the_delay() {
+    let (free, broken_down) = (trip(false), trip(true));
+
+    // 90 m at 80 km/h
+    for record in [free, broken_down] {
+        assert!((record.free_flow_time - 90. / (80. / 3.6)).abs() < 0.1, "{:?}", record);
+        assert!((record.delay() - (record.travel_time() - record.free_flow_time)).abs() < 1e-3, "{:?}", record);
+    }
+    assert_eq!(free.stops, 0);
+    assert!(free.delay() < 2., "{:?}", free);
+    assert_eq!(broken_down.stops, 1);
+    assert!(broken_down.delay() > free.delay() + BREAKDOWN_DURATION, "{:?}", broken_down);
+    assert!(broken_down.emissions.fuel > free.emissions.fuel);
+}